@@ -0,0 +1,905 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::env;
+use std::fs;
+use serde::Deserialize;
+use std::time::Duration;
+use crate::{parse_duration, check_collection_templates, collection_name, MAX_DOCUMENT_BYTES, YEAR_PLACEHOLDER};
+use crate::varmap::VarMap;
+use crate::sink;
+use crate::log::Level;
+
+pub const USAGE: &str = "\
+usage: bsose-sync <file> <variable> <lolat> <hilat> <lolong> <hilong> [options]
+       bsose-sync <file> <variable> --lat-deg-min <deg> ... [options]
+       bsose-sync --config <config.toml> [options]
+
+<file> may also be a directory of .nc files or a quoted glob pattern; matching files are ingested in
+sorted order with the same variable and tile. <variable> may be a comma separated list, e.g. TRAC02,THETA,
+to ingest several variables of each file. Builds with the gzip feature also read .nc.gz files, and
+builds with the remote feature read http(s):// and public s3:// URLs; both go through a temporary file.
+
+options:
+  --config <path>           TOML file of options; keys are flag names without the leading --,
+                            plus file, variable, lolat, hilat, lolong and hilong for the positional arguments
+  --lat-deg-min <deg>       instead of index bounds, the tile runs from the grid point nearest each given
+  --lat-deg-max <deg>       degree bound; a missing bound covers the rest of that axis. Longitude bounds use
+  --lon-deg-min <deg>       the short way round the globe but can't wrap past the end of the file's grid
+  --lon-deg-max <deg>
+  --level-min <k>           first level to ingest, as an index into the depth variable (default 0, the top)
+  --level-max <k>           end of the levels to ingest, exclusive like <hilat>, so --level-max 10 is the top 10
+                            levels (default all); a surface variable's single document counts as level 0
+  --id-precision <n>        decimal places in document ids (default 3)
+  --source <label>          source label recorded in metadoc provenance (default BSOSE)
+  --global-attributes <a,b> NetCDF global attributes recorded in metadoc provenance
+                            (default title,history,source,institution,references,comment,date_created)
+  --data-type <label>       data_type of the metadocs inserted, for products other than BSOSE (default
+                            BSOSE-profile, or BSOSE-surface for a surface variable); a cell's metadoc keeps the
+                            label of the ingest that created it
+  --iter <iteration>        BSOSE iteration recorded in metadoc provenance (default 156)
+  --db <name>               database, or $BSOSE_DB (default argo)
+  --data-collection <name>  data collection, or $BSOSE_DATA_COLLECTION (default bsose)
+  --meta-collection <name>  metadata collection, or $BSOSE_META_COLLECTION (default timeseriesMeta)
+                            Both names may contain {iter}, replaced by --iter, or {year}, which splits each file's
+                            timesteps by calendar year into per-year collections, e.g. bsose_{year} and
+                            timeseriesMeta_{year}; the two must use the same placeholders. A cell's metadoc id is
+                            the same in every year, so its whole timeseries is the concatenation, in year order, of
+                            its documents across the collections, e.g. found together with $unionWith
+  --write-concern <w>       majority or a number of nodes, e.g. 1, 2 or 0 (default majority)
+  --wtimeout <ms>           how long the server waits for the write concern before reporting a timeout. A timeout
+                            isn't retried: it fails the cell with --continue-on-error and the run otherwise, though
+                            the write may already be on some nodes; rerunning merges over it
+  --no-journal              don't request journaled writes
+  --transactions            write each cell's metadoc and data documents in one transaction, so an interrupted
+                            cell leaves nothing behind; needs a replica set or sharded cluster, and is turned
+                            off with a warning on a standalone server
+  --tls-ca-file <path>      CA file for TLS connections
+  --auth-source <db>        authentication database
+  --slow-cell <duration>    warn about a cell whose data documents take longer than this to write, e.g. 2m
+                            (default 30s); the slowest cells are listed in the summary either way
+  --ping-timeout <duration> how long the startup ping may wait for MongoDB before the run fails, e.g. 30s
+                            (default 10s); nothing is read from the NetCDF files until it answers
+  --connect-timeout <duration>
+                            how long opening a connection to MongoDB may take before it fails, e.g. 5s;
+                            replaces any connectTimeoutMS in MONGODB_URI (default the driver's, 10s)
+  --server-selection-timeout <duration>
+                            how long an operation waits for a usable MongoDB server before it fails, e.g. 15s;
+                            replaces any serverSelectionTimeoutMS in MONGODB_URI (default the driver's, 30s)
+  --var-map <path>          JSON object renaming the NetCDF variables read, e.g. {\"lat\": \"YG\", \"lon\": \"XG\"};
+                            keys are time, lat, lon, depth, cell_area, ocean_depth, depth_r0_to_bottom,
+                            interior_2d_mask, depth_r0_to_ref_surface, cell_vertical_fraction,
+                            sea_binary_mask_at_t_location, ctrl_vector_3d_mask, cell_z_size,
+                            interface_z_size and reference_density_profile; unlisted variables keep their
+                            MITgcm names
+  --depth-var <name>        vertical coordinate the data variable's levels run along (default Z), e.g. Zl for
+                            w-point variables; on cell faces, cell_z_size comes from drC and
+                            reference_density_profile is the mean of rhoRef at the centers either side
+  --basin-file <path>       NetCDF basin mask with BASIN_TAG (default /tmp/basinmask_01.nc)
+  --basin-mode <mode>       nearest (default) snaps to the nearest mask center, ties going south-west;
+                            cell uses the mask cell containing the point, edges going north-east
+  --basin-tags <list>       basin codes the mask may hold, as codes and low-high ranges (default 1-58);
+                            0 marks points in no basin, and any other code stops the run
+  --since <date>            only ingest timesteps at or after a date (YYYY-MM-DD or RFC 3339), appending
+                            any new ones to the stored timeseries
+  --time-min <date>         earliest plausible timestamp (default 2012-01-01)
+  --time-max <date>         latest plausible timestamp (default now)
+  --on-bad-time <mode>      when a file has timestamps outside --time-min to --time-max, usually a wrong time
+                            origin or units: warn ingests them anyway, skip leaves those timesteps out, error
+                            (default) stops the run
+  --geolocation <type>      point (default) stores each data document's geolocation as a GeoJSON Point at the
+                            cell center; polygon as a Polygon over the cell, bounded half way to the neighbouring
+                            XC and YC values
+  --lon-convention <range>  pm180 (default) stores longitudes, formats them into ids and gives them in geolocation
+                            in [-180, 180); zero360 in [0, 360) as BSOSE's XC, which a 2dsphere index rejects for
+                            geolocation east of 180, so only for collections without one. Not with --geolocation
+                            polygon. A tile already ingested keeps its ids only under the convention it used
+  --out-of-range <mode>     for data values outside the variable's valid_range, or valid_min and valid_max:
+                            mask stores them as NaN, clamp as the nearest bound, keep (default) as they are
+  --qc-var <var>=<flags>[,...]
+                            store the NetCDF variable flags, which must have var's dimensions, as a <var>_qc
+                            column aligned with var's values, e.g. THETA=THETA_QC
+  --limit <n>               only process the first n cells of the tile
+  --shuffle-cells <seed>    process the tile's cells in an order shuffled by seed instead of lat-major, so
+                            parallel workers on adjacent tiles rarely reach shared boundary metadocs at the
+                            same time; costs locality of reads, and --limit takes the first n shuffled cells
+  --max-runtime <duration>  stop starting new cells once this long has passed since ingesting began, e.g. 30m,
+                            2h or 1h30m; cells in flight are finished and a rerun picks up the rest
+  --run-id <id>             record each finished cell under this id in the ingest_progress collection, keyed
+                            by file and variable, so workers on different tiles report to one place
+  --resume                  with --run-id, skip the cells already recorded for that run, file and variable
+  --output-json <dir>       write the documents to <meta-collection>.ndjson and <data-collection>.ndjson in dir
+                            instead of MongoDB; MONGODB_URI isn't needed
+  --compress <format>       with --output-json, write .ndjson.gz (gzip) or .ndjson.zst (zstd) files instead;
+                            each needs the build feature of the same name
+  --summary-json <path>     also write the end of run summary as JSON; with {year} collections, each year's to
+                            <path>.<year>
+  --metrics-addr <addr>     serve the summary counters and cells/second in Prometheus text format on addr,
+                            e.g. 127.0.0.1:9464, until the run ends; needs a build with the metrics feature
+  --workers-per-variable    ingest the listed variables of each file concurrently rather than one after another;
+                            writes to a document two variables share wait for each other, so neither is lost
+  --level-concurrency <n>   database writes for up to n levels of a cell in flight at once (default 1); the gain
+                            grows with the server's round trip time, so measure it against yours with bsose-bench
+  --threads <n>             runtime worker threads, or $BSOSE_THREADS (default one per core); level writes
+                            from --level-concurrency share these workers, so more threads than that buys nothing
+  --meta-cache-size <n>     metadocs kept in memory for the whole run, saving their lookups (default 10000)
+  --max-document-size <n>   stop before writing a data document over n bytes (default 16777216, MongoDB's limit)
+  --split-documents         store a new data document over --max-document-size as time-range chunks <id>_c0,
+                            <id>_c1, ... listed in its metadoc's chunked field; later ingests into a chunked
+                            document stop rather than merge into it
+  --on-inconsistent <mode>  when a stored document's data doesn't match its metadoc's timeseries, as an
+                            interrupted run can leave: repair pads or truncates it, skip leaves it alone,
+                            error (default) stops the run
+  --dry-cells <mode>        how levels without ocean are found, so no new data documents are written for them:
+                            zeros (default) when the data is all zero, mask from maskC, or vertical-fraction
+                            when hFacC is zero, which also catches levels below the bathymetry holding fill values
+  --reference-variable <v>  with --dry-cells zeros, a level is dry when this variable is all zero there instead of
+                            the data variable, e.g. a mask for data where zero is a real value; one without a
+                            level dimension applies to every level
+  --drop-empty              don't write data that is entirely NaN
+  --min-valid-fraction <f>  don't write data when fewer than this fraction of its timesteps have values,
+                            e.g. 0.1; unlike --drop-empty this also skips sparse but not empty profiles
+  --overwrite               replace previously stored data for this variable instead of only filling NaNs
+  --replace-source          make this run's source, iter and file a stored metadoc's only provenance, instead of
+                            adding them to its source list, e.g. when a new iteration supersedes the old one
+  --append-only             never change stored values: only fill NaNs and add timesteps or variables; with
+                            --overwrite, stops at the first document whose stored values would change; can't be
+                            combined with --on-inconsistent repair
+  --strict-units            stop at a stored document whose units or long_name for this variable differ from the
+                            file's, instead of warning once per file
+  --require-attrs           stop at a data variable without text units and long_name attributes, instead of
+                            warning and storing empty strings
+  --delete-var <variable>   remove a variable from the tile's data documents instead of ingesting
+  --delete-before <date>    remove timesteps before a date (YYYY-MM-DD or RFC 3339) from the tile instead of ingesting
+  --repair-duplicates       collapse timestamps repeated in the tile's stored metadocs, dropping the matching
+                            positions from their data documents, instead of ingesting
+  --rebuild-meta            regenerate the tile's metadata documents instead of ingesting: <file> must list every
+                            file ingested into the tile, in time order, since data documents don't store their
+                            times; the timeseries is the union of the files' times, and a cell is only rewritten
+                            if all its data documents have that many timesteps
+  --compact                 remove timesteps where every variable of every data document in a cell is NaN from
+                            the tile's metadocs and data documents, instead of ingesting; run it with --dry-run
+                            first, then again with --confirm set to the number of timesteps the dry run reported
+  --confirm <n>             the number of timesteps --compact is expected to remove; a tile that changed since the
+                            dry run is left alone
+  --fix-mask-key            rename sea_binary_mask_at_t_locaiton to sea_binary_mask_at_t_location in the tile's
+                            stored data documents instead of ingesting; an ingest writes the corrected key too,
+                            but only to the documents it merges into
+  --validate-only           check the tile's stored documents for consistency instead of ingesting
+  --dry-run                 with --delete-var, --delete-before, --repair-duplicates, --compact, --fix-mask-key
+                            or --rebuild-meta, only report what would change
+  --diff                    ingest without writing anything, printing each stored document the run would insert
+                            or change: timesteps and variables added, values filled and values overwritten
+  --diff-json <path>        with --diff, also write the changes as JSON, to <path>.<year> for {year} collections
+  --continue-on-error       keep going when a cell, or one of several files, fails, logging each failure; the
+                            run still exits with an error if anything failed
+  --force-new               insert every document without first looking for a stored one, for a first load
+                            into empty collections; unsafe against populated ones, where the run stops with an
+                            error at the first document already stored. Documents the run itself wrote earlier
+                            are still merged into
+  --verbose                 log how each updated document changed; same as --log-level debug, which overrides it
+  --log-level <level>       error, warn, info or debug (default info): error prints only errors, warn adds
+                            warnings, info adds progress, debug adds the --verbose logging
+  --quiet                   print only errors and the summaries a run ends with; same as --log-level error
+  --quiet-summary           with --quiet, leave out the summaries too
+  --print-schema            print the JSON Schema of each collection's documents and exit, without reading a
+                            file or connecting to MongoDB
+  --self-test               run built-in checks of the longitude, timeline and basin lookup functions and exit,
+                            failing if any check does; needs no file or MongoDB
+  -h, --help                print this message
+
+environment:
+  MONGODB_URI               connection string (required, never read from --config)
+
+precedence:
+  command line, then --config, then environment variables, then built-in defaults
+
+exit codes:
+  0  success
+  1  runtime error
+  2  invalid arguments or failed validation
+  3  NetCDF file could not be opened or read
+  4  MongoDB connection could not be configured
+  5  interrupted by SIGINT or SIGTERM; writes already started were completed
+  6  --max-runtime deadline reached; writes already started were completed";
+
+// flags that take a value, given as either `--flag value` or `--flag=value`
+const VALUED_FLAGS: &[&str] = &[
+    "id-precision",
+    "level-min",
+    "level-max",
+    "source",
+    "iter",
+    "data-type",
+    "db",
+    "data-collection",
+    "meta-collection",
+    "write-concern",
+    "wtimeout",
+    "tls-ca-file",
+    "auth-source",
+    "ping-timeout",
+    "connect-timeout",
+    "server-selection-timeout",
+    "slow-cell",
+    "run-id",
+    "basin-file",
+    "limit",
+    "confirm",
+    "min-valid-fraction",
+    "shuffle-cells",
+    "max-runtime",
+    "summary-json",
+    "diff-json",
+    "metrics-addr",
+    "delete-var",
+    "delete-before",
+    "level-concurrency",
+    "meta-cache-size",
+    "max-document-size",
+    "config",
+    "basin-mode",
+    "basin-tags",
+    "since",
+    "time-min",
+    "time-max",
+    "on-bad-time",
+    "out-of-range",
+    "geolocation",
+    "lon-convention",
+    "dry-cells",
+    "reference-variable",
+    "depth-var",
+    "threads",
+    "on-inconsistent",
+    "var-map",
+    "qc-var",
+    "output-json",
+    "compress",
+    "lat-deg-min",
+    "lat-deg-max",
+    "lon-deg-min",
+    "lon-deg-max",
+    "global-attributes",
+    "log-level",
+];
+
+// flags that take no value
+const SWITCHES: &[&str] = &[
+    "no-journal",
+    "drop-empty",
+    "overwrite",
+    "dry-run",
+    "verbose",
+    "validate-only",
+    "continue-on-error",
+    "append-only",
+    "repair-duplicates",
+    "compact",
+    "fix-mask-key",
+    "workers-per-variable",
+    "rebuild-meta",
+    "resume",
+    "strict-units",
+    "require-attrs",
+    "transactions",
+    "split-documents",
+    "force-new",
+    "replace-source",
+    "diff",
+    "quiet",
+    "quiet-summary",
+];
+
+#[derive(Clone)]
+pub struct Args {
+    pub filename: String,
+    pub dv: String,
+    // <variable> split on commas; each is ingested with dv set to it
+    pub variables: Vec<String>,
+    pub lolat: usize,
+    pub hilat: usize,
+    pub lolong: usize,
+    pub hilong: usize,
+    // degree bounds as (min, max); when set the index bounds are resolved from the file's grid
+    pub lat_deg: (Option<f64>, Option<f64>),
+    pub lon_deg: (Option<f64>, Option<f64>),
+    // decimal places used when formatting lon/lat/level into _id and metaid
+    pub id_precision: usize,
+    // level index bounds, checked against the depth variable once the file is open
+    pub level_min: Option<usize>,
+    pub level_max: Option<usize>,
+    // provenance recorded in each metadoc's source array
+    pub source: String,
+    pub iter: String,
+    // replaces the data_type label chosen from the variable's dimensions
+    pub data_type: Option<String>,
+    pub global_attributes: Vec<String>,
+    // mongo destinations
+    pub db: String,
+    pub data_collection: String,
+    pub meta_collection: String,
+    // the year of a {year} collection partition, set per partition rather than by a flag
+    pub partition_year: Option<i32>,
+    // majority or a number of nodes
+    pub write_concern: String,
+    pub wtimeout: Option<Duration>,
+    pub journal: bool,
+    // one transaction per cell; cleared at startup if the server can't run them
+    pub transactions: bool,
+    // merged into the client options parsed from MONGODB_URI
+    pub tls_ca_file: Option<String>,
+    pub auth_source: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    pub server_selection_timeout: Option<Duration>,
+    // how long the startup ping may take
+    pub ping_timeout: Duration,
+    // a cell taking longer than this is reported as it finishes
+    pub slow_cell: Duration,
+    // NetCDF variable names, from --var-map and --depth-var
+    pub var_map: VarMap,
+    pub basin_file: String,
+    // nearest or cell, see basin::BasinMode
+    pub basin_mode: String,
+    // codes and ranges, see basin::BasinTags
+    pub basin_tags: String,
+    // zeros, mask or vertical-fraction, see DryCells
+    pub dry_cells: String,
+    // decides dryness in place of the data variable under --dry-cells zeros
+    pub reference_variable: Option<String>,
+    // skip writing all-NaN profiles instead of storing them
+    pub drop_empty: bool,
+    // smallest share of non-NaN timesteps a profile needs to be written
+    pub min_valid_fraction: Option<f64>,
+    // first timestep to ingest, for incremental updates
+    pub since: Option<String>,
+    // plausible timestamp range and what to do outside it, see OnBadTime
+    pub time_min: String,
+    pub time_max: Option<String>,
+    pub on_bad_time: String,
+    // point or polygon
+    pub geolocation: String,
+    // pm180 or zero360, see LonConvention
+    pub lon_convention: String,
+    // mask, clamp or keep data values outside the variable's valid_range, see OutOfRange
+    pub out_of_range: String,
+    // quality flag variable for a data variable, stored as its <variable>_qc column
+    pub qc_vars: HashMap<String, String>,
+    // cap on the number of lat/lon cells processed
+    pub limit: Option<usize>,
+    // seed for a shuffled cell order
+    pub shuffle_cells: Option<u64>,
+    // time after which no new cells are started
+    pub max_runtime: Option<Duration>,
+    pub summary_json: Option<String>,
+    // --diff, and where --diff-json writes the changes
+    pub diff: bool,
+    pub diff_json: Option<String>,
+    // where to serve live Prometheus metrics
+    pub metrics_addr: Option<String>,
+    // export directory replacing the database
+    pub output_json: Option<String>,
+    pub compress: sink::Compression,
+    // replace rather than gap-fill an already ingested variable
+    pub overwrite: bool,
+    // refuse anything that would change or remove stored values
+    pub append_only: bool,
+    // reset metadoc provenance to this run's Sourcedoc rather than adding to it
+    pub replace_source: bool,
+    // deletion modes; <file> then only supplies the tile's coordinates
+    pub delete_var: Option<String>,
+    pub delete_before: Option<String>,
+    pub repair_duplicates: bool,
+    // --compact, and the timestep count its dry run reported
+    pub compact: bool,
+    pub confirm: Option<usize>,
+    pub fix_mask_key: bool,
+    pub rebuild_meta: bool,
+    pub dry_run: bool,
+    pub level_concurrency: usize,
+    // the largest data document written, and whether a new one over it is split rather than an error
+    pub max_document_size: usize,
+    pub split_documents: bool,
+    pub workers_per_variable: bool,
+    // a stored variable's units or long_name differing from the file's is an error rather than a warning
+    pub strict_units: bool,
+    // a data variable without units or long_name is an error rather than stored with empty strings
+    pub require_attrs: bool,
+    // completed cells are recorded under run_id, and skipped on a rerun with resume
+    pub run_id: Option<String>,
+    pub resume: bool,
+    // tokio worker threads; None keeps the runtime's default of one per core
+    pub threads: Option<usize>,
+    // bound on the in-memory metadoc timeseries cache
+    pub meta_cache_size: usize,
+    // skip looking up stored documents, assuming there are none
+    pub force_new: bool,
+    // what gets printed, see crate::log
+    pub log_level: Level,
+    pub quiet_summary: bool,
+    // repair, skip or error, see OnInconsistent
+    pub on_inconsistent: String,
+    // read-only audit mode
+    pub validate_only: bool,
+    // log a failing cell or file and move on to the next
+    pub continue_on_error: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    // contents of a --config file; options use the same names as the command line flags,
+    // and unknown names are rejected by merge_into
+    file: Option<String>,
+    variable: Option<String>,
+    lolat: Option<usize>,
+    hilat: Option<usize>,
+    lolong: Option<usize>,
+    hilong: Option<usize>,
+    #[serde(flatten)]
+    options: HashMap<String, toml::Value>
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read --config {}: {}", path, e))?;
+        Ok(toml::from_str::<Config>(&text).map_err(|e| format!("invalid --config {}: {}", path, e))?)
+    }
+
+    fn positional(&self) -> Option<Vec<String>> {
+        // file and variable, then the index bounds if they're all set
+        let mut positional = vec!(self.file.clone()?, self.variable.clone()?);
+        if let (Some(lolat), Some(hilat), Some(lolong), Some(hilong)) = (self.lolat, self.hilat, self.lolong, self.hilong) {
+            positional.extend([lolat, hilat, lolong, hilong].iter().map(|i| i.to_string()));
+        }
+        Some(positional)
+    }
+
+    fn merge_into(&self, flags: &mut HashMap<String, String>, switches: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+        // only fills in what the command line didn't set
+        for (name, value) in &self.options {
+            if SWITCHES.contains(&name.as_str()) {
+                match value {
+                    toml::Value::Boolean(true) => if !switches.contains(name) { switches.push(name.clone()) },
+                    toml::Value::Boolean(false) => {},
+                    _ => return Err(format!("config option {} must be true or false", name).into())
+                }
+            } else if VALUED_FLAGS.contains(&name.as_str()) && name != "config" {
+                let value = match value {
+                    toml::Value::String(v) => v.clone(),
+                    toml::Value::Integer(v) => v.to_string(),
+                    toml::Value::Float(v) => v.to_string(),
+                    _ => return Err(format!("config option {} must be a string or number", name).into())
+                };
+                flags.entry(name.clone()).or_insert(value);
+            } else {
+                return Err(format!("unknown config option {}", name).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn flag_or_env(flags: &HashMap<String, String>, flag: &str, var: &str, default: &str) -> String {
+    // command line wins, then the environment, then the default
+    if let Some(v) = flags.get(flag) {
+        return v.clone();
+    }
+    match env::var(var) {
+        Ok(v) => v,
+        Err(_) => String::from(default)
+    }
+}
+
+impl Args {
+    pub fn parse(argv: &[String]) -> Result<Args, Box<dyn Error>> {
+        // usage: bsose-sync <file> <variable> <lolat> <hilat> <lolong> <hilong> [--flag value ...]
+        let mut positional: Vec<String> = Vec::new();
+        let mut flags: HashMap<String, String> = HashMap::new();
+        let mut switches: Vec<String> = Vec::new();
+
+        let mut i = 1;
+        while i < argv.len() {
+            let arg = &argv[i];
+            if let Some(flag) = arg.strip_prefix("--") {
+                let (name, inline) = match flag.split_once('=') {
+                    Some((n, v)) => (n, Some(v.to_string())),
+                    None => (flag, None)
+                };
+                if SWITCHES.contains(&name) {
+                    if inline.is_some() {
+                        return Err(format!("flag --{} does not take a value", name).into());
+                    }
+                    switches.push(name.to_string());
+                } else if VALUED_FLAGS.contains(&name) {
+                    let value = match inline {
+                        Some(v) => v,
+                        None => {
+                            i += 1;
+                            match argv.get(i) {
+                                Some(v) => v.clone(),
+                                None => return Err(format!("flag --{} requires a value", name).into())
+                            }
+                        }
+                    };
+                    flags.insert(name.to_string(), value);
+                } else {
+                    return Err(format!("unknown flag --{}", name).into());
+                }
+            } else {
+                positional.push(arg.clone());
+            }
+            i += 1;
+        }
+
+        if let Some(path) = flags.get("config").cloned() {
+            let config = Config::load(&path)?;
+            config.merge_into(&mut flags, &mut switches)?;
+            if positional.is_empty() {
+                positional = config.positional().ok_or("--config without positional arguments must set file and variable, and lolat, hilat, lolong and hilong unless using degree bounds")?;
+            }
+        }
+
+        let degree = |name: &str| -> Result<Option<f64>, Box<dyn Error>> {
+            match flags.get(name) {
+                Some(d) => Ok(Some(d.parse::<f64>().map_err(|e| format!("invalid --{} {}: {}", name, d, e))?)),
+                None => Ok(None)
+            }
+        };
+        let lat_deg = (degree("lat-deg-min")?, degree("lat-deg-max")?);
+        let lon_deg = (degree("lon-deg-min")?, degree("lon-deg-max")?);
+        let by_degree = lat_deg != (None, None) || lon_deg != (None, None);
+        if by_degree && positional.len() == 6 {
+            return Err("give either index bounds <lolat> <hilat> <lolong> <hilong> or --lat-deg/--lon-deg bounds, not both".into());
+        }
+        if by_degree && positional.len() == 2 {
+            // placeholders until the bounds are resolved against the file's grid
+            positional.extend([0, 0, 0, 0].iter().map(|i: &usize| i.to_string()));
+        }
+        if positional.len() != 6 {
+            return Err(format!("expected 6 positional arguments <file> <variable> <lolat> <hilat> <lolong> <hilong>, got {}", positional.len()).into());
+        }
+
+        let id_precision = match flags.get("id-precision") {
+            Some(p) => p.parse::<usize>().map_err(|e| format!("invalid --id-precision {}: {}", p, e))?,
+            None => 3
+        };
+
+        let mut level_bounds = Vec::new();
+        for name in ["level-min", "level-max"] {
+            level_bounds.push(match flags.get(name) {
+                Some(k) => Some(k.parse::<usize>().map_err(|e| format!("invalid --{} {}: {}", name, k, e))?),
+                None => None
+            });
+        }
+        if let [Some(min), Some(max)] = level_bounds[..] {
+            if min >= max {
+                return Err(format!("--level-min {} must be below --level-max {}", min, max).into());
+            }
+        }
+
+        let write_concern = flags.get("write-concern").cloned().unwrap_or(String::from("majority"));
+        let wtimeout = match flags.get("wtimeout") {
+            Some(ms) => Some(Duration::from_millis(ms.parse::<u64>().map_err(|e| format!("invalid --wtimeout {}: {}", ms, e))?)),
+            None => None
+        };
+        sink::write_concern(&write_concern, true, wtimeout).map_err(|e| format!("--write-concern: {}", e))?;
+
+        let limit = match flags.get("limit") {
+            Some(n) => Some(n.parse::<usize>().map_err(|e| format!("invalid --limit {}: {}", n, e))?),
+            None => None
+        };
+        let min_valid_fraction = match flags.get("min-valid-fraction") {
+            Some(f) => Some(f.parse::<f64>().map_err(|e| format!("invalid --min-valid-fraction {}: {}", f, e))?),
+            None => None
+        };
+        if min_valid_fraction.is_some_and(|f| !(0.0..=1.0).contains(&f)) {
+            return Err("--min-valid-fraction must be between 0 and 1".into());
+        }
+        let shuffle_cells = match flags.get("shuffle-cells") {
+            Some(n) => Some(n.parse::<u64>().map_err(|e| format!("invalid --shuffle-cells {}: {}", n, e))?),
+            None => None
+        };
+
+        let ping_timeout = parse_duration(flags.get("ping-timeout").map(String::as_str).unwrap_or("10s")).map_err(|e| format!("--ping-timeout: {}", e))?;
+        if ping_timeout.is_zero() {
+            return Err("--ping-timeout must be longer than 0s".into());
+        }
+
+        let mut client_timeouts = Vec::new();
+        for name in ["connect-timeout", "server-selection-timeout"] {
+            let timeout = match flags.get(name) {
+                Some(d) => Some(parse_duration(d).map_err(|e| format!("--{}: {}", name, e))?),
+                None => None
+            };
+            if timeout.is_some_and(|t| t.is_zero()) {
+                return Err(format!("--{} must be longer than 0s", name).into());
+            }
+            client_timeouts.push(timeout);
+        }
+
+        let slow_cell = parse_duration(flags.get("slow-cell").map(String::as_str).unwrap_or("30s")).map_err(|e| format!("--slow-cell: {}", e))?;
+
+        let max_runtime = match flags.get("max-runtime") {
+            Some(d) => Some(parse_duration(d).map_err(|e| format!("--max-runtime: {}", e))?),
+            None => None
+        };
+
+        let level_concurrency = match flags.get("level-concurrency") {
+            Some(n) => n.parse::<usize>().map_err(|e| format!("invalid --level-concurrency {}: {}", n, e))?,
+            None => 1
+        };
+        if level_concurrency == 0 {
+            return Err("--level-concurrency must be at least 1".into());
+        }
+        let max_document_size = match flags.get("max-document-size") {
+            Some(n) => n.parse::<usize>().map_err(|e| format!("invalid --max-document-size {}: {}", n, e))?,
+            None => MAX_DOCUMENT_BYTES
+        };
+        if max_document_size == 0 || max_document_size > MAX_DOCUMENT_BYTES {
+            return Err(format!("--max-document-size must be between 1 and {}", MAX_DOCUMENT_BYTES).into());
+        }
+
+        let threads = match flags.get("threads").cloned().or(env::var("BSOSE_THREADS").ok()) {
+            Some(n) => Some(n.parse::<usize>().map_err(|e| format!("invalid --threads {}: {}", n, e))?),
+            None => None
+        };
+        if threads == Some(0) {
+            return Err("--threads must be at least 1".into());
+        }
+
+        let on_inconsistent = flags.get("on-inconsistent").cloned().unwrap_or(String::from("error"));
+        if !["repair", "skip", "error"].contains(&on_inconsistent.as_str()) {
+            return Err(format!("invalid --on-inconsistent {}, expected one of repair, skip, error", on_inconsistent).into());
+        }
+
+        let on_bad_time = flags.get("on-bad-time").cloned().unwrap_or(String::from("error"));
+        if !["warn", "skip", "error"].contains(&on_bad_time.as_str()) {
+            return Err(format!("invalid --on-bad-time {}, expected one of warn, skip, error", on_bad_time).into());
+        }
+
+        let geolocation = flags.get("geolocation").cloned().unwrap_or(String::from("point"));
+        if !["point", "polygon"].contains(&geolocation.as_str()) {
+            return Err(format!("invalid --geolocation {}, expected point or polygon", geolocation).into());
+        }
+
+        let lon_convention = flags.get("lon-convention").cloned().unwrap_or(String::from("pm180"));
+        if !["pm180", "zero360"].contains(&lon_convention.as_str()) {
+            return Err(format!("invalid --lon-convention {}, expected pm180 or zero360", lon_convention).into());
+        }
+        if lon_convention == "zero360" && geolocation == "polygon" {
+            return Err("--lon-convention zero360 can't be used with --geolocation polygon, whose cells across 0 would have a western edge east of the eastern one".into());
+        }
+
+        let out_of_range = flags.get("out-of-range").cloned().unwrap_or(String::from("keep"));
+        if !["mask", "clamp", "keep"].contains(&out_of_range.as_str()) {
+            return Err(format!("invalid --out-of-range {}, expected one of mask, clamp, keep", out_of_range).into());
+        }
+
+        let dry_cells = flags.get("dry-cells").cloned().unwrap_or(String::from("zeros"));
+        if !["zeros", "mask", "vertical-fraction"].contains(&dry_cells.as_str()) {
+            return Err(format!("invalid --dry-cells {}, expected one of zeros, mask, vertical-fraction", dry_cells).into());
+        }
+        if flags.contains_key("reference-variable") && dry_cells != "zeros" {
+            return Err(format!("--reference-variable replaces the data variable in --dry-cells zeros, so it can't be combined with --dry-cells {}", dry_cells).into());
+        }
+
+        let mut var_map = match flags.get("var-map") {
+            Some(path) => VarMap::load(path)?,
+            None => VarMap::default()
+        };
+        if let Some(depth) = flags.get("depth-var") {
+            var_map.set("depth", depth).map_err(|e| format!("--depth-var: {}", e))?;
+        }
+
+        let append_only = switches.contains(&String::from("append-only"));
+        if append_only && (flags.contains_key("delete-var") || flags.contains_key("delete-before") || switches.contains(&String::from("repair-duplicates")) || switches.contains(&String::from("compact")) || switches.contains(&String::from("rebuild-meta"))) {
+            return Err("--append-only can't be combined with --delete-var, --delete-before, --repair-duplicates, --compact or --rebuild-meta".into());
+        }
+        let replace_source = switches.contains(&String::from("replace-source"));
+        if replace_source && append_only {
+            return Err("--replace-source drops stored provenance, which --append-only doesn't allow".into());
+        }
+        if append_only && on_inconsistent == "repair" {
+            // repair truncates a stored column that's longer than the timeseries
+            return Err("--on-inconsistent repair can drop stored values, which --append-only doesn't allow".into());
+        }
+
+        let data_type = flags.get("data-type").cloned();
+        if data_type.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            return Err("--data-type can't be empty".into());
+        }
+
+        let compact = switches.contains(&String::from("compact"));
+        let confirm = match flags.get("confirm") {
+            Some(n) => Some(n.parse::<usize>().map_err(|e| format!("invalid --confirm {}: {}", n, e))?),
+            None => None
+        };
+        if confirm.is_some() && !compact {
+            return Err("--confirm only applies to --compact".into());
+        }
+        if compact && confirm.is_none() && !switches.contains(&String::from("dry-run")) {
+            return Err("--compact removes stored timesteps: run it with --dry-run first, then with --confirm <n> using the count it reports".into());
+        }
+
+        let iter = flags.get("iter").cloned().unwrap_or(String::from("156"));
+        let data_collection = flag_or_env(&flags, "data-collection", "BSOSE_DATA_COLLECTION", "bsose");
+        let meta_collection = flag_or_env(&flags, "meta-collection", "BSOSE_META_COLLECTION", "timeseriesMeta");
+        check_collection_templates(&data_collection, &meta_collection)?;
+        let by_year = data_collection.contains(YEAR_PLACEHOLDER);
+        if by_year && (compact || switches.iter().any(|s| ["validate-only", "fix-mask-key", "repair-duplicates", "rebuild-meta"].contains(&s.as_str()))
+            || flags.contains_key("delete-var") || flags.contains_key("delete-before")) {
+            return Err(format!("{} collections are only for ingesting; name the year's collections to run the other modes on them", YEAR_PLACEHOLDER).into());
+        }
+
+        let diff = switches.contains(&String::from("diff"));
+        if flags.contains_key("diff-json") && !diff {
+            return Err("--diff-json only applies to --diff".into());
+        }
+        if diff && flags.contains_key("output-json") {
+            return Err("--diff compares against the documents in MongoDB, so it can't be combined with --output-json".into());
+        }
+        if diff && (compact || switches.iter().any(|s| ["validate-only", "fix-mask-key", "repair-duplicates", "rebuild-meta"].contains(&s.as_str()))
+            || flags.contains_key("delete-var") || flags.contains_key("delete-before")) {
+            return Err("--diff only applies to ingesting; the other modes report their changes with --dry-run".into());
+        }
+
+        let run_id = flags.get("run-id").cloned();
+        if run_id.as_ref().is_some_and(|id| id.is_empty()) {
+            return Err("--run-id can't be empty".into());
+        }
+        if switches.contains(&String::from("resume")) && run_id.is_none() {
+            return Err("--resume needs the --run-id of the run to resume".into());
+        }
+        if run_id.is_some() && flags.contains_key("output-json") {
+            return Err("--run-id records progress in MongoDB, so it can't be combined with --output-json".into());
+        }
+        let compress = match flags.get("compress") {
+            Some(name) => sink::Compression::parse(name)?,
+            None => sink::Compression::None
+        };
+        if compress != sink::Compression::None && !flags.contains_key("output-json") {
+            return Err("--compress only applies to --output-json".into());
+        }
+        let transactions = switches.contains(&String::from("transactions"));
+        if transactions && switches.contains(&String::from("workers-per-variable")) {
+            // concurrent variables would interleave their cells in the one session
+            return Err("--transactions can't be combined with --workers-per-variable".into());
+        }
+        if transactions && flags.contains_key("output-json") {
+            return Err("--transactions only applies to MongoDB, so it can't be combined with --output-json".into());
+        }
+
+        let quiet = switches.contains(&String::from("quiet"));
+        if quiet && flags.contains_key("log-level") {
+            return Err("--quiet sets the log level, so it can't be combined with --log-level".into());
+        }
+        if quiet && switches.contains(&String::from("verbose")) {
+            return Err("--quiet can't be combined with --verbose".into());
+        }
+        let quiet_summary = switches.contains(&String::from("quiet-summary"));
+        if quiet_summary && !quiet {
+            return Err("--quiet-summary only applies to --quiet".into());
+        }
+        let log_level = match flags.get("log-level") {
+            Some(level) => Level::parse(level).map_err(|e| format!("--log-level: {}", e))?,
+            None if quiet => Level::Error,
+            None if switches.contains(&String::from("verbose")) => Level::Debug,
+            None => Level::Info
+        };
+
+        let variables: Vec<String> = positional[1].split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect();
+        if variables.is_empty() {
+            return Err("no data variable given".into());
+        }
+        if let Some(v) = variables.iter().enumerate().find(|(i, v)| variables[..*i].contains(v)).map(|(_, v)| v) {
+            return Err(format!("variable {} is listed twice", v).into());
+        }
+
+        let mut qc_vars = HashMap::new();
+        if let Some(list) = flags.get("qc-var") {
+            for pair in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+                let (variable, qc) = match pair.split_once('=') {
+                    Some((v, q)) if !v.trim().is_empty() && !q.trim().is_empty() => (v.trim().to_string(), q.trim().to_string()),
+                    _ => return Err(format!("invalid --qc-var {}, expected <variable>=<flag variable>", pair).into())
+                };
+                if !variables.contains(&variable) {
+                    return Err(format!("--qc-var names {}, which is not one of the variables ingested", variable).into());
+                }
+                if qc_vars.insert(variable.clone(), qc).is_some() {
+                    return Err(format!("--qc-var lists {} twice", variable).into());
+                }
+            }
+        }
+
+        let meta_cache_size = match flags.get("meta-cache-size") {
+            Some(n) => n.parse::<usize>().map_err(|e| format!("invalid --meta-cache-size {}: {}", n, e))?,
+            None => 10000
+        };
+
+        Ok(Args {
+            filename: positional[0].clone(),
+            dv: positional[1].clone(),
+            variables: variables,
+            lolat: positional[2].parse::<usize>()?,
+            hilat: positional[3].parse::<usize>()?,
+            lolong: positional[4].parse::<usize>()?,
+            hilong: positional[5].parse::<usize>()?,
+            lat_deg: lat_deg,
+            lon_deg: lon_deg,
+            id_precision: id_precision,
+            level_min: level_bounds[0],
+            level_max: level_bounds[1],
+            source: flags.get("source").cloned().unwrap_or(String::from("BSOSE")),
+            data_collection: collection_name(&data_collection, None, &iter),
+            meta_collection: collection_name(&meta_collection, None, &iter),
+            partition_year: None,
+            iter: iter,
+            data_type: data_type,
+            global_attributes: flags.get("global-attributes").cloned()
+                .unwrap_or(String::from("title,history,source,institution,references,comment,date_created"))
+                .split(',').map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect(),
+            db: flag_or_env(&flags, "db", "BSOSE_DB", "argo"),
+            write_concern: write_concern,
+            wtimeout: wtimeout,
+            journal: !switches.contains(&String::from("no-journal")),
+            transactions: transactions,
+            tls_ca_file: flags.get("tls-ca-file").cloned(),
+            auth_source: flags.get("auth-source").cloned(),
+            connect_timeout: client_timeouts[0],
+            server_selection_timeout: client_timeouts[1],
+            ping_timeout: ping_timeout,
+            slow_cell: slow_cell,
+            var_map: var_map,
+            basin_file: flags.get("basin-file").cloned().unwrap_or(String::from("/tmp/basinmask_01.nc")),
+            basin_mode: flags.get("basin-mode").cloned().unwrap_or(String::from("nearest")),
+            basin_tags: flags.get("basin-tags").cloned().unwrap_or(String::from("1-58")),
+            dry_cells: dry_cells,
+            reference_variable: flags.get("reference-variable").cloned(),
+            drop_empty: switches.contains(&String::from("drop-empty")),
+            min_valid_fraction: min_valid_fraction,
+            since: flags.get("since").cloned(),
+            time_min: flags.get("time-min").cloned().unwrap_or(String::from("2012-01-01")),
+            time_max: flags.get("time-max").cloned(),
+            on_bad_time: on_bad_time,
+            geolocation: geolocation,
+            lon_convention: lon_convention,
+            out_of_range: out_of_range,
+            qc_vars: qc_vars,
+            limit: limit,
+            shuffle_cells: shuffle_cells,
+            max_runtime: max_runtime,
+            summary_json: flags.get("summary-json").cloned(),
+            diff: diff,
+            diff_json: flags.get("diff-json").cloned(),
+            metrics_addr: flags.get("metrics-addr").cloned(),
+            output_json: flags.get("output-json").cloned(),
+            compress: compress,
+            overwrite: switches.contains(&String::from("overwrite")),
+            append_only: append_only,
+            replace_source: replace_source,
+            delete_var: flags.get("delete-var").cloned(),
+            delete_before: flags.get("delete-before").cloned(),
+            repair_duplicates: switches.contains(&String::from("repair-duplicates")),
+            compact: compact,
+            confirm: confirm,
+            fix_mask_key: switches.contains(&String::from("fix-mask-key")),
+            rebuild_meta: switches.contains(&String::from("rebuild-meta")),
+            run_id: run_id,
+            resume: switches.contains(&String::from("resume")),
+            strict_units: switches.contains(&String::from("strict-units")),
+            require_attrs: switches.contains(&String::from("require-attrs")),
+            dry_run: switches.contains(&String::from("dry-run")),
+            level_concurrency: level_concurrency,
+            max_document_size: max_document_size,
+            split_documents: switches.contains(&String::from("split-documents")),
+            workers_per_variable: switches.contains(&String::from("workers-per-variable")),
+            threads: threads,
+            meta_cache_size: meta_cache_size,
+            log_level: log_level,
+            force_new: switches.contains(&String::from("force-new")),
+            quiet_summary: quiet_summary,
+            on_inconsistent: on_inconsistent,
+            validate_only: switches.contains(&String::from("validate-only")),
+            continue_on_error: switches.contains(&String::from("continue-on-error")),
+        })
+    }
+}
@@ -0,0 +1,199 @@
+use std::error::Error;
+use crate::error::SyncError;
+use crate::{containing_index, nearest_index};
+
+// tag for a point the mask puts in no basin; never a real basin's code, so a mask can use any other value
+pub const UNASSIGNED_BASIN: i32 = 0;
+
+pub struct BasinGrid {
+    // basin tags row-major [lat, lon], at the cell centers lats and lons; both ascending, but not necessarily
+    // evenly spaced
+    pub tags: Vec<i32>,
+    pub lats: Vec<f64>,
+    pub lons: Vec<f64>
+}
+
+impl BasinGrid {
+    pub fn from_file(file: &netcdf::File) -> Result<BasinGrid, Box<dyn Error>> {
+        // BASIN_TAG's cell centers are read from its own coordinate variables
+        let basins = file.variable("BASIN_TAG").ok_or_else(|| SyncError::missing_variable("BASIN_TAG", String::from("Could not find variable 'BASIN_TAG'")))?;
+        let dims: Vec<String> = basins.dimensions().iter().map(|d| d.name()).collect();
+        if dims.len() != 2 {
+            return Err(format!("BASIN_TAG should have dimensions [lat, lon], found [{}]", dims.join(", ")).into());
+        }
+        let lats = coordinate(file, &dims[0])?;
+        let lons = coordinate(file, &dims[1])?;
+
+        // tags are stored as i32; a wider one is an error rather than a silently wrapped basin code
+        let mut tags = Vec::with_capacity(lats.len() * lons.len());
+        for latidx in 0..lats.len() {
+            for lonidx in 0..lons.len() {
+                let tag = basins.value::<i64, _>([latidx, lonidx])?;
+                tags.push(i32::try_from(tag).map_err(|_| format!("BASIN_TAG {} at [{}, {}] does not fit in an i32", tag, latidx, lonidx))?);
+            }
+        }
+        Ok(BasinGrid {
+            tags: tags,
+            lats: lats,
+            lons: lons
+        })
+    }
+
+    pub fn regular(tags: Vec<i32>, nlat: usize, nlon: usize, lat0: f64, lon0: f64) -> BasinGrid {
+        // a grid at 1 degree spacing from the center of its first cell
+        BasinGrid {
+            tags: tags,
+            lats: (0..nlat).map(|i| lat0 + i as f64).collect(),
+            lons: (0..nlon).map(|i| lon0 + i as f64).collect()
+        }
+    }
+
+    fn ring(&self) -> bool {
+        // whether the longitudes go all the way round, so lookups wrap across the ends rather than clamping;
+        // the gap between the last and first center is taken to be the one between the first two
+        match self.lons.as_slice() {
+            [first, second, .., last] => last - first + (second - first) >= 360.0 - 1e-6,
+            _ => false
+        }
+    }
+
+    fn tag(&self, latidx: usize, lonidx: usize) -> i32 {
+        self.tags[latidx * self.lons.len() + lonidx]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BasinMode {
+    // how a point is assigned a basin tag. Mask values sit at cell centers, so both modes agree away
+    // from cell edges; they differ for points on an edge, where NearestCorner breaks the distance tie
+    // toward the south-west center and ContainingCell uses half-open cells between the midpoints of
+    // neighbouring centers, assigning the edge to the north-east.
+    NearestCorner,
+    ContainingCell
+}
+
+impl BasinMode {
+    pub fn parse(mode: &str) -> Result<BasinMode, Box<dyn Error>> {
+        match mode {
+            "nearest" => Ok(BasinMode::NearestCorner),
+            "cell" => Ok(BasinMode::ContainingCell),
+            _ => Err(format!("invalid basin mode {}, expected nearest or cell", mode).into())
+        }
+    }
+
+    pub fn find(&self, grid: &BasinGrid, longitude: f64, latitude: f64) -> Result<i32, Box<dyn Error>> {
+        match self {
+            BasinMode::NearestCorner => find_basin(grid, longitude, latitude),
+            BasinMode::ContainingCell => find_basin_containing(grid, longitude, latitude)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasinTags {
+    // the basin codes a lookup may return, as inclusive ranges; UNASSIGNED_BASIN is always accepted
+    ranges: Vec<(i32, i32)>
+}
+
+impl BasinTags {
+    pub fn parse(list: &str) -> Result<BasinTags, Box<dyn Error>> {
+        // comma separated codes and low-high ranges, e.g. 1-58 or 1-10,12
+        let mut ranges = Vec::new();
+        for item in list.split(',').map(str::trim) {
+            let (lo, hi) = match item.split_once('-') {
+                Some((lo, hi)) if !lo.is_empty() => (lo, hi),
+                _ => (item, item)
+            };
+            let lo: i32 = lo.trim().parse().map_err(|_| format!("invalid basin tag {} in {}", lo, list))?;
+            let hi: i32 = hi.trim().parse().map_err(|_| format!("invalid basin tag {} in {}", hi, list))?;
+            if lo > hi {
+                return Err(format!("basin tag range {} runs backwards", item).into());
+            }
+            if lo <= UNASSIGNED_BASIN && UNASSIGNED_BASIN <= hi {
+                return Err(format!("basin tag {} is reserved for unassigned points", UNASSIGNED_BASIN).into());
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(BasinTags{ranges: ranges})
+    }
+
+    pub fn contains(&self, tag: i32) -> bool {
+        tag == UNASSIGNED_BASIN || self.ranges.iter().any(|&(lo, hi)| lo <= tag && tag <= hi)
+    }
+
+    pub fn check(&self, tag: i32, longitude: f64, latitude: f64) -> Result<i32, Box<dyn Error>> {
+        // an unexpected code usually means the wrong mask file, so it stops the run rather than being stored
+        if self.contains(tag) {
+            Ok(tag)
+        } else {
+            Err(format!("unexpected basin tag {} at {}, {}; expected {} or {}", tag, longitude, latitude, UNASSIGNED_BASIN, self).into())
+        }
+    }
+}
+
+impl std::fmt::Display for BasinTags {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let items: Vec<String> = self.ranges.iter().map(|&(lo, hi)| if lo == hi { lo.to_string() } else { format!("{}-{}", lo, hi) }).collect();
+        write!(f, "{}", items.join(","))
+    }
+}
+
+fn check_lookup(grid: &BasinGrid, longitude: f64, latitude: f64) -> Result<(), Box<dyn Error>> {
+    if grid.tags.is_empty() || grid.tags.len() != grid.lats.len() * grid.lons.len() {
+        return Err(format!("basin grid has {} tags for a {}x{} grid", grid.tags.len(), grid.lats.len(), grid.lons.len()).into());
+    }
+    if !longitude.is_finite() || !latitude.is_finite() {
+        return Err(format!("no basin for non-finite coordinates {}, {}", longitude, latitude).into());
+    }
+    Ok(())
+}
+
+pub fn find_basin_containing(grid: &BasinGrid, longitude: f64, latitude: f64) -> Result<i32, Box<dyn Error>> {
+    check_lookup(grid, longitude, latitude)?;
+    let lons = &grid.lons;
+    let longitude = if grid.ring() {
+        // into the 360 degrees from the western edge of the first cell, half way across the gap from the last
+        let edge = (lons[lons.len() - 1] - 360.0 + lons[0]) / 2.0;
+        edge + (longitude - edge).rem_euclid(360.0)
+    } else {
+        longitude
+    };
+    Ok(grid.tag(containing_index(&grid.lats, latitude), containing_index(lons, longitude)))
+}
+
+fn coordinate(file: &netcdf::File, name: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    // a basin grid coordinate, which must be ascending; the spacing may vary
+    let var = file.variable(name).ok_or_else(|| SyncError::missing_variable(name, format!("Could not find basin coordinate variable '{}'", name)))?;
+    let mut values = Vec::with_capacity(var.len());
+    for i in 0..var.len() {
+        values.push(var.value::<f64, _>(i)?);
+    }
+    if values.is_empty() {
+        return Err(format!("basin coordinate {} is empty", name).into());
+    }
+    for pair in values.windows(2) {
+        if pair[1] <= pair[0] {
+            return Err(format!("basin coordinate {} is not ascending: {} follows {}", name, pair[1], pair[0]).into());
+        }
+    }
+    Ok(values)
+}
+
+pub fn find_basin(grid: &BasinGrid, longitude: f64, latitude: f64) -> Result<i32, Box<dyn Error>> {
+    // on a rectilinear grid the nearest center is the nearest latitude row and longitude column
+    check_lookup(grid, longitude, latitude)?;
+    Ok(grid.tag(nearest_index(&grid.lats, latitude, false), nearest_index(&grid.lons, longitude, grid.ring())))
+}
+
+pub struct BasinLookup {
+    // everything a cell's basin tag depends on: the mask, how points map onto it and the codes it may hold
+    pub grid: BasinGrid,
+    pub mode: BasinMode,
+    pub tags: BasinTags
+}
+
+impl BasinLookup {
+    pub fn basin(&self, longitude: f64, latitude: f64) -> Result<i32, Box<dyn Error>> {
+        self.tags.check(self.mode.find(&self.grid, longitude, latitude)?, longitude, latitude)
+    }
+}
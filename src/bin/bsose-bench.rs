@@ -0,0 +1,104 @@
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::time::Instant;
+use bsose_sync::Summary;
+use mongodb::Client;
+use serde::Serialize;
+
+const USAGE: &str = "\
+usage: bsose-bench [--repeat <n>] <file> <variable> <lolat> <hilat> <lolong> <hilong> [bsose-sync options]
+
+Runs bsose-sync against a throwaway database on $MONGODB_URI and prints one JSON line per run with
+cells/second and documents/second. Every option other than --repeat is passed through to bsose-sync,
+so concurrency flags like --level-concurrency and --threads can be swept from a script; --db and
+--summary-json are set by the benchmark and the database is dropped after each run.";
+
+#[derive(Serialize)]
+struct Run {
+    // one timed ingest, written as a line of JSON for trend tracking
+    run: usize,
+    args: Vec<String>,
+    seconds: f64,
+    cells: u64,
+    docs: u64,
+    cells_per_second: f64,
+    docs_per_second: f64
+}
+
+fn sync_binary() -> Result<PathBuf, Box<dyn Error>> {
+    // bsose-sync is built next to this binary
+    let exe = env::current_exe()?;
+    let dir = exe.parent().ok_or("could not locate the bsose-sync binary")?;
+    Ok(dir.join(format!("bsose-sync{}", env::consts::EXE_SUFFIX)))
+}
+
+async fn bench(argv: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut repeat = 1;
+    let mut passthrough = Vec::new();
+    let mut i = 1;
+    while i < argv.len() {
+        if argv[i] == "--repeat" {
+            i += 1;
+            let n = argv.get(i).ok_or("flag --repeat requires a value")?;
+            repeat = n.parse::<usize>().map_err(|e| format!("invalid --repeat {}: {}", n, e))?;
+        } else if argv[i] == "--db" || argv[i] == "--summary-json" {
+            return Err(format!("{} is set by the benchmark", argv[i]).into());
+        } else {
+            passthrough.push(argv[i].clone());
+        }
+        i += 1;
+    }
+
+    let uri = env::var("MONGODB_URI").map_err(|_| "You must set the MONGODB_URI environment var!")?;
+    let client = Client::with_uri_str(&uri).await?;
+    let binary = sync_binary()?;
+
+    for run in 1..=repeat {
+        let db = format!("bsose_bench_{}_{}", process::id(), run);
+        let summary_path = env::temp_dir().join(format!("{}.json", db));
+        let started = Instant::now();
+        let status = Command::new(&binary)
+            .args(&passthrough)
+            .arg("--db").arg(&db)
+            .arg("--summary-json").arg(&summary_path)
+            .status()?;
+        let seconds = started.elapsed().as_secs_f64();
+        let dropped = client.database(&db).drop(None).await;
+        if !status.success() {
+            return Err(format!("bsose-sync exited with {}", status).into());
+        }
+        dropped?;
+
+        let summary: Summary = serde_json::from_str(&std::fs::read_to_string(&summary_path)?)?;
+        std::fs::remove_file(&summary_path)?;
+        // a fresh database means every cell gets a new metadoc
+        let cells = summary.metadocs_inserted + summary.metadocs_updated;
+        let docs = summary.metadocs_inserted + summary.metadocs_updated + summary.data_inserted + summary.data_updated;
+        let result = Run{
+            run: run,
+            args: passthrough.clone(),
+            seconds: seconds,
+            cells: cells,
+            docs: docs,
+            cells_per_second: cells as f64 / seconds,
+            docs_per_second: docs as f64 / seconds
+        };
+        println!("{}", serde_json::to_string(&result)?);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let argv: Vec<String> = env::args().collect();
+    if argv.len() < 2 || argv.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(e) = bench(&argv).await {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
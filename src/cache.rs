@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, HashMap};
+use mongodb::bson::DateTime;
+use crate::BsoseMetadoc;
+
+pub struct MetaCache {
+    // least recently used cache of the metadocs a run has read or written, keyed by metaid
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (BsoseMetadoc, u64)>,
+    order: BTreeMap<u64, String>,
+    pub hits: u64,
+    pub misses: u64
+}
+
+impl MetaCache {
+    pub fn new(capacity: usize) -> MetaCache {
+        MetaCache {
+            capacity: capacity,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&mut self, metaid: &str) -> Option<&BsoseMetadoc> {
+        self.tick += 1;
+        match self.entries.get_mut(metaid) {
+            Some((meta, last_used)) => {
+                self.order.remove(last_used);
+                self.order.insert(self.tick, metaid.to_string());
+                *last_used = self.tick;
+                self.hits += 1;
+                Some(meta)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn put(&mut self, meta: BsoseMetadoc) {
+        // also used to replace an entry after its metadoc is rewritten
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.remove(&meta._id) {
+            self.order.remove(&last_used);
+        } else if self.entries.len() >= self.capacity {
+            let oldest = self.order.keys().next().copied();
+            if let Some(oldest) = oldest {
+                if let Some(evicted) = self.order.remove(&oldest) {
+                    self.entries.remove(&evicted);
+                }
+            }
+        }
+        self.order.insert(self.tick, meta._id.clone());
+        self.entries.insert(meta._id.clone(), (meta, self.tick));
+    }
+
+    pub fn set_timeseries(&mut self, metaid: &str, timeseries: &[DateTime]) {
+        // mirrors a sink's set_meta_timeseries, without counting as a use
+        if let Some((meta, _)) = self.entries.get_mut(metaid) {
+            meta.timeseries = timeseries.to_vec();
+        }
+    }
+
+    pub fn remove(&mut self, metaid: &str) {
+        // for a metadoc whose stored state is no longer known, e.g. after its cell failed
+        if let Some((_, last_used)) = self.entries.remove(metaid) {
+            self.order.remove(&last_used);
+        }
+    }
+}
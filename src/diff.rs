@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use mongodb::bson::DateTime;
+use serde::Serialize;
+use crate::{BsoseDocument, BsoseMetadoc, CellRecord, Sourcedoc};
+use crate::sink::{DocumentSink, MemorySink};
+
+// --diff: an ingest run against the stored documents without writing any. Reads go through to the real sink,
+// writes land in an in-memory overlay that later reads see, so every merge is computed exactly as a real run
+// would; what changed is then each touched document in the overlay compared with the one stored.
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Change {
+    pub id: String,
+    // "meta" or "data"
+    pub kind: String,
+    // nothing was stored under id
+    pub new: bool,
+    // timeseries length, or a data document's column length, before and after
+    pub timesteps: (usize, usize),
+    pub variables_added: Vec<String>,
+    // stored NaNs that get a value, and stored values replaced by a different one, NaN included
+    pub values_filled: usize,
+    pub values_overwritten: usize,
+    // provenance as source/iter
+    pub sources_added: Vec<String>,
+    pub sources_removed: Vec<String>
+}
+
+impl Change {
+    pub fn is_empty(&self) -> bool {
+        !self.new && self.timesteps.0 == self.timesteps.1 && self.variables_added.is_empty() && self.values_filled == 0
+            && self.values_overwritten == 0 && self.sources_added.is_empty() && self.sources_removed.is_empty()
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.new {
+            parts.push(format!("new, {} timesteps", self.timesteps.1));
+        } else if self.timesteps.1 != self.timesteps.0 {
+            parts.push(format!("timesteps {} -> {}", self.timesteps.0, self.timesteps.1));
+        }
+        if !self.variables_added.is_empty() {
+            parts.push(format!("{} {}", if self.new { "variables" } else { "variables added" }, self.variables_added.join(", ")));
+        }
+        if self.values_filled > 0 {
+            parts.push(format!("{} values filled", self.values_filled));
+        }
+        if self.values_overwritten > 0 {
+            parts.push(format!("{} values overwritten", self.values_overwritten));
+        }
+        if !self.sources_added.is_empty() {
+            parts.push(format!("source {} added", self.sources_added.join(", ")));
+        }
+        if !self.sources_removed.is_empty() {
+            parts.push(format!("source {} removed", self.sources_removed.join(", ")));
+        }
+        write!(f, "{:<4} {}: {}", self.kind, self.id, parts.join("; "))
+    }
+}
+
+fn source_label(source: &Sourcedoc) -> String {
+    format!("{}/{}", source.source.join(","), source.iter)
+}
+
+pub fn meta_change(old: Option<&BsoseMetadoc>, new: &BsoseMetadoc) -> Change {
+    let labels = |meta: Option<&BsoseMetadoc>| -> Vec<String> { meta.map(|m| m.source.iter().map(source_label).collect()).unwrap_or_default() };
+    let (before, after) = (labels(old), labels(Some(new)));
+    Change {
+        id: new._id.clone(),
+        kind: String::from("meta"),
+        new: old.is_none(),
+        timesteps: (old.map(|m| m.timeseries.len()).unwrap_or(0), new.timeseries.len()),
+        sources_added: after.iter().filter(|s| !before.contains(s)).cloned().collect(),
+        sources_removed: before.iter().filter(|s| !after.contains(s)).cloned().collect(),
+        ..Default::default()
+    }
+}
+
+pub fn data_change(old: Option<&BsoseDocument>, new: &BsoseDocument) -> Change {
+    // values are compared by variable and position; positions past the stored length are new timesteps, and a
+    // new variable's values count towards neither filled nor overwritten
+    let length = |doc: &BsoseDocument| doc.data.first().map(|d| d.len()).unwrap_or(0);
+    let mut change = Change {
+        id: new._id.clone(),
+        kind: String::from("data"),
+        new: old.is_none(),
+        timesteps: (old.map(length).unwrap_or(0), length(new)),
+        ..Default::default()
+    };
+    for (variable, values) in new.data_info.0.iter().zip(&new.data) {
+        let stored = old.and_then(|o| o.data_info.0.iter().position(|v| v == variable).and_then(|i| o.data.get(i)));
+        let Some(stored) = stored else {
+            change.variables_added.push(variable.clone());
+            continue;
+        };
+        for (before, after) in stored.iter().zip(values) {
+            if before.is_nan() && !after.is_nan() {
+                change.values_filled += 1;
+            } else if !before.is_nan() && before != after {
+                change.values_overwritten += 1;
+            }
+        }
+    }
+    change
+}
+
+pub struct DiffSink<'a, S: DocumentSink> {
+    inner: &'a S,
+    overlay: MemorySink,
+    // documents as first found in inner, None where nothing was stored, keyed by id
+    stored_meta: Mutex<HashMap<String, Option<BsoseMetadoc>>>,
+    stored_data: Mutex<HashMap<String, Option<BsoseDocument>>>,
+    // ids the run wrote to, in the order it first did, and as a set to look them up in
+    touched: Mutex<Vec<(bool, String)>>,
+    touched_ids: Mutex<HashSet<(bool, String)>>
+}
+
+impl<'a, S: DocumentSink> DiffSink<'a, S> {
+    pub fn new(inner: &'a S) -> DiffSink<'a, S> {
+        DiffSink {
+            inner: inner,
+            overlay: MemorySink::default(),
+            stored_meta: Mutex::new(HashMap::new()),
+            stored_data: Mutex::new(HashMap::new()),
+            touched: Mutex::new(Vec::new()),
+            touched_ids: Mutex::new(HashSet::new())
+        }
+    }
+
+    async fn load_meta(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        // copies a stored metadoc into the overlay the first time the run asks for it
+        if self.stored_meta.lock().unwrap().contains_key(id) {
+            return Ok(());
+        }
+        let stored = self.inner.find_meta(id).await?;
+        if let Some(meta) = &stored {
+            self.overlay.metadocs.lock().unwrap().insert(id.to_string(), meta.clone());
+        }
+        self.stored_meta.lock().unwrap().insert(id.to_string(), stored);
+        Ok(())
+    }
+
+    async fn load_data(&self, id: &str) -> Result<(), Box<dyn Error>> {
+        if self.stored_data.lock().unwrap().contains_key(id) {
+            return Ok(());
+        }
+        let stored = self.inner.find_data(id).await?;
+        if let Some(doc) = &stored {
+            self.overlay.docs.lock().unwrap().insert(id.to_string(), doc.clone());
+        }
+        self.stored_data.lock().unwrap().insert(id.to_string(), stored);
+        Ok(())
+    }
+
+    fn touch(&self, meta: bool, id: &str) {
+        // every write of a run touches a document, so this stays constant time over a whole tile
+        if self.touched_ids.lock().unwrap().insert((meta, id.to_string())) {
+            self.touched.lock().unwrap().push((meta, id.to_string()));
+        }
+    }
+
+    pub fn changes(&self) -> Vec<Change> {
+        // what the run would change, one entry per document that would differ from the stored one
+        let stored_meta = self.stored_meta.lock().unwrap();
+        let stored_data = self.stored_data.lock().unwrap();
+        let metadocs = self.overlay.metadocs.lock().unwrap();
+        let docs = self.overlay.docs.lock().unwrap();
+        let mut changes = Vec::new();
+        for (meta, id) in self.touched.lock().unwrap().iter() {
+            let change = if *meta {
+                metadocs.get(id).map(|m| meta_change(stored_meta.get(id).and_then(|s| s.as_ref()), m))
+            } else {
+                docs.get(id).map(|d| data_change(stored_data.get(id).and_then(|s| s.as_ref()), d))
+            };
+            changes.extend(change.filter(|c| !c.is_empty()));
+        }
+        changes
+    }
+}
+
+pub fn report(changes: &[Change]) -> String {
+    // the changes, one per line, and a count of each kind
+    let mut lines: Vec<String> = changes.iter().map(|c| c.to_string()).collect();
+    let count = |kind: &str, new: bool| changes.iter().filter(|c| c.kind == kind && c.new == new).count();
+    lines.push(format!("would insert {} metadata and {} data documents, and change {} metadata and {} data documents",
+        count("meta", true), count("data", true), count("meta", false), count("data", false)));
+    lines.join("\n")
+}
+
+#[async_trait(?Send)]
+impl<'a, S: DocumentSink> DocumentSink for DiffSink<'a, S> {
+    async fn find_meta(&self, id: &str) -> Result<Option<BsoseMetadoc>, Box<dyn Error>> {
+        self.load_meta(id).await?;
+        self.overlay.find_meta(id).await
+    }
+
+    async fn insert_meta(&self, meta: BsoseMetadoc) -> Result<(), Box<dyn Error>> {
+        // a stored metadoc makes this fail as the real insert would
+        self.load_meta(&meta._id).await?;
+        self.touch(true, &meta._id);
+        self.overlay.insert_meta(meta).await
+    }
+
+    async fn set_meta_timeseries(&self, id: &str, timeseries: &[DateTime]) -> Result<(), Box<dyn Error>> {
+        self.load_meta(id).await?;
+        self.touch(true, id);
+        self.overlay.set_meta_timeseries(id, timeseries).await
+    }
+
+    async fn add_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        self.load_meta(id).await?;
+        self.touch(true, id);
+        self.overlay.add_meta_source(id, source).await
+    }
+
+    async fn replace_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        self.load_meta(id).await?;
+        self.touch(true, id);
+        self.overlay.replace_meta_source(id, source).await
+    }
+
+    async fn add_meta_chunked(&self, id: &str, data_id: &str) -> Result<(), Box<dyn Error>> {
+        self.load_meta(id).await?;
+        self.overlay.add_meta_chunked(id, data_id).await
+    }
+
+    async fn find_data(&self, id: &str) -> Result<Option<BsoseDocument>, Box<dyn Error>> {
+        self.load_data(id).await?;
+        self.overlay.find_data(id).await
+    }
+
+    async fn insert_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        self.load_data(&doc._id).await?;
+        self.touch(false, &doc._id);
+        self.overlay.insert_data(doc).await
+    }
+
+    async fn replace_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        self.load_data(&doc._id).await?;
+        self.touch(false, &doc._id);
+        self.overlay.replace_data(doc).await
+    }
+
+    async fn completed_cells(&self, run_id: &str, file: &str, variable: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        self.inner.completed_cells(run_id, file, variable).await
+    }
+
+    async fn record_cell(&self, _: CellRecord) -> Result<(), Box<dyn Error>> {
+        // nothing is written, so there is nothing for --resume to skip
+        Ok(())
+    }
+}
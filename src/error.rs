@@ -0,0 +1,87 @@
+use std::error::Error;
+use thiserror::Error;
+
+// what went wrong, by category; the binary turns each into its exit code
+#[derive(Debug, Error)]
+pub enum SyncError {
+    // a bad flag, config file or environment variable
+    #[error("{0}")]
+    Args(String),
+    // a NetCDF file that couldn't be opened or read
+    #[error("{0}")]
+    Netcdf(String),
+    // a MongoDB client that couldn't be configured or reached
+    #[error("{0}")]
+    Mongo(String),
+    // input that fails a consistency check, like grid values colliding in document ids
+    #[error("{0}")]
+    Validation(String),
+    // a variable the run needs isn't in the file; detail says which role it was looked up for
+    #[error("{detail}")]
+    MissingVariable{name: String, detail: String},
+    // a signal, or --max-runtime running out, stopped the run between cells
+    #[error("interrupted; rerun to pick up the remaining cells")]
+    Interrupted,
+    #[error("--max-runtime deadline reached; rerun to pick up the remaining cells")]
+    Deadline,
+    // anything else, typically a failed read or write partway through a run
+    #[error("{0}")]
+    Other(String)
+}
+
+impl SyncError {
+    // category constructors for map_err; an error that already has a category keeps it
+
+    pub fn args<E: Into<Box<dyn Error>>>(e: E) -> SyncError {
+        SyncError::categorize(e.into(), SyncError::Args)
+    }
+
+    pub fn netcdf<E: Into<Box<dyn Error>>>(e: E) -> SyncError {
+        SyncError::categorize(e.into(), SyncError::Netcdf)
+    }
+
+    pub fn mongo<E: Into<Box<dyn Error>>>(e: E) -> SyncError {
+        SyncError::categorize(e.into(), SyncError::Mongo)
+    }
+
+    pub fn missing_variable(name: &str, detail: String) -> SyncError {
+        SyncError::MissingVariable{name: name.to_string(), detail: detail}
+    }
+
+    fn categorize(e: Box<dyn Error>, otherwise: fn(String) -> SyncError) -> SyncError {
+        // a NetCDF read passed up with ? is a NetCDF failure whatever the call site expected
+        let e = match e.downcast::<SyncError>() {
+            Ok(e) => return *e,
+            Err(e) => e
+        };
+        match e.downcast::<netcdf::error::Error>() {
+            Ok(e) => SyncError::from(*e),
+            Err(e) => otherwise(e.to_string())
+        }
+    }
+}
+
+impl From<netcdf::error::Error> for SyncError {
+    fn from(e: netcdf::error::Error) -> SyncError {
+        SyncError::Netcdf(e.to_string())
+    }
+}
+
+impl From<mongodb::error::Error> for SyncError {
+    fn from(e: mongodb::error::Error) -> SyncError {
+        SyncError::Mongo(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for SyncError {
+    fn from(e: std::io::Error) -> SyncError {
+        SyncError::Other(e.to_string())
+    }
+}
+
+impl From<Box<dyn Error>> for SyncError {
+    // the library's Box<dyn Error> results, which may carry a SyncError inside
+    fn from(e: Box<dyn Error>) -> SyncError {
+        SyncError::categorize(e, SyncError::Other)
+    }
+}
@@ -0,0 +1,711 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use mongodb::bson::DateTime;
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use crate::{Sourcedoc, BsoseDocument, Extractor, format_id, cf_times, partition_by_year, collection_name, YEAR_PLACEHOLDER, check_id_collisions, check_center_collisions, check_meta_coordinates, check_data_lengths, check_timeline, set_metadata, chunk_id, document_size, split_document, TimelineRef, Summary, ValueStats, CellTiming, CellRecord, shuffle, parse_date, valid_fraction, global_attributes, extend_timeline, covered, place, pad_data, reconcile, OnInconsistent, OnBadTime, OutOfRange, LonConvention, DryCells, Reconciled, LatIdx, LonIdx};
+use crate::args::Args;
+use crate::cache::MetaCache;
+use crate::basin::{BasinGrid, BasinMode, BasinTags, BasinLookup};
+use crate::sink::{DocumentSink, IdLocks};
+use crate::input;
+use crate::error::SyncError;
+use crate::metrics::Progress;
+use crate::{debug, info, summary, warn};
+
+// an ingest of the tile from a list of files into any DocumentSink: every cell's metadoc, then its data documents,
+// per file and variable. The binary picks the sink and handles the maintenance modes around it.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pass {
+    // which half of a cell's work a step of the cell loop does
+    Meta,
+    Data
+}
+
+enum Written {
+    // outcome of writing one level's profile, with the number of values written
+    Inserted(u64),
+    Updated(u64),
+    Skipped
+}
+
+fn force_new_error(args: &Args, id: &str, e: Box<dyn Error>) -> Box<dyn Error> {
+    // an insert failing under --force-new most likely hit a document the run assumed wasn't there
+    if args.force_new {
+        format!("--force-new: could not insert {}, which may already be stored; rerun without --force-new to merge into existing documents: {}", id, e).into()
+    } else {
+        e
+    }
+}
+
+async fn write_level<S: DocumentSink>(sink: &S, extractor: &Extractor<'_>, id: String, (profile, flags): (Vec<f64>, Option<Vec<f64>>), new_doc: Option<BsoseDocument>, (metaid, stored_len, timeline, fresh): (&str, usize, &[DateTime], bool), args: &Args) -> Result<Written, Box<dyn Error>> {
+    // metaid is the cell's metadoc, which exists by now; stored_len is its timeseries length before this run,
+    // timeline the timeseries after it; fresh skips looking for a stored document under --force-new
+    let n_values = profile.len() as u64;
+    let n_timesteps = timeline.len();
+
+    // Check if a document with property "_id" matching id exists
+    let stored = if fresh { None } else { sink.find_data(&id).await? };
+    if let Some(mut doc) = stored {
+        if let Err(e) = check_timeline(&doc, timeline, stored_len) {
+            if OnInconsistent::parse(&args.on_inconsistent)? != OnInconsistent::Skip {
+                return Err(e);
+            }
+            warn!("{}; skipped", e);
+            return Ok(Written::Skipped);
+        }
+        // another variable of this run may already have extended the document to the new timeline
+        let expected = if doc.data.iter().all(|d| d.len() == n_timesteps) { n_timesteps } else { stored_len };
+        match reconcile(&mut doc, expected, OnInconsistent::parse(&args.on_inconsistent)?)? {
+            Reconciled::Consistent => {},
+            Reconciled::Repaired(warning) => warn!("{}; repaired", warning),
+            Reconciled::Skipped(warning) => {
+                warn!("{}; skipped", warning);
+                return Ok(Written::Skipped);
+            }
+        }
+        if let Some(warning) = set_metadata(&mut doc, metaid) {
+            warn!("{}; repaired", warning);
+        }
+        // Merge the profile into the existing "data" property
+        let old_len = doc.data.first().map(|d| d.len()).unwrap_or(0);
+        pad_data(&mut doc, n_timesteps);
+        // where this file's timesteps sit in the timeline; a file covering part of it leaves the rest alone
+        let covered = covered(timeline, &extractor.timeseries);
+        if args.append_only && args.overwrite {
+            let blocked = extractor.overwrites_at(&doc, &profile, &covered);
+            if !blocked.is_empty() {
+                return Err(format!("--append-only: document {} would overwrite {} stored {} values at timestep indexes {:?}",
+                    id, blocked.len(), extractor.dv, blocked).into());
+            }
+        }
+        if let Some(conflict) = extractor.units_conflict(&doc) {
+            // files disagreeing on a variable's metadata are probably not meant to be combined
+            if args.strict_units {
+                return Err(format!("--strict-units: {}", conflict).into());
+            }
+            if extractor.first_units_conflict() {
+                warn!("{}; {} {}, further conflicts aren't reported", conflict,
+                    if args.overwrite { "replacing it with this file's for" } else { "keeping the stored metadata for" }, extractor.dv);
+            }
+        }
+        let written = extractor.merge_at(&mut doc, profile, args.overwrite, &covered);
+        if let Some(flags) = &flags {
+            extractor.merge_qc_at(&mut doc, flags, &written);
+        }
+        debug!("data {}: {} wrote {} timesteps at indexes {:?}; length {} -> {} across {} variables",
+            id, extractor.dv, written.len(), written, old_len, n_timesteps, doc.data.len());
+        check_data_lengths(&doc, n_timesteps)?;
+        doc.timeline = Some(TimelineRef::of(timeline));
+        let size = document_size(&doc)?;
+        if size > args.max_document_size {
+            // only new documents are split, so a stored one can't grow past the limit
+            return Err(SyncError::Validation(format!("document {} would grow to {} bytes, over --max-document-size {}", id, size, args.max_document_size)).into());
+        }
+        sink.replace_data(doc).await?;
+        return Ok(Written::Updated(n_values));
+    }
+    match new_doc {
+        Some(mut doc) => {
+            if !fresh && sink.find_data(&chunk_id(&id, 0)).await?.is_some() {
+                return Err(SyncError::Validation(format!("document {} is stored in chunks, which ingests don't merge into", id)).into());
+            }
+            check_data_lengths(&doc, n_timesteps)?;
+            doc.timeline = Some(TimelineRef::of(timeline));
+            let size = document_size(&doc)?;
+            if size <= args.max_document_size {
+                sink.insert_data(doc).await.map_err(|e| force_new_error(args, &id, e))?;
+                return Ok(Written::Inserted(n_values));
+            }
+            if !args.split_documents {
+                return Err(SyncError::Validation(format!("document {} would be {} bytes, over --max-document-size {}; --split-documents stores it in chunks",
+                    id, size, args.max_document_size)).into());
+            }
+            let chunks = split_document(&doc, args.max_document_size)?;
+            debug!("data {}: {} bytes, stored as {} chunks", id, size, chunks.len());
+            sink.add_meta_chunked(&doc.metadata[0], &id).await?;
+            for chunk in chunks {
+                sink.insert_data(chunk).await.map_err(|e| force_new_error(args, &id, e))?;
+            }
+            Ok(Written::Inserted(n_values))
+        }
+        None => Ok(Written::Skipped)
+    }
+}
+
+pub struct Stop {
+    // why the cell loops should stop starting new cells: a signal, or --max-runtime running out
+    signalled: Arc<AtomicBool>,
+    // tokio's clock, which tests can pause and advance
+    deadline: Option<tokio::time::Instant>,
+    // latched the first time a loop sees the deadline, so a run that finishes just after it isn't reported as cut short
+    expired: AtomicBool
+}
+
+impl Stop {
+    pub fn new(signalled: Arc<AtomicBool>, max_runtime: Option<std::time::Duration>) -> Stop {
+        Stop {
+            signalled: signalled,
+            deadline: max_runtime.and_then(|d| tokio::time::Instant::now().checked_add(d)),
+            expired: AtomicBool::new(false)
+        }
+    }
+
+    fn requested(&self) -> bool {
+        if self.deadline.is_some_and(|d| tokio::time::Instant::now() >= d) && !self.expired.swap(true, Ordering::SeqCst) {
+            warn!("--max-runtime reached, finishing writes in flight");
+        }
+        self.stopped()
+    }
+
+    fn stopped(&self) -> bool {
+        // whether a loop has already stopped, without checking the clock again
+        self.signalled.load(Ordering::SeqCst) || self.expired.load(Ordering::SeqCst)
+    }
+}
+
+pub struct Run<'a> {
+    // state shared by every file and variable ingested by one call of ingest_all
+    stop: &'a Stop,
+    progress: Arc<Progress>,
+    // serializes the read-modify-write of a document between variables ingested concurrently
+    locks: IdLocks,
+    // pre-run timeseries lengths of the metadocs the current file appended to, shared so a variable
+    // that finds a timeseries another variable already extended still knows its stored length
+    appended: Mutex<HashMap<String, usize>>,
+    // under --force-new, the ids this run inserted, which are the only ones it looks up before writing
+    inserted: Mutex<HashSet<String>>,
+    // metadocs already read or written, so a cell met again in a later file or variable isn't looked up again
+    meta_cache: Mutex<MetaCache>
+}
+
+impl<'a> Run<'a> {
+    pub fn new(stop: &'a Stop, meta_cache_size: usize) -> Run<'a> {
+        Run {
+            stop: stop,
+            progress: Arc::new(Progress::default()),
+            locks: IdLocks::default(),
+            appended: Mutex::new(HashMap::new()),
+            inserted: Mutex::new(HashSet::new()),
+            meta_cache: Mutex::new(MetaCache::new(meta_cache_size))
+        }
+    }
+}
+
+pub fn watch_signals() -> Arc<AtomicBool> {
+    // SIGINT or SIGTERM only raises a flag; the cell loops check it so writes in flight can finish
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            let mut term = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(term) => term,
+                Err(_) => return
+            };
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = term.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+        }
+        warn!("interrupted, finishing writes in flight");
+        flag.store(true, Ordering::SeqCst);
+    });
+    stop
+}
+
+// the options of one collection pair an ingest writes to, with the files it takes
+pub type Partition = (Args, Vec<String>);
+
+pub fn partitions(args: &Args, files: &[String]) -> Result<Vec<Partition>, Box<dyn Error>> {
+    // the collections an ingest writes to: the ones named, or with a {year} placeholder one pair per year,
+    // each taking every file with timesteps in the year. Files are opened here for their times and again
+    // when each of their years is ingested
+    if !args.data_collection.contains(YEAR_PLACEHOLDER) {
+        return Ok(vec!((args.clone(), files.to_vec())));
+    }
+    let since = match &args.since {
+        Some(date) => Some(parse_date(date).map_err(SyncError::args)?),
+        None => None
+    };
+    let mut years: BTreeMap<i32, Vec<String>> = BTreeMap::new();
+    for path in files {
+        let file = input::open(path).map_err(SyncError::netcdf)?;
+        let times: Vec<DateTime> = cf_times(&args.var_map.variable(&file, "time")?)?.into_iter().map(DateTime::from_chrono).collect();
+        // timesteps --since leaves out don't make a year of their own
+        let times: Vec<DateTime> = times.into_iter().filter(|t| !matches!(since, Some(cutoff) if *t < cutoff)).collect();
+        for year in partition_by_year(&times).into_keys() {
+            years.entry(year).or_default().push(path.clone());
+        }
+    }
+    Ok(years.into_iter().map(|(year, files)| {
+        let partition = Args{
+            data_collection: collection_name(&args.data_collection, Some(year), &args.iter),
+            meta_collection: collection_name(&args.meta_collection, Some(year), &args.iter),
+            partition_year: Some(year),
+            // a cell is done once per year, so each year records its progress apart, and reports to its own files
+            run_id: args.run_id.as_ref().map(|id| format!("{}/{}", id, year)),
+            summary_json: args.summary_json.as_ref().map(|path| format!("{}.{}", path, year)),
+            diff_json: args.diff_json.as_ref().map(|path| format!("{}.{}", path, year)),
+            ..args.clone()
+        };
+        (partition, files)
+    }).collect())
+}
+
+pub async fn ingest_all<S: DocumentSink>(args: &Args, files: &[String], sink: &S, stop: &Stop) -> Result<(), Box<dyn Error>> {
+    // each file in turn, with a combined report; stop is the whole invocation's, so --max-runtime and a signal
+    // cover every {year} partition together
+    if args.force_new {
+        warn!("--force-new assumes {} and {} hold none of the tile's documents; against populated collections the run stops at the first one already stored",
+            args.meta_collection, args.data_collection);
+    }
+    if args.lon_convention == "zero360" {
+        warn!("--lon-convention zero360 puts geolocation longitudes east of 180 into {}, which a 2dsphere index on it rejects",
+            args.data_collection);
+    }
+    // basin lookup
+    let basinfile = netcdf::open(&args.basin_file).map_err(SyncError::netcdf)?;
+    let basins = BasinLookup{
+        grid: BasinGrid::from_file(&basinfile)?,
+        mode: BasinMode::parse(&args.basin_mode).map_err(SyncError::args)?,
+        tags: BasinTags::parse(&args.basin_tags).map_err(SyncError::args)?
+    };
+
+    let run = Run::new(stop, args.meta_cache_size);
+    #[cfg(feature = "metrics")]
+    let server = match &args.metrics_addr {
+        Some(addr) => Some(crate::metrics::serve(addr, run.progress.clone()).await.map_err(SyncError::args)?),
+        None => None
+    };
+    #[cfg(not(feature = "metrics"))]
+    if args.metrics_addr.is_some() {
+        return Err(SyncError::Args(String::from("--metrics-addr needs a build with --features metrics")).into());
+    }
+    // each variable is ingested with its own copy of the options
+    let per_variable: Vec<Args> = args.variables.iter().map(|v| Args{dv: v.clone(), ..args.clone()}).collect();
+    let mut succeeded = Vec::new();
+    let mut failed = Vec::new();
+    for path in files {
+        if run.stop.requested() {
+            break;
+        }
+        run.appended.lock().unwrap().clear();
+        let result = if args.workers_per_variable {
+            // variables proceed together, taking turns on documents they share; the first error stops them all
+            future::try_join_all(per_variable.iter().map(|a| ingest(a, path, sink, &basins, &run))).await
+        } else {
+            async {
+                let mut summaries = Vec::new();
+                for a in &per_variable {
+                    summaries.push(ingest(a, path, sink, &basins, &run).await?);
+                }
+                Ok::<_, Box<dyn Error>>(summaries)
+            }.await
+        };
+        match result {
+            Ok(summaries) => {
+                for (a, summary) in per_variable.iter().zip(&summaries) {
+                    run.progress.finish(&a.dv, summary);
+                }
+                // a file cut short by a signal or the deadline, or with cells that failed, is only partly ingested
+                if run.stop.stopped() || summaries.iter().any(|s| s.cells_failed > 0) {
+                    failed.push(path.clone());
+                } else {
+                    succeeded.push(path.clone());
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {}: {}", path, e);
+                for a in &per_variable {
+                    run.progress.finish(&a.dv, &Summary::default());
+                }
+                if !args.continue_on_error {
+                    return Err(e);
+                }
+                failed.push(path.clone());
+            }
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Some(server) = server {
+        server.shutdown().await;
+    }
+    let total = run.progress.total();
+    let interrupted = run.stop.stopped();
+    if files.len() > 1 || interrupted {
+        summary!("{} of {} files succeeded", succeeded.len(), files.len());
+        for path in &succeeded {
+            summary!("  ok      {}", path);
+        }
+        for path in &failed {
+            summary!("  failed  {}", path);
+        }
+        summary!("{}", total);
+    }
+    if let Some(path) = &args.summary_json {
+        std::fs::write(path, serde_json::to_string_pretty(&total)?)?;
+    }
+    if run.stop.signalled.load(Ordering::SeqCst) {
+        return Err(SyncError::Interrupted.into());
+    }
+    if interrupted {
+        return Err(SyncError::Deadline.into());
+    }
+    if total.cells_failed > 0 {
+        return Err(format!("{} cells failed, in {} of {} files", total.cells_failed, failed.len(), files.len()).into());
+    }
+    if !failed.is_empty() {
+        return Err(format!("{} of {} files failed", failed.len(), files.len()).into());
+    }
+
+    Ok(())
+}
+
+async fn ingest<S: DocumentSink>(args: &Args, filename: &str, sink: &S, basins: &BasinLookup, run: &Run<'_>) -> Result<Summary, Box<dyn Error>> {
+    // opens the file at filename and ingests it
+    let file = input::open(filename).map_err(SyncError::netcdf)?;
+    ingest_file(args, &file, filename, sink, basins, run).await
+}
+
+pub async fn ingest_file<S: DocumentSink>(args: &Args, file: &netcdf::File, filename: &str, sink: &S, basins: &BasinLookup, run: &Run<'_>) -> Result<Summary, Box<dyn Error>> {
+    // one already-open file's worth of metadata and data documents; filename labels it in provenance and output
+    let started = Instant::now();
+    let dv = &args.dv;
+    let lolat = args.lolat;
+    let hilat = args.hilat;
+    let lolong = args.lolong;
+    let hilong = args.hilong;
+    let id_precision = args.id_precision;
+
+    let basename = Path::new(filename).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or(filename.to_string());
+    let attributes = global_attributes(file, &args.global_attributes)?;
+
+    // document construction //////////////////////////////////////
+
+    let mut extractor = Extractor::with_var_map(file, dv, &args.var_map).map_err(SyncError::netcdf)?;
+    if let Some(data_type) = &args.data_type {
+        extractor.data_type = data_type.clone();
+    }
+    let missing = extractor.missing_attributes()?;
+    if !missing.is_empty() {
+        if args.require_attrs {
+            return Err(SyncError::Validation(format!("--require-attrs: {} in {} has no {} attribute", dv, filename, missing.join(" or "))).into());
+        }
+        warn!("{} in {} has no {} attribute; storing an empty string", dv, filename, missing.join(" or "));
+    }
+    let earliest = parse_date(&args.time_min).map_err(SyncError::args)?;
+    let latest = match &args.time_max {
+        Some(date) => parse_date(date).map_err(SyncError::args)?,
+        None => DateTime::now()
+    };
+    let on_bad_time = OnBadTime::parse(&args.on_bad_time).map_err(SyncError::args)?;
+    let dry_cells = DryCells::parse(&args.dry_cells).map_err(SyncError::args)?;
+    let bad = extractor.check_times(earliest, latest, on_bad_time).map_err(SyncError::args)?;
+    if !bad.is_empty() {
+        warn!("{}: {} timesteps outside {} to {}, the first being {}; {}",
+            filename, bad.len(), earliest, latest, bad[0], if on_bad_time == OnBadTime::Skip { "skipped" } else { "ingesting anyway" });
+    }
+    extractor.lon_convention(LonConvention::parse(&args.lon_convention).map_err(SyncError::args)?);
+    if args.geolocation == "polygon" {
+        extractor.cell_polygons().map_err(SyncError::args)?;
+    }
+    if let Some(name) = args.qc_vars.get(dv) {
+        extractor.qc(file, name).map_err(SyncError::netcdf)?;
+    }
+    extractor.out_of_range(OutOfRange::parse(&args.out_of_range).map_err(SyncError::args)?);
+    if let Some(name) = &args.reference_variable {
+        extractor.reference(file, name, &args.var_map).map_err(SyncError::netcdf)?;
+    }
+    if let Some(since) = &args.since {
+        extractor.since(parse_date(since).map_err(SyncError::args)?).map_err(SyncError::args)?;
+    }
+    if let Some(year) = args.partition_year {
+        extractor.year(year).map_err(SyncError::args)?;
+    }
+    let extractor = extractor;
+
+    // make sure every cell and level in the tile gets a distinct id before writing anything; on a curvilinear
+    // grid neither coordinate follows a single index, so cells are compared by their centers instead
+    let (mut tile_lons, mut tile_lats) = (Vec::new(), Vec::new());
+    if extractor.curvilinear() {
+        let mut centers = Vec::new();
+        for latidx in lolat..hilat {
+            for lonidx in lolong..hilong {
+                centers.push(extractor.center(LatIdx(latidx), LonIdx(lonidx))?);
+            }
+        }
+        check_center_collisions(id_precision, &centers).map_err(SyncError::args)?;
+    } else {
+        for lonidx in lolong..hilong {
+            tile_lons.push(extractor.center(LatIdx(lolat), LonIdx(lonidx))?.0);
+        }
+        for latidx in lolat..hilat {
+            tile_lats.push(extractor.center(LatIdx(latidx), LonIdx(lolong))?.1);
+        }
+    }
+    let levels = extractor.level_range(args.level_min, args.level_max)?;
+    let mut tile_levels = Vec::new();
+    for level in &levels {
+        tile_levels.push(extractor.depth(*level)?);
+    }
+    check_id_collisions(id_precision, &tile_lons, &tile_lats, &tile_levels).map_err(SyncError::args)?;
+
+    // cells to process, in lat-major order unless --shuffle-cells
+    let mut cells = Vec::new();
+    for latidx in lolat..hilat {
+        for lonidx in lolong..hilong {
+            cells.push((LatIdx(latidx), LonIdx(lonidx)));
+        }
+    }
+    if let Some(seed) = args.shuffle_cells {
+        shuffle(&mut cells, seed);
+    }
+    if let (Some(run_id), true) = (&args.run_id, args.resume) {
+        // cells an earlier attempt at this run finished, with their metadocs already extended
+        let done = sink.completed_cells(run_id, &basename, dv).await?;
+        let before = cells.len();
+        let mut pending = Vec::new();
+        for (latidx, lonidx) in cells {
+            let (lon_val, lat_val) = extractor.center(latidx, lonidx)?;
+            if !done.contains(&format_id(id_precision, &[lon_val, lat_val])) {
+                pending.push((latidx, lonidx));
+            }
+        }
+        cells = pending;
+        info!("--resume: {} of {} cells already done in run {}", before - cells.len(), before, run_id);
+    }
+    if let Some(limit) = args.limit {
+        if cells.len() > limit {
+            info!("--limit reached: processing {} of {} cells", limit, cells.len());
+            cells.truncate(limit);
+        }
+    }
+
+    let mut summary = Summary{variables: vec!(dv.to_string()), ..Default::default()};
+
+    // every cell's metadoc, then every cell's data documents; with --transactions each cell's metadoc and data
+    // documents go together instead, in one transaction
+    let steps: Vec<(Pass, LatIdx, LonIdx)> = if args.transactions {
+        cells.iter().flat_map(|&(latidx, lonidx)| [(Pass::Meta, latidx, lonidx), (Pass::Data, latidx, lonidx)]).collect()
+    } else {
+        cells.iter().map(|&(latidx, lonidx)| (Pass::Meta, latidx, lonidx)).chain(cells.iter().map(|&(latidx, lonidx)| (Pass::Data, latidx, lonidx))).collect()
+    };
+    // cells that failed under --continue-on-error, whose remaining step is skipped
+    let mut failed_cells = HashSet::new();
+    for (pass, latidx, lonidx) in steps {
+        // a cell whose transaction has begun is finished first
+        if run.stop.requested() && !(args.transactions && pass == Pass::Data) {
+            break;
+        }
+        if failed_cells.contains(&(latidx, lonidx)) {
+            continue;
+        }
+        let result = match pass {
+            Pass::Meta => async {
+                sink.begin_cell().await?;
+                // construct metadata documents
+                let sourcedoc = Sourcedoc{
+                    source: vec!(args.source.clone()),
+                    iter: args.iter.clone(),
+                    file: basename.clone(),
+                    attributes: attributes.clone()
+                };
+                let (lon_val, lat_val) = extractor.center(latidx, lonidx)?;
+                let metaid = format_id(id_precision, &[lon_val, lat_val]);
+                let _meta_lock = run.locks.lock(&metaid).await;
+                let cached = run.meta_cache.lock().unwrap().get(&metaid).cloned();
+                let stored = if args.force_new && !run.inserted.lock().unwrap().contains(&metaid) {
+                    None
+                } else if cached.is_some() {
+                    cached
+                } else {
+                    let found = sink.find_meta(&metaid).await?;
+                    if let Some(meta) = &found {
+                        run.meta_cache.lock().unwrap().put(meta.clone());
+                    }
+                    found
+                };
+                if let Some(existing) = stored {
+                    check_meta_coordinates(&existing, lon_val, lat_val)?;
+                    let timeline = extend_timeline(&existing.timeseries, &extractor.timeseries).map_err(|e| format!("metadata document {}: {}", metaid, e))?;
+                    let extended = timeline.len() > existing.timeseries.len();
+                    if extended {
+                        run.appended.lock().unwrap().insert(metaid.clone(), existing.timeseries.len());
+                        sink.set_meta_timeseries(&metaid, &timeline).await?;
+                        run.meta_cache.lock().unwrap().set_timeseries(&metaid, &timeline);
+                    }
+                    // record this run's provenance once per (source, iter) pair, or as the only one with --replace-source
+                    let modified = if args.replace_source {
+                        sink.replace_meta_source(&metaid, &sourcedoc).await?
+                    } else {
+                        sink.add_meta_source(&metaid, &sourcedoc).await?
+                    };
+                    if modified || extended {
+                        summary.metadocs_updated += 1;
+                    }
+                    debug!("meta {}: timeseries length {} -> {}, file has {} timesteps; provenance {}/{} {}",
+                        metaid, existing.timeseries.len(), timeline.len(), extractor.timeseries.len(), args.source, args.iter,
+                        match (modified, args.replace_source) {
+                            (true, true) => "replaced the stored sources",
+                            (true, false) => "added",
+                            (false, _) => "already present"
+                        });
+                    return Ok(());
+                }
+                let meta = extractor.metadoc(id_precision, latidx, lonidx, sourcedoc)?;
+                run.meta_cache.lock().unwrap().put(meta.clone());
+                sink.insert_meta(meta).await.map_err(|e| force_new_error(args, &metaid, e))?;
+                if args.force_new {
+                    run.inserted.lock().unwrap().insert(metaid.clone());
+                }
+                summary.metadocs_inserted += 1;
+                Ok::<(), Box<dyn Error>>(())
+            }.await,
+            Pass::Data => async {
+                let cell_started = Instant::now();
+                let (lon_val, lat_val) = extractor.center(latidx, lonidx)?;
+                // construct data documents, one timeseries per lon/lat/level triple
+                let basin = basins.basin(lon_val, lat_val)?;
+                let metaid = format_id(id_precision, &[lon_val, lat_val]);
+                let cached = run.meta_cache.lock().unwrap().get(&metaid).map(|meta| meta.timeseries.clone());
+                let timeline = match cached {
+                    Some(timeseries) => timeseries,
+                    None => {
+                        // evicted since the metadata pass
+                        let meta = sink.find_meta(&metaid).await?.ok_or(format!("metadata document {} disappeared during ingest", metaid))?;
+                        let timeseries = meta.timeseries.clone();
+                        run.meta_cache.lock().unwrap().put(meta);
+                        timeseries
+                    }
+                };
+                let n_timesteps = timeline.len();
+                let stored_len = run.appended.lock().unwrap().get(&metaid).copied().unwrap_or(n_timesteps);
+                let mut jobs = Vec::new();
+                for (level, depth_val) in levels.iter().zip(tile_levels.iter()) {
+                    let mut datavar_profile = extractor.profile(*level, latidx, lonidx)?;
+                    let mut flags = extractor.qc_profile(*level, latidx, lonidx)?;
+                    let id = format_id(id_precision, &[lon_val, lat_val, *depth_val]);
+                    if args.drop_empty && datavar_profile.iter().all(|x| x.is_nan()) {
+                        info!("skipping {}: {} is entirely NaN", id, dv);
+                        summary.data_skipped += 1;
+                        continue;
+                    }
+                    if let Some(min) = args.min_valid_fraction {
+                        let fraction = valid_fraction(&datavar_profile);
+                        if fraction < min {
+                            info!("skipping {}: {:.3} of {}'s timesteps have values, below --min-valid-fraction {}", id, fraction, dv, min);
+                            summary.data_skipped += 1;
+                            continue;
+                        }
+                    }
+                    // land and levels below the bathymetry, per --dry-cells
+                    let dry = extractor.is_dry(*level, latidx, lonidx, &datavar_profile, dry_cells)?;
+                    let stats = ValueStats::of(&datavar_profile);
+                    if timeline != extractor.timeseries {
+                        // the stored timeseries is longer than this run's, e.g. with --since
+                        datavar_profile = place(&timeline, &extractor.timeseries, &datavar_profile);
+                        flags = flags.map(|f| place(&timeline, &extractor.timeseries, &f));
+                    }
+
+                    // new documents are only written for profiles with data
+                    let new_doc = if dry {
+                        None
+                    } else {
+                        let mut doc = extractor.document(id_precision, *level, latidx, lonidx, basin, datavar_profile.clone())?;
+                        if let Some(flags) = &flags {
+                            extractor.merge_qc_at(&mut doc, flags, &(0..flags.len()).collect::<Vec<usize>>());
+                        }
+                        Some(doc)
+                    };
+                    jobs.push((id, (datavar_profile, flags), new_doc, stats));
+                }
+
+                // levels are independent, so their database round trips can overlap; the first error stops the cell.
+                // Simulated rather than against MongoDB: a 52-level, 73-timestep cell ingested into a MemorySink
+                // that delayed every call by 5 ms took 973 ms with --level-concurrency 1, 257 ms with 4, 91 ms with
+                // 16 and 37 ms with 52, and its update 657, 178, 67 and 33 ms
+                let extractor = &extractor;
+                let metaid_ref = metaid.as_str();
+                let timeline = timeline.as_slice();
+                let documents = jobs.len() as u64;
+                let outcomes: Vec<(Written, ValueStats)> = stream::iter(jobs)
+                    .map(|(id, profile, new_doc, stats)| async move {
+                        let _lock = run.locks.lock(&id).await;
+                        let fresh = args.force_new && !run.inserted.lock().unwrap().contains(&id);
+                        let written = write_level(sink, extractor, id.clone(), profile, new_doc, (metaid_ref, stored_len, timeline, fresh), args).await?;
+                        if fresh && matches!(written, Written::Inserted(_)) {
+                            run.inserted.lock().unwrap().insert(id);
+                        }
+                        Ok::<(Written, ValueStats), Box<dyn Error>>((written, stats))
+                    })
+                    .buffer_unordered(args.level_concurrency)
+                    .try_collect()
+                    .await?;
+                for (outcome, stats) in outcomes {
+                    match outcome {
+                        Written::Inserted(n) => {
+                            summary.data_inserted += 1;
+                            summary.timesteps_merged += n;
+                        }
+                        Written::Updated(n) => {
+                            summary.data_updated += 1;
+                            summary.timesteps_merged += n;
+                        }
+                        Written::Skipped => {
+                            summary.data_skipped += 1;
+                            continue;
+                        }
+                    }
+                    summary.values.entry(dv.to_string()).or_default().add(&stats);
+                }
+                summary.cells += 1;
+                let elapsed = cell_started.elapsed();
+                if elapsed > args.slow_cell {
+                    warn!("cell {} took {:.1}s for {} {} documents", metaid, elapsed.as_secs_f64(), documents, dv);
+                }
+                summary.time_cell(CellTiming{cell: metaid.clone(), variable: dv.to_string(), seconds: elapsed.as_secs_f64(), documents: documents});
+                run.progress.set_current(dv, &summary);
+                if let Some(run_id) = &args.run_id {
+                    sink.record_cell(CellRecord::new(run_id, &basename, dv, &metaid)).await?;
+                }
+                sink.commit_cell().await?;
+                Ok::<(), Box<dyn Error>>(())
+            }.await
+        };
+        if let Err(e) = result {
+            // a failed cell may have left its metadoc other than the cache says, or rolled it back
+            let center = extractor.center(latidx, lonidx).map(|(lon_val, lat_val)| format_id(id_precision, &[lon_val, lat_val]));
+            if let Ok(metaid) = &center {
+                run.meta_cache.lock().unwrap().remove(metaid);
+            }
+            // with --continue-on-error a cell's failure is logged and counted, and the run goes on to the next cell
+            if !args.continue_on_error {
+                return Err(e);
+            }
+            let cell = match center {
+                Ok(metaid) => metaid,
+                Err(_) => format!("at [{}, {}]", latidx.0, lonidx.0)
+            };
+            eprintln!("error: {} cell {}: {}", filename, cell, e);
+            summary.cells_failed += 1;
+            failed_cells.insert((latidx, lonidx));
+        }
+    }
+
+    summary.elapsed_seconds = started.elapsed().as_secs_f64();
+    summary!("{}: {}", filename, summary);
+    Ok(summary)
+}
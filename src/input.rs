@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::ops::Deref;
+use std::path::PathBuf;
+#[cfg(any(feature = "gzip", feature = "remote"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// netcdf only reads from a real file on disk, so compressed and remote inputs are copied to a
+// temporary file first and read from there; plain local paths are opened directly
+pub struct InputFile {
+    file: netcdf::File,
+    // temporary copy to remove once the file is closed, if one was made
+    temp: Option<PathBuf>
+}
+
+impl Deref for InputFile {
+    type Target = netcdf::File;
+
+    fn deref(&self) -> &netcdf::File {
+        &self.file
+    }
+}
+
+impl Drop for InputFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.temp {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://") || path.starts_with("s3://")
+}
+
+pub fn is_gzip(path: &str) -> bool {
+    path.ends_with(".gz")
+}
+
+pub fn open(path: &str) -> Result<InputFile, Box<dyn Error>> {
+    // a local path, a .gz of one with the gzip feature, or an http(s):// or s3:// URL with the remote feature
+    if is_remote(path) {
+        return open_remote(path);
+    }
+    if is_gzip(path) {
+        return open_gzip(path);
+    }
+    Ok(InputFile{file: netcdf::open(path)?, temp: None})
+}
+
+#[cfg(any(feature = "gzip", feature = "remote"))]
+fn temp_path() -> PathBuf {
+    // unique per process and per input, since several files can be open at once
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!("bsose-sync-{}-{}.nc", std::process::id(), n))
+}
+
+#[cfg(any(feature = "gzip", feature = "remote"))]
+fn open_temp(temp: PathBuf) -> Result<InputFile, Box<dyn Error>> {
+    // the temporary file is removed on failure too
+    match netcdf::open(&temp) {
+        Ok(file) => Ok(InputFile{file: file, temp: Some(temp)}),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(e.into())
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+fn open_gzip(path: &str) -> Result<InputFile, Box<dyn Error>> {
+    gunzip(std::path::Path::new(path), path)
+}
+
+#[cfg(not(feature = "gzip"))]
+fn open_gzip(path: &str) -> Result<InputFile, Box<dyn Error>> {
+    Err(format!("{} is gzip compressed; rebuild with --features gzip to read it", path).into())
+}
+
+#[cfg(feature = "gzip")]
+fn gunzip(local: &std::path::Path, name: &str) -> Result<InputFile, Box<dyn Error>> {
+    // name is what the user gave, for messages, when local is itself a download
+    let compressed = std::fs::File::open(local).map_err(|e| format!("could not open {}: {}", name, e))?;
+    let mut decoder = flate2::read::MultiGzDecoder::new(std::io::BufReader::new(compressed));
+    let temp = temp_path();
+    let copied = std::fs::File::create(&temp).and_then(|mut out| std::io::copy(&mut decoder, &mut out));
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("could not decompress {}: {}", name, e).into());
+    }
+    open_temp(temp)
+}
+
+#[cfg(feature = "remote")]
+fn url(path: &str) -> String {
+    // s3://bucket/key is fetched from the bucket's public HTTPS endpoint; private buckets aren't supported
+    match path.strip_prefix("s3://") {
+        Some(rest) => match rest.split_once('/') {
+            Some((bucket, key)) => format!("https://{}.s3.amazonaws.com/{}", bucket, key),
+            None => format!("https://{}.s3.amazonaws.com/", rest)
+        },
+        None => path.to_string()
+    }
+}
+
+#[cfg(feature = "remote")]
+fn open_remote(path: &str) -> Result<InputFile, Box<dyn Error>> {
+    let response = ureq::get(&url(path)).call().map_err(|e| format!("could not download {}: {}", path, e))?;
+    let mut reader = response.into_reader();
+    let temp = temp_path();
+    let copied = std::fs::File::create(&temp).and_then(|mut out| std::io::copy(&mut reader, &mut out));
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(&temp);
+        return Err(format!("could not download {}: {}", path, e).into());
+    }
+    if !is_gzip(path) {
+        return open_temp(temp);
+    }
+    // the compressed download is only needed until it's decompressed
+    #[cfg(feature = "gzip")]
+    let opened = gunzip(&temp, path);
+    #[cfg(not(feature = "gzip"))]
+    let opened = open_gzip(path);
+    let _ = std::fs::remove_file(&temp);
+    opened
+}
+
+#[cfg(not(feature = "remote"))]
+fn open_remote(path: &str) -> Result<InputFile, Box<dyn Error>> {
+    Err(format!("{} is a URL; rebuild with --features remote to read it", path).into())
+}
@@ -0,0 +1,1877 @@
+use std::error::Error;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use chrono::Utc;
+use chrono::TimeZone;
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::NaiveTime;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use crate::error::SyncError;
+
+pub mod error;
+pub mod cache;
+pub mod basin;
+pub mod varmap;
+pub mod sink;
+pub mod input;
+pub mod metrics;
+pub mod schema;
+pub mod log;
+pub mod selftest;
+pub mod diff;
+pub mod args;
+pub mod ingest;
+
+use varmap::VarMap;
+
+// cell_z_size and reference_density_profile are depth-indexed and have no meaning for surface fields
+pub const SURFACE_SENTINEL: f64 = -9999.0;
+
+// MongoDB's limit on the size of a stored BSON document
+pub const MAX_DOCUMENT_BYTES: usize = 16 * 1024 * 1024;
+
+// Rust structs to describe documents in the "bsose" collections
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sourcedoc {
+    pub source: Vec<String>,
+    pub iter: String,
+    pub file: String,
+    // the file's global attributes named by --global-attributes; absent on documents from older runs
+    #[serde(default)]
+    pub attributes: BTreeMap<String, String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BsoseMetadoc {
+    pub _id: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub data_type: String,
+    pub date_updated_argovis: DateTime,
+    pub timeseries: Vec<DateTime>,
+    pub source: Vec<Sourcedoc>,
+    pub cell_area: f64,
+    pub ocean_depth: f64,
+    pub depth_r0_to_bottom: f64,
+    pub interior_2d_mask: bool,
+    pub depth_r0_to_ref_surface: f64,
+    // ids of this cell's data documents stored as time-range chunks, <id>_c0, <id>_c1, ... rather than under <id>
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunked: Vec<String>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BsoseDocument {
+    pub _id: String,
+    pub metadata: Vec<String>,
+    pub basin: i32,
+    pub geolocation: Geolocation,
+    pub level: f64,
+    pub data: Vec<Vec<f64>>,
+    pub data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>),
+    pub cell_vertical_fraction: f64,
+    // written under the corrected key; documents stored before it have the misspelled one, which still loads,
+    // until their next merge rewrites them or --fix-mask-key renames it in place
+    #[serde(alias = "sea_binary_mask_at_t_locaiton")]
+    pub sea_binary_mask_at_t_location: bool,
+    pub ctrl_vector_3d_mask: bool,
+    pub cell_z_size: f64,
+    pub reference_density_profile: f64,
+    // the metadoc timeseries the data arrays were last written against; absent on documents from older runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<TimelineRef>,
+    // set on one time-range piece of a document too large to store whole
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk: Option<Chunk>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Chunk {
+    // chunk index of count; its data arrays hold the metadoc timesteps from start on
+    pub index: u64,
+    pub count: u64,
+    pub start: u64
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimelineRef {
+    // enough of a timeseries to tell whether a document still lines up with its metadoc
+    pub length: u64,
+    pub first: Option<DateTime>,
+    pub last: Option<DateTime>
+}
+
+impl TimelineRef {
+    pub fn of(timeseries: &[DateTime]) -> TimelineRef {
+        TimelineRef{
+            length: timeseries.len() as u64,
+            first: timeseries.first().copied(),
+            last: timeseries.last().copied()
+        }
+    }
+}
+
+impl fmt::Display for TimelineRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => write!(f, "{} timesteps from {} to {}", self.length, first, last),
+            _ => write!(f, "an empty timeseries")
+        }
+    }
+}
+
+// a cell whose data documents a run finished writing, in the "ingest_progress" collection. Workers sharing a
+// --run-id record cells there as they go, and --resume skips the cells already recorded for a file and variable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CellRecord {
+    // run_id/file/variable/cell, so recording a cell again replaces its record
+    pub _id: String,
+    pub run_id: String,
+    // file name without its directory, as in Sourcedoc
+    pub file: String,
+    pub variable: String,
+    // the cell's metadata document id
+    pub cell: String,
+    pub completed: DateTime
+}
+
+impl CellRecord {
+    pub fn new(run_id: &str, file: &str, variable: &str, cell: &str) -> CellRecord {
+        CellRecord{
+            _id: format!("{}/{}/{}/{}", run_id, file, variable, cell),
+            run_id: run_id.to_string(),
+            file: file.to_string(),
+            variable: variable.to_string(),
+            cell: cell.to_string(),
+            completed: DateTime::now()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Geolocation {
+    // GeoJSON: "Point" at the cell center, or "Polygon" over the cell's bounds with --geolocation polygon
+    #[serde(rename = "type")]
+    pub location_type: String,
+    pub coordinates: Coordinates,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Coordinates {
+    // [longitude, latitude]
+    Point([f64; 2]),
+    // one closed ring of [longitude, latitude] corners, counterclockwise as GeoJSON asks of an exterior ring
+    Polygon(Vec<Vec<[f64; 2]>>)
+}
+
+pub fn cell_edges(centers: &[f64], wrap: bool) -> Vec<f64> {
+    // the n + 1 bounds of the cells around n ascending centers, half way between neighbours; the outer bounds
+    // are half the end spacing out, or on a ring half way across the gap between the last center and the first
+    let n = centers.len();
+    if n < 2 {
+        return Vec::new();
+    }
+    let mut edges = Vec::with_capacity(n + 1);
+    edges.push(if wrap { (centers[n - 1] - 360.0 + centers[0]) / 2.0 } else { centers[0] - (centers[1] - centers[0]) / 2.0 });
+    edges.extend(centers.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0));
+    edges.push(if wrap { edges[0] + 360.0 } else { centers[n - 1] + (centers[n - 1] - centers[n - 2]) / 2.0 });
+    edges
+}
+
+pub fn cell_polygon(west: f64, east: f64, south: f64, north: f64, convention: LonConvention) -> Coordinates {
+    // corners from the south-west, counterclockwise; longitudes in the same range as the centers
+    let (west, east) = (convention.apply(west), convention.apply(east));
+    let (south, north) = (south.max(-90.0), north.min(90.0));
+    Coordinates::Polygon(vec!(vec!([west, south], [east, south], [east, north], [west, north], [west, south])))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Summary {
+    // what a run did, reported at completion
+    pub variables: Vec<String>,
+    #[serde(default)]
+    pub cells: u64,
+    // cells skipped after an error under --continue-on-error
+    #[serde(default)]
+    pub cells_failed: u64,
+    pub metadocs_inserted: u64,
+    pub metadocs_updated: u64,
+    pub data_inserted: u64,
+    pub data_updated: u64,
+    pub data_skipped: u64,
+    pub timesteps_merged: u64,
+    pub elapsed_seconds: f64,
+    // the longest-running cells, slowest first, at most SLOWEST_CELLS of them
+    #[serde(default)]
+    pub slowest_cells: Vec<CellTiming>,
+    // per variable, the values of the data documents inserted or updated
+    #[serde(default)]
+    pub values: BTreeMap<String, ValueStats>
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ValueStats {
+    // counts over a variable's profiles as read from the files, so timesteps a profile is padded with don't count
+    pub values: u64,
+    pub nan: u64,
+    // over the values that aren't NaN; None while there are none
+    pub min: Option<f64>,
+    pub max: Option<f64>
+}
+
+impl ValueStats {
+    pub fn of(profile: &[f64]) -> ValueStats {
+        let mut stats = ValueStats{values: profile.len() as u64, ..Default::default()};
+        for &value in profile {
+            if value.is_nan() {
+                stats.nan += 1;
+            } else {
+                stats.min = Some(stats.min.map_or(value, |m| m.min(value)));
+                stats.max = Some(stats.max.map_or(value, |m| m.max(value)));
+            }
+        }
+        stats
+    }
+
+    pub fn add(&mut self, other: &ValueStats) {
+        self.values += other.values;
+        self.nan += other.nan;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b)
+        };
+    }
+
+    pub fn nan_fraction(&self) -> f64 {
+        if self.values == 0 { 0.0 } else { self.nan as f64 / self.values as f64 }
+    }
+}
+
+impl fmt::Display for ValueStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} values, {:.1}% NaN", self.values, 100.0 * self.nan_fraction())?;
+        match (self.min, self.max) {
+            (Some(min), Some(max)) => write!(f, ", min {}, max {}", min, max),
+            _ => write!(f, ", no values other than NaN")
+        }
+    }
+}
+
+// how many of the slowest cells a Summary keeps
+pub const SLOWEST_CELLS: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CellTiming {
+    // how long one variable's data documents for a cell took to write
+    pub cell: String,
+    pub variable: String,
+    pub seconds: f64,
+    pub documents: u64
+}
+
+impl Summary {
+    pub fn add(&mut self, other: &Summary) {
+        // accumulate another file's summary into a run total
+        for v in &other.variables {
+            if !self.variables.contains(v) {
+                self.variables.push(v.clone());
+            }
+        }
+        self.cells += other.cells;
+        self.cells_failed += other.cells_failed;
+        self.metadocs_inserted += other.metadocs_inserted;
+        self.metadocs_updated += other.metadocs_updated;
+        self.data_inserted += other.data_inserted;
+        self.data_updated += other.data_updated;
+        self.data_skipped += other.data_skipped;
+        self.timesteps_merged += other.timesteps_merged;
+        self.elapsed_seconds += other.elapsed_seconds;
+        for timing in &other.slowest_cells {
+            self.time_cell(timing.clone());
+        }
+        for (variable, stats) in &other.values {
+            self.values.entry(variable.clone()).or_default().add(stats);
+        }
+    }
+
+    pub fn time_cell(&mut self, timing: CellTiming) {
+        // keep timing if it's among the slowest cells seen so far
+        let at = self.slowest_cells.iter().position(|t| t.seconds < timing.seconds).unwrap_or(self.slowest_cells.len());
+        if at < SLOWEST_CELLS {
+            self.slowest_cells.insert(at, timing);
+            self.slowest_cells.truncate(SLOWEST_CELLS);
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "variables: {}", self.variables.join(", "))?;
+        if self.cells_failed > 0 {
+            writeln!(f, "cells: {}, {} failed", self.cells, self.cells_failed)?;
+        } else {
+            writeln!(f, "cells: {}", self.cells)?;
+        }
+        writeln!(f, "metadata documents: {} inserted, {} updated", self.metadocs_inserted, self.metadocs_updated)?;
+        writeln!(f, "data documents: {} inserted, {} updated, {} skipped", self.data_inserted, self.data_updated, self.data_skipped)?;
+        writeln!(f, "timesteps merged: {}", self.timesteps_merged)?;
+        for (variable, stats) in &self.values {
+            writeln!(f, "{}: {}", variable, stats)?;
+        }
+        write!(f, "elapsed: {:.1}s", self.elapsed_seconds)?;
+        for t in &self.slowest_cells {
+            write!(f, "\nslow cell: {} {} took {:.1}s over {} documents", t.cell, t.variable, t.seconds, t.documents)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn tidylon(longitude: f64) -> f64{
+    // map longitude on [0,360] to [-180,180], required for mongo indexing
+    if longitude <= 180.0{
+        return longitude;
+    }
+    else{
+        return longitude-360.0;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LonConvention {
+    // the range longitudes are stored in, formatted into ids and given in geolocation. Pm180 is tidylon's,
+    // which a 2dsphere index needs; Zero360 is [0, 360), as BSOSE's XC, for consumers that expect it, but
+    // a 2dsphere index rejects its geolocation east of 180
+    Pm180,
+    Zero360
+}
+
+impl LonConvention {
+    pub fn parse(name: &str) -> Result<LonConvention, Box<dyn Error>> {
+        match name {
+            "pm180" => Ok(LonConvention::Pm180),
+            "zero360" => Ok(LonConvention::Zero360),
+            _ => Err(SyncError::Args(format!("invalid longitude convention {}, expected pm180 or zero360", name)).into())
+        }
+    }
+
+    pub fn apply(&self, longitude: f64) -> f64 {
+        match self {
+            LonConvention::Pm180 => tidylon(longitude),
+            LonConvention::Zero360 => longitude.rem_euclid(360.0)
+        }
+    }
+
+    pub fn check(&self, id: &str, longitude: f64, latitude: f64) -> Result<(), Box<dyn Error>> {
+        // check_coordinates, with the longitude range of the convention
+        match self {
+            LonConvention::Pm180 => check_coordinates(id, longitude, latitude),
+            LonConvention::Zero360 => {
+                if !(0.0..360.0).contains(&longitude) {
+                    return Err(SyncError::Validation(format!("cell {}: longitude {} is outside [0, 360)", id, longitude)).into());
+                }
+                check_coordinates(id, 0.0, latitude)
+            }
+        }
+    }
+}
+
+pub fn format_id(precision: usize, coords: &[f64]) -> String {
+    // join coordinates into a document id, e.g. lon_lat or lon_lat_level
+    coords.iter().map(|c| format!("{:.*}", precision, c)).collect::<Vec<String>>().join("_")
+}
+
+pub fn check_center_collisions(precision: usize, centers: &[(f64, f64)]) -> Result<(), Box<dyn Error>> {
+    // as check_id_collisions, for a curvilinear grid, where cells are told apart by their lon/lat pair
+    let mut seen = HashMap::new();
+    for (i, &(lon, lat)) in centers.iter().enumerate() {
+        let key = format_id(precision, &[lon, lat]);
+        if let Some(first) = seen.insert(key.clone(), i) {
+            return Err(SyncError::Validation(format!("id collision: cell centers {:?} (cell {}) and {:?} (cell {}) in the requested tile both format as {} at --id-precision {}",
+                centers[first], first, (lon, lat), i, key, precision)).into());
+        }
+    }
+    Ok(())
+}
+
+pub fn check_id_collisions(precision: usize, lons: &[f64], lats: &[f64], levels: &[f64]) -> Result<(), Box<dyn Error>> {
+    // distinct grid values must remain distinct after formatting, or documents silently overwrite each other
+    for (axis, values) in [("longitude", lons), ("latitude", lats), ("level", levels)] {
+        let mut seen = HashMap::new();
+        for (i, v) in values.iter().enumerate() {
+            let key = format_id(precision, &[*v]);
+            if let Some(first) = seen.insert(key.clone(), i) {
+                return Err(SyncError::Validation(format!("id collision: {} values {} (index {}) and {} (index {}) in the requested tile both format as {} at --id-precision {}",
+                    axis, values[first], first, v, i, key, precision)).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// how far, in degrees, a stored metadoc's coordinates may be from the file's for the same _id
+pub const COORDINATE_TOLERANCE: f64 = 1e-6;
+
+pub fn check_meta_coordinates(meta: &BsoseMetadoc, longitude: f64, latitude: f64) -> Result<(), Box<dyn Error>> {
+    // an id only says the coordinates round alike, so a changed grid or --id-precision could reuse another cell's metadoc
+    if (meta.longitude - longitude).abs() > COORDINATE_TOLERANCE || (meta.latitude - latitude).abs() > COORDINATE_TOLERANCE {
+        return Err(SyncError::Validation(format!("metadata document {} is stored at {}, {} but this file's cell is at {}, {}; refusing to merge into another cell",
+            meta._id, meta.longitude, meta.latitude, longitude, latitude)).into());
+    }
+    Ok(())
+}
+
+pub fn check_coordinates(id: &str, longitude: f64, latitude: f64) -> Result<(), Box<dyn Error>> {
+    // 2dsphere indexes reject anything outside these ranges, so catch grid mistakes before writing
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(SyncError::Validation(format!("cell {}: latitude {} is outside [-90, 90]", id, latitude)).into());
+    }
+    if !(-180.0..180.0).contains(&longitude) {
+        return Err(SyncError::Validation(format!("cell {}: longitude {} is outside [-180, 180)", id, longitude)).into());
+    }
+    Ok(())
+}
+
+pub fn nearest_index(coords: &[f64], value: f64, wrap: bool) -> usize {
+    // index of the coordinate closest to value on a monotonic axis, which needn't be evenly spaced; longitudes
+    // compare the short way round the globe. A tie goes to the coordinate below value.
+    let distance = |c: f64| {
+        if wrap {
+            let d = (c - value).rem_euclid(360.0);
+            d.min(360.0 - d)
+        } else {
+            (c - value).abs()
+        }
+    };
+    let n = coords.len();
+    if n < 2 {
+        return 0;
+    }
+    let ascending = coords[0] <= coords[n - 1];
+    // a longitude is brought into the 360 degrees from the start of the axis first
+    let target = if wrap {
+        let start = coords[0].min(coords[n - 1]);
+        start + (value - start).rem_euclid(360.0)
+    } else {
+        value
+    };
+    // the neighbours either side of target; on a ring they wrap round past the ends
+    let split = coords.partition_point(|&c| if ascending { c < target } else { c > target });
+    let below = match split {
+        0 if wrap => n - 1,
+        0 => 0,
+        _ => split - 1
+    };
+    let above = if split < n { split } else if wrap { 0 } else { n - 1 };
+    if distance(coords[above]) < distance(coords[below]) { above } else { below }
+}
+
+pub fn containing_index(coords: &[f64], value: f64) -> usize {
+    // index of the cell containing value on an ascending axis, whose cells run between the midpoints of
+    // neighbouring coordinates. A value on a boundary belongs to the cell above it; past either end, to the end cell.
+    let above = coords.partition_point(|&c| c <= value);
+    if above == 0 {
+        return 0;
+    }
+    if above == coords.len() {
+        return above - 1;
+    }
+    if value >= (coords[above - 1] + coords[above]) / 2.0 { above } else { above - 1 }
+}
+
+pub fn degree_range(coords: &[f64], min: Option<f64>, max: Option<f64>, wrap: bool) -> Result<(usize, usize), Box<dyn Error>> {
+    // half-open index range from the grid point nearest min to the one nearest max;
+    // a missing bound runs to that end of the axis
+    let lo = min.map(|m| nearest_index(coords, m, wrap)).unwrap_or(0);
+    let hi = max.map(|m| nearest_index(coords, m, wrap) + 1).unwrap_or(coords.len());
+    if lo >= hi {
+        return Err(SyncError::Args(format!("degree bounds {:?} to {:?} select no grid points between indexes {} and {}; ranges can't wrap past the end of the grid",
+            min, max, lo, hi)).into());
+    }
+    Ok((lo, hi))
+}
+
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    // Fisher-Yates driven by splitmix64, so a seed gives the same order on every machine and release
+    let mut state = seed;
+    for i in (1..items.len()).rev() {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        items.swap(i, (z % (i as u64 + 1)) as usize);
+    }
+}
+
+pub fn global_attributes(file: &netcdf::File, names: &[String]) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    // the named global attributes a file has, as text; names it doesn't have are left out
+    let mut attributes = BTreeMap::new();
+    for name in names {
+        let value = match file.attribute(name) {
+            Some(attr) => attr.value()?,
+            None => continue
+        };
+        let text = match value {
+            netcdf::AttrValue::Str(v) => v,
+            netcdf::AttrValue::Double(v) => v.to_string(),
+            netcdf::AttrValue::Float(v) => v.to_string(),
+            netcdf::AttrValue::Int(v) => v.to_string(),
+            netcdf::AttrValue::Short(v) => v.to_string(),
+            netcdf::AttrValue::Longlong(v) => v.to_string(),
+            other => format!("{:?}", other)
+        };
+        attributes.insert(name.clone(), text);
+    }
+    Ok(attributes)
+}
+
+pub fn is_surface_variable(name: &str, dims: &[String]) -> Result<bool, Box<dyn Error>> {
+    // data variables are either [time, level, lat, lon] or surface fields [time, lat, lon]
+    match dims.len() {
+        4 => Ok(false),
+        3 => Ok(true),
+        n => Err(SyncError::Validation(format!("data variable {} has {} dimensions [{}]; expected [time, level, lat, lon] or [time, lat, lon]", name, n, dims.join(", "))).into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Staggering {
+    // where the depth variable's levels sit on MITgcm's vertical grid: Center at cell centers (Z), Upper on
+    // the top face of each cell (Zl, the w-points) and Lower on the bottom face (Zu)
+    Center,
+    Upper,
+    Lower
+}
+
+impl Staggering {
+    pub fn of(name: &str, shift: Option<f64>) -> Staggering {
+        // MITgcm marks face coordinates with a c_grid_axis_shift of -0.5 or 0.5; without one the name decides
+        match shift {
+            Some(s) if s < 0.0 => Staggering::Upper,
+            Some(s) if s > 0.0 => Staggering::Lower,
+            Some(_) => Staggering::Center,
+            None => match name {
+                "Zl" => Staggering::Upper,
+                "Zu" => Staggering::Lower,
+                _ => Staggering::Center
+            }
+        }
+    }
+}
+
+pub fn z_sign(positive: Option<&str>) -> Result<f64, Box<dyn Error>> {
+    // factor turning a vertical coordinate into height, negative below the surface, from its CF
+    // positive attribute; MITgcm's Z is positive up, which is also assumed when the attribute is missing
+    match positive.map(|p| p.to_lowercase()) {
+        None => Ok(1.0),
+        Some(p) if p == "up" => Ok(1.0),
+        Some(p) if p == "down" => Ok(-1.0),
+        Some(p) => Err(SyncError::Validation(format!("unsupported vertical coordinate positive attribute '{}', expected up or down", p)).into())
+    }
+}
+
+pub fn time_unit_seconds(units: &str) -> Result<f64, Box<dyn Error>> {
+    // length in seconds of the unit in a CF-style "<unit> since <date>" string
+    match units.split_whitespace().next().unwrap_or("") {
+        "seconds" | "second" | "secs" | "sec" | "s" => Ok(1.0),
+        "minutes" | "minute" | "mins" | "min" => Ok(60.0),
+        "hours" | "hour" | "hrs" | "hr" | "h" => Ok(3600.0),
+        "days" | "day" | "d" => Ok(86400.0),
+        _ => Err(SyncError::Validation(format!("unsupported time units '{}', expected seconds, minutes, hours or days", units)).into())
+    }
+}
+
+// units assumed for a time variable without any, as in the BSOSE files this tool was first written for
+pub const DEFAULT_TIME_UNITS: &str = "seconds since 2012-12-01";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CfTime {
+    // a CF "<unit> since <date>" time encoding on the standard or proleptic Gregorian calendar
+    pub unit_seconds: f64,
+    pub origin: chrono::DateTime<Utc>
+}
+
+impl CfTime {
+    pub fn parse(units: &str, calendar: Option<&str>) -> Result<CfTime, Box<dyn Error>> {
+        // other CF calendars (noleap, 360_day, julian, ...) count days that don't line up with real dates
+        let calendar = calendar.map(|c| c.trim().to_lowercase());
+        let proleptic = match calendar.as_deref() {
+            None | Some("") | Some("standard") | Some("gregorian") => false,
+            Some("proleptic_gregorian") => true,
+            Some(c) => return Err(SyncError::Validation(format!("unsupported calendar '{}': only standard, gregorian and proleptic_gregorian times can be stored as dates", c)).into())
+        };
+        let (_, reference) = units.split_once(" since ").ok_or_else(|| format!("invalid time units '{}', expected '<unit> since <date>'", units))?;
+        let unit_seconds = time_unit_seconds(units)?;
+        let origin = cf_reference_date(reference.trim()).ok_or_else(|| format!("invalid reference date '{}' in time units '{}'", reference.trim(), units))?;
+        // the standard calendar is Julian before the Gregorian reform, which DateTime can't count in
+        if !proleptic && origin < Utc.with_ymd_and_hms(1582, 10, 15, 0, 0, 0).unwrap() {
+            return Err(SyncError::Validation(format!("time units '{}' start before 1582-10-15 on the mixed Julian/Gregorian standard calendar", units)).into());
+        }
+        Ok(CfTime{unit_seconds: unit_seconds, origin: origin})
+    }
+
+    pub fn decode(&self, value: f64) -> chrono::DateTime<Utc> {
+        // rounded to the nearest second
+        self.origin + Duration::seconds((value * self.unit_seconds).round() as i64)
+    }
+}
+
+fn cf_reference_date(text: &str) -> Option<chrono::DateTime<Utc>> {
+    // YYYY-MM-DD, optionally followed by a space or T and a time of day, in UTC
+    let text = text.strip_suffix("UTC").or(text.strip_suffix('Z')).unwrap_or(text).trim();
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((date, time)) => (date, time.trim()),
+        None => (text, "")
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    // a zero offset may follow the time, as in 00:00:00+00:00 or 00:00:00 0:00
+    let time = match time.split_once(' ') {
+        Some((time, zone)) if ["+00:00", "+0000", "00:00", "0:00", "0"].contains(&zone.trim()) => time,
+        Some(_) => return None,
+        None => time.strip_suffix("+00:00").unwrap_or(time)
+    };
+    let time = if time.is_empty() {
+        NaiveTime::MIN
+    } else {
+        ["%H:%M:%S%.f", "%H:%M:%S", "%H:%M"].iter().find_map(|f| NaiveTime::parse_from_str(time, f).ok())?
+    };
+    Some(Utc.from_utc_datetime(&date.and_time(time)))
+}
+
+fn string_attribute(var: &netcdf::Variable, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    // a text attribute, or None if the variable has no such attribute or it isn't text
+    match var.attribute_value(name) {
+        Some(Ok(netcdf::AttrValue::Str(s))) => Ok(Some(s)),
+        Some(Ok(_)) | None => Ok(None),
+        Some(Err(e)) => Err(SyncError::Netcdf(format!("{}:{}: {}", var.name(), name, e)).into())
+    }
+}
+
+pub fn cf_times(time: &netcdf::Variable) -> Result<Vec<chrono::DateTime<Utc>>, Box<dyn Error>> {
+    // a time variable's values per its units and calendar attributes; values may be integer or fractional
+    let units = match time.attribute_value("units") {
+        Some(Ok(netcdf::AttrValue::Str(u))) => u,
+        _ => String::from(DEFAULT_TIME_UNITS)
+    };
+    let calendar = match time.attribute_value("calendar") {
+        Some(Ok(netcdf::AttrValue::Str(c))) => Some(c),
+        _ => None
+    };
+    let encoding = CfTime::parse(&units, calendar.as_deref()).map_err(|e| SyncError::Validation(format!("{}: {}", time.name(), e)))?;
+    let mut times = Vec::new();
+    for timeidx in 0..time.len() {
+        times.push(encoding.decode(time.value::<f64, _>(timeidx)?));
+    }
+    Ok(times)
+}
+
+// placeholders --data-collection and --meta-collection may hold to partition the documents: {iter} is the
+// run's --iter, and {year} the calendar year (UTC) of the timesteps, each year's getting their own metadocs
+// and data documents. A cell keeps its metadoc id in every partition, so its whole timeseries is read by
+// querying each year's collections in turn, or at once with a $unionWith aggregation, and concatenating the
+// timeseries and data of its documents in year order.
+pub const YEAR_PLACEHOLDER: &str = "{year}";
+pub const ITER_PLACEHOLDER: &str = "{iter}";
+
+pub fn check_collection_templates(data: &str, meta: &str) -> Result<(), Box<dyn Error>> {
+    // a data document indexes into its metadoc's timeseries, so both have to be partitioned the same way
+    for name in [data, meta] {
+        let rest = name.replace(YEAR_PLACEHOLDER, "").replace(ITER_PLACEHOLDER, "");
+        if rest.contains(['{', '}']) {
+            return Err(SyncError::Args(format!("collection name {} has a placeholder other than {} and {}", name, YEAR_PLACEHOLDER, ITER_PLACEHOLDER)).into());
+        }
+    }
+    for placeholder in [YEAR_PLACEHOLDER, ITER_PLACEHOLDER] {
+        if data.contains(placeholder) != meta.contains(placeholder) {
+            return Err(SyncError::Args(format!("data collection {} and metadata collection {} must both contain {}, or neither, so a cell's documents are partitioned together",
+                data, meta, placeholder)).into());
+        }
+    }
+    Ok(())
+}
+
+pub fn collection_name(template: &str, year: Option<i32>, iter: &str) -> String {
+    // a collection template with its placeholders filled in; {year} stays as it is without a year
+    let name = template.replace(ITER_PLACEHOLDER, iter);
+    match year {
+        Some(year) => name.replace(YEAR_PLACEHOLDER, &year.to_string()),
+        None => name
+    }
+}
+
+pub fn partition_by_year(timeseries: &[DateTime]) -> BTreeMap<i32, Vec<usize>> {
+    // indexes of the timesteps in each calendar year, UTC
+    use chrono::Datelike;
+    let mut years: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+    for (i, t) in timeseries.iter().enumerate() {
+        years.entry(t.to_chrono().year()).or_default().push(i);
+    }
+    years
+}
+
+pub fn check_unique_times(timeseries: &[DateTime]) -> Result<(), Box<dyn Error>> {
+    // a malformed file repeating a timestamp would otherwise put two values in one timestep's slot
+    let mut seen = HashSet::new();
+    for (i, t) in timeseries.iter().enumerate() {
+        if !seen.insert(t.timestamp_millis()) {
+            return Err(SyncError::Validation(format!("time index {} repeats timestamp {}; refusing to ingest a file with duplicate times", i, t)).into());
+        }
+    }
+    Ok(())
+}
+
+pub fn valid_fraction(profile: &[f64]) -> f64 {
+    // share of a profile's timesteps holding a value; an empty profile has none
+    if profile.is_empty() {
+        return 0.0;
+    }
+    profile.iter().filter(|x| !x.is_nan()).count() as f64 / profile.len() as f64
+}
+
+pub fn first_occurrences(timeseries: &[DateTime]) -> Vec<usize> {
+    // indexes of a stored timeseries with repeated timestamps dropped, keeping the first of each
+    let mut seen = HashSet::new();
+    timeseries.iter().enumerate().filter(|(_, t)| seen.insert(t.timestamp_millis())).map(|(i, _)| i).collect()
+}
+
+pub fn parse_date(date: &str) -> Result<DateTime, Box<dyn Error>> {
+    // accepts RFC 3339 timestamps or plain YYYY-MM-DD dates, taken as midnight UTC
+    if let Ok(d) = chrono::DateTime::parse_from_rfc3339(date) {
+        return Ok(DateTime::from_chrono(d.with_timezone(&Utc)));
+    }
+    let d = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| SyncError::Args(format!("invalid date {}: {}", date, e)))?;
+    Ok(DateTime::from_chrono(Utc.from_utc_datetime(&d.and_hms_opt(0, 0, 0).unwrap())))
+}
+
+pub fn parse_duration(text: &str) -> Result<std::time::Duration, Box<dyn Error>> {
+    // whole numbers of d, h, m or s, optionally combined as in 1h30m
+    let invalid = || SyncError::Args(format!("invalid duration {}, expected something like 90s, 30m, 2h or 1h30m", text));
+    let mut seconds = 0u64;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid().into())
+        };
+        let n = digits.parse::<u64>().map_err(|_| invalid())?;
+        seconds = n.checked_mul(unit).and_then(|s| seconds.checked_add(s)).ok_or_else(invalid)?;
+        digits.clear();
+    }
+    if text.is_empty() || !digits.is_empty() {
+        return Err(invalid().into());
+    }
+    Ok(std::time::Duration::from_secs(seconds))
+}
+
+pub fn times_outside(timeseries: &[DateTime], earliest: DateTime, latest: DateTime) -> Vec<usize> {
+    // indexes of timestamps outside [earliest, latest]
+    timeseries.iter().enumerate().filter(|(_, &t)| t < earliest || t > latest).map(|(i, _)| i).collect()
+}
+
+pub fn remove_variable(doc: &mut BsoseDocument, variable: &str) -> bool {
+    // drop a variable's column from data and data_info, keeping the remaining columns aligned
+    match doc.data_info.0.iter().position(|v| v == variable) {
+        Some(idx) => {
+            doc.data.remove(idx);
+            doc.data_info.0.remove(idx);
+            doc.data_info.2.remove(idx);
+            true
+        }
+        None => false
+    }
+}
+
+pub fn qc_column(variable: &str) -> String {
+    // the data_info name of the quality flags stored alongside a variable with --qc-var
+    format!("{}_qc", variable)
+}
+
+pub fn timesteps_since(timeseries: &[DateTime], cutoff: DateTime) -> Vec<usize> {
+    // indexes of the timesteps at or after cutoff
+    timeseries.iter().enumerate().filter(|(_, t)| **t >= cutoff).map(|(i, _)| i).collect()
+}
+
+pub fn populated_timesteps(docs: &[BsoseDocument], n_timesteps: usize) -> Vec<usize> {
+    // timestep indexes where at least one variable of one of a cell's data documents has a value
+    (0..n_timesteps).filter(|&i| docs.iter().any(|doc| doc.data.iter().any(|d| d.get(i).is_some_and(|x| !x.is_nan())))).collect()
+}
+
+pub fn select<T: Clone>(values: &[T], indexes: &[usize]) -> Vec<T> {
+    indexes.iter().map(|&i| values[i].clone()).collect()
+}
+
+pub fn extend_timeline(timeline: &[DateTime], times: &[DateTime]) -> Result<Vec<DateTime>, Box<dyn Error>> {
+    // a stored timeseries with any new times appended in order, whatever order the file lists them in;
+    // stored data can only grow at its end, so a new time before the last stored one is refused.
+    // placements() then maps each of times to its index in the result
+    let mut new: Vec<DateTime> = times.iter().filter(|t| !timeline.contains(t)).copied().collect();
+    new.sort();
+    new.dedup();
+    if let (Some(first), Some(last)) = (new.first(), timeline.last()) {
+        if first < last {
+            return Err(SyncError::Validation(format!("timestep {} falls inside the stored timeseries but isn't part of it", first)).into());
+        }
+    }
+    let mut extended = timeline.to_vec();
+    extended.extend(new);
+    Ok(extended)
+}
+
+pub fn placements(timeline: &[DateTime], times: &[DateTime]) -> Vec<(usize, usize)> {
+    // (timeline index, index in times) for each of times found in the timeline, in timeline order. Neither
+    // needs to be sorted: a value read at times[source] belongs at timeline[merged], whatever order they're in
+    let positions: HashMap<DateTime, usize> = timeline.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+    let mut mapping: Vec<(usize, usize)> = times.iter().enumerate()
+        .filter_map(|(source, t)| positions.get(t).map(|&merged| (merged, source)))
+        .collect();
+    mapping.sort();
+    mapping
+}
+
+pub fn covered(timeline: &[DateTime], times: &[DateTime]) -> Vec<usize> {
+    // indexes of a timeline holding one of times, i.e. where a placed profile came from the file
+    placements(timeline, times).into_iter().map(|(merged, _)| merged).collect()
+}
+
+pub fn place(timeline: &[DateTime], times: &[DateTime], profile: &[f64]) -> Vec<f64> {
+    // spread a profile over a longer timeline by timestamp, NaN where it has no value
+    let mut placed = vec!(f64::NAN; timeline.len());
+    for (merged, source) in placements(timeline, times) {
+        if let Some(value) = profile.get(source) {
+            placed[merged] = *value;
+        }
+    }
+    placed
+}
+
+pub fn pad_data(doc: &mut BsoseDocument, n_timesteps: usize) {
+    // stored columns gain NaN for timesteps appended to the timeline since they were written
+    for data in doc.data.iter_mut() {
+        if data.len() < n_timesteps {
+            data.resize(n_timesteps, f64::NAN);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnInconsistent {
+    // what to do with a stored document whose data doesn't match its metadoc's timeseries,
+    // as an interrupted run can leave behind
+    Repair,
+    Skip,
+    Error
+}
+
+impl OnInconsistent {
+    pub fn parse(mode: &str) -> Result<OnInconsistent, Box<dyn Error>> {
+        match mode {
+            "repair" => Ok(OnInconsistent::Repair),
+            "skip" => Ok(OnInconsistent::Skip),
+            "error" => Ok(OnInconsistent::Error),
+            _ => Err(SyncError::Args(format!("invalid inconsistency mode {}, expected repair, skip or error", mode)).into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DryCells {
+    // how a level of a cell is recognised as having no ocean, so no new data document is written for it:
+    // Zeros from the data being all zero, Mask from maskC, VerticalFraction from hFacC, which is zero for
+    // land and for levels below the bathymetry whatever the data holds there
+    Zeros,
+    Mask,
+    VerticalFraction
+}
+
+impl DryCells {
+    pub fn parse(mode: &str) -> Result<DryCells, Box<dyn Error>> {
+        match mode {
+            "zeros" => Ok(DryCells::Zeros),
+            "mask" => Ok(DryCells::Mask),
+            "vertical-fraction" => Ok(DryCells::VerticalFraction),
+            _ => Err(SyncError::Args(format!("invalid dry cell mode {}, expected zeros, mask or vertical-fraction", mode)).into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnBadTime {
+    // what to do with timestamps outside the plausible BSOSE range, which usually mean a bad time origin
+    Warn,
+    Skip,
+    Error
+}
+
+impl OnBadTime {
+    pub fn parse(mode: &str) -> Result<OnBadTime, Box<dyn Error>> {
+        match mode {
+            "warn" => Ok(OnBadTime::Warn),
+            "skip" => Ok(OnBadTime::Skip),
+            "error" => Ok(OnBadTime::Error),
+            _ => Err(SyncError::Args(format!("invalid bad time mode {}, expected warn, skip or error", mode)).into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutOfRange {
+    // what to do with data values outside the variable's valid_range, or valid_min and valid_max
+    Mask,
+    Clamp,
+    Keep
+}
+
+impl OutOfRange {
+    pub fn parse(mode: &str) -> Result<OutOfRange, Box<dyn Error>> {
+        match mode {
+            "mask" => Ok(OutOfRange::Mask),
+            "clamp" => Ok(OutOfRange::Clamp),
+            "keep" => Ok(OutOfRange::Keep),
+            _ => Err(SyncError::Args(format!("invalid out of range mode {}, expected mask, clamp or keep", mode)).into())
+        }
+    }
+}
+
+pub fn apply_valid_range(values: &mut [f64], range: (f64, f64), mode: OutOfRange) -> usize {
+    // applies mode to the values outside range, bounds included, and returns how many there were; NaN is left alone
+    let (min, max) = range;
+    let mut outside = 0;
+    for value in values.iter_mut() {
+        if *value < min || *value > max {
+            outside += 1;
+            match mode {
+                OutOfRange::Mask => *value = f64::NAN,
+                OutOfRange::Clamp => *value = value.clamp(min, max),
+                OutOfRange::Keep => {}
+            }
+        }
+    }
+    outside
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reconciled {
+    Consistent,
+    // the warning to log
+    Repaired(String),
+    Skipped(String)
+}
+
+pub fn reconcile(doc: &mut BsoseDocument, n_timesteps: usize, mode: OnInconsistent) -> Result<Reconciled, Box<dyn Error>> {
+    // pre-merge check of a stored document against its metadoc's timeseries length
+    let mismatched: Vec<String> = doc.data.iter().zip(doc.data_info.0.iter())
+        .filter(|(data, _)| data.len() != n_timesteps)
+        .map(|(data, variable)| format!("{} has {} values", variable, data.len()))
+        .collect();
+    if mismatched.is_empty() {
+        return Ok(Reconciled::Consistent);
+    }
+    let message = format!("document {}: {} but the timeseries has {} timesteps", doc._id, mismatched.join(", "), n_timesteps);
+    match mode {
+        OnInconsistent::Repair => {
+            // truncate or NaN-pad every column to the timeseries
+            for data in doc.data.iter_mut() {
+                data.resize(n_timesteps, f64::NAN);
+            }
+            Ok(Reconciled::Repaired(message))
+        }
+        OnInconsistent::Skip => Ok(Reconciled::Skipped(message)),
+        OnInconsistent::Error => Err(SyncError::Validation(message).into())
+    }
+}
+
+pub fn check_data_lengths(doc: &BsoseDocument, n_timesteps: usize) -> Result<(), Box<dyn Error>> {
+    // every data array must line up with its metadoc's timeseries
+    for (i, data) in doc.data.iter().enumerate() {
+        if data.len() != n_timesteps {
+            let variable = doc.data_info.0.get(i).cloned().unwrap_or_default();
+            return Err(SyncError::Validation(format!("document {}: data for {} has {} values but the timeseries has {} timesteps", doc._id, variable, data.len(), n_timesteps)).into());
+        }
+    }
+    Ok(())
+}
+
+pub fn set_metadata(doc: &mut BsoseDocument, metaid: &str) -> Option<String> {
+    // a stored document's reference to its metadoc, reset to exactly metaid; returns what was stored instead,
+    // duplicated or stale, if anything
+    if doc.metadata.len() == 1 && doc.metadata[0] == metaid {
+        return None;
+    }
+    let stored = std::mem::replace(&mut doc.metadata, vec![metaid.to_string()]);
+    Some(format!("document {}: metadata was {:?}, not [{:?}]", doc._id, stored, metaid))
+}
+
+pub fn chunk_id(id: &str, index: usize) -> String {
+    format!("{}_c{}", id, index)
+}
+
+pub fn document_size(doc: &BsoseDocument) -> Result<usize, Box<dyn Error>> {
+    // bytes as stored in MongoDB
+    Ok(mongodb::bson::to_vec(doc)?.len())
+}
+
+pub fn split_document(doc: &BsoseDocument, max_bytes: usize) -> Result<Vec<BsoseDocument>, Box<dyn Error>> {
+    // equal time ranges of a document, in as few chunks as keep each within max_bytes; a document that fits
+    // comes back whole. Every variable is split at the same timesteps.
+    let size = document_size(doc)?;
+    if size <= max_bytes {
+        return Ok(vec!(doc.clone()));
+    }
+    let n_timesteps = doc.data.first().map(|d| d.len()).unwrap_or(0);
+    let template = BsoseDocument{data: Vec::new(), ..doc.clone()};
+    let chunk = |index: usize, length: usize| -> BsoseDocument {
+        let start = index * length;
+        let end = (start + length).min(n_timesteps);
+        BsoseDocument{
+            _id: chunk_id(&doc._id, index),
+            data: doc.data.iter().map(|d| d[start..end].to_vec()).collect(),
+            chunk: Some(Chunk{index: index as u64, count: n_timesteps.div_ceil(length) as u64, start: start as u64}),
+            ..template.clone()
+        }
+    };
+    // the whole document's size over the limit is close to the count needed, and never far above it
+    let mut count = (size / max_bytes).max(2);
+    while count <= n_timesteps {
+        let length = n_timesteps.div_ceil(count);
+        let chunks: Vec<BsoseDocument> = (0..n_timesteps.div_ceil(length)).map(|index| chunk(index, length)).collect();
+        let mut fits = true;
+        for c in &chunks {
+            fits = fits && document_size(c)? <= max_bytes;
+        }
+        if fits {
+            return Ok(chunks);
+        }
+        if document_size(&chunk(0, 1))? > max_bytes {
+            // a single timestep doesn't fit, so no count of chunks will
+            break;
+        }
+        count += 1;
+    }
+    Err(SyncError::Validation(format!("document {} is over {} bytes even with one timestep per chunk", doc._id, max_bytes)).into())
+}
+
+pub fn check_timeline(doc: &BsoseDocument, timeline: &[DateTime], stored_len: usize) -> Result<(), Box<dyn Error>> {
+    // a document's recorded timeseries must be its metadoc's from before this run, or from after it when
+    // another variable of the run already extended the document
+    let before = TimelineRef::of(&timeline[..stored_len.min(timeline.len())]);
+    match &doc.timeline {
+        Some(written) if *written != before && *written != TimelineRef::of(timeline) =>
+            Err(SyncError::Validation(format!("document {} was written against {} but its metadoc has {}; refusing to misalign it", doc._id, written, before)).into()),
+        _ => Ok(())
+    }
+}
+
+pub fn validate_document(doc: &BsoseDocument, meta: Option<&BsoseMetadoc>, id_precision: usize) -> Vec<String> {
+    // invariants every stored data document should satisfy; returns one message per violation
+    let mut violations = Vec::new();
+    let metaid = doc.metadata.first().cloned().unwrap_or_default();
+    match meta {
+        Some(meta) => {
+            // a chunk holds a range of the timeseries, from its start on
+            let (start, expected) = match &doc.chunk {
+                Some(chunk) => (chunk.start as usize, doc.data.first().map(|d| d.len()).unwrap_or(0)),
+                None => (0, meta.timeseries.len())
+            };
+            if start + expected > meta.timeseries.len() {
+                violations.push(format!("document {}: chunk from timestep {} runs past the {} timesteps of its metadoc", doc._id, start, meta.timeseries.len()));
+            } else if let Err(e) = check_data_lengths(doc, expected) {
+                violations.push(e.to_string());
+            }
+            if let Err(e) = check_timeline(doc, &meta.timeseries, meta.timeseries.len()) {
+                violations.push(e.to_string());
+            }
+        }
+        None => violations.push(format!("document {}: metadata {} does not reference an existing metadata document", doc._id, metaid))
+    }
+    if doc.metadata.len() != 1 {
+        violations.push(format!("document {}: metadata {:?} should hold exactly one metadata id", doc._id, doc.metadata));
+    }
+    if doc.data.len() != doc.data_info.0.len() || doc.data.len() != doc.data_info.2.len() {
+        violations.push(format!("document {}: {} data arrays but {} variable names and {} variable attribute lists", doc._id, doc.data.len(), doc.data_info.0.len(), doc.data_info.2.len()));
+    }
+    match &doc.geolocation.coordinates {
+        Coordinates::Point(point) => {
+            let location = format_id(id_precision, point);
+            if !doc._id.starts_with(&format!("{}_", location)) {
+                violations.push(format!("document {}: geolocation {:?} doesn't match the id", doc._id, point));
+            }
+            if metaid != location {
+                violations.push(format!("document {}: metadata {} doesn't match geolocation {:?}", doc._id, metaid, point));
+            }
+        }
+        Coordinates::Polygon(rings) => {
+            // a polygon's corners don't give the center back, so only its shape and the id are checked
+            if rings.len() != 1 || rings[0].len() != 5 || rings[0].first() != rings[0].last() {
+                violations.push(format!("document {}: geolocation polygon {:?} isn't a single closed ring of four corners", doc._id, rings));
+            }
+            if !doc._id.starts_with(&format!("{}_", metaid)) {
+                violations.push(format!("document {}: metadata {} doesn't match the id", doc._id, metaid));
+            }
+        }
+    }
+    violations
+}
+
+fn fill_value(var: &netcdf::Variable) -> Option<f64> {
+    // a variable's _FillValue, if it declares one
+    match var.attribute_value("_FillValue") {
+        Some(Ok(netcdf::AttrValue::Double(v))) => Some(v),
+        Some(Ok(netcdf::AttrValue::Float(v))) => Some(v as f64),
+        Some(Ok(netcdf::AttrValue::Int(v))) => Some(v as f64),
+        Some(Ok(netcdf::AttrValue::Short(v))) => Some(v as f64),
+        _ => None
+    }
+}
+
+fn attribute_numbers(var: &netcdf::Variable, name: &str) -> Option<Vec<f64>> {
+    // a numeric attribute as doubles, whether it holds one value or several
+    match var.attribute_value(name) {
+        Some(Ok(netcdf::AttrValue::Double(v))) => Some(vec!(v)),
+        Some(Ok(netcdf::AttrValue::Float(v))) => Some(vec!(v as f64)),
+        Some(Ok(netcdf::AttrValue::Int(v))) => Some(vec!(v as f64)),
+        Some(Ok(netcdf::AttrValue::Short(v))) => Some(vec!(v as f64)),
+        Some(Ok(netcdf::AttrValue::Doubles(v))) => Some(v),
+        Some(Ok(netcdf::AttrValue::Floats(v))) => Some(v.into_iter().map(|x| x as f64).collect()),
+        Some(Ok(netcdf::AttrValue::Ints(v))) => Some(v.into_iter().map(|x| x as f64).collect()),
+        Some(Ok(netcdf::AttrValue::Shorts(v))) => Some(v.into_iter().map(|x| x as f64).collect()),
+        _ => None
+    }
+}
+
+fn valid_range(var: &netcdf::Variable) -> Result<Option<(f64, f64)>, Box<dyn Error>> {
+    // CF's valid_range, or valid_min and valid_max of which either may be missing
+    if let Some(range) = attribute_numbers(var, "valid_range") {
+        if range.len() != 2 || range[0] > range[1] {
+            return Err(SyncError::Validation(format!("{} has valid_range {:?}, expected a minimum and a maximum", var.name(), range)).into());
+        }
+        return Ok(Some((range[0], range[1])));
+    }
+    let min = attribute_numbers(var, "valid_min").and_then(|v| v.first().copied());
+    let max = attribute_numbers(var, "valid_max").and_then(|v| v.first().copied());
+    if min.is_none() && max.is_none() {
+        return Ok(None);
+    }
+    let range = (min.unwrap_or(f64::NEG_INFINITY), max.unwrap_or(f64::INFINITY));
+    if range.0 > range.1 {
+        return Err(SyncError::Validation(format!("{} has valid_min {} above valid_max {}", var.name(), range.0, range.1)).into());
+    }
+    Ok(Some(range))
+}
+
+// grid positions along each dimension; the NetCDF index arrays are only built by the
+// helpers below, so a lat/lon or level/time swap is a type error rather than a wrong cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LatIdx(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LonIdx(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LevelIdx(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimeIdx(pub usize);
+
+fn cell(lat: LatIdx, lon: LonIdx) -> [usize; 2] {
+    // (YC, XC) variables
+    [lat.0, lon.0]
+}
+
+fn cell_at_level(level: LevelIdx, lat: LatIdx, lon: LonIdx) -> [usize; 3] {
+    // (Z, YC, XC) variables
+    [level.0, lat.0, lon.0]
+}
+
+fn surface_point(time: TimeIdx, lat: LatIdx, lon: LonIdx) -> [usize; 3] {
+    // (time, YC, XC) variables
+    [time.0, lat.0, lon.0]
+}
+
+fn point(time: TimeIdx, level: LevelIdx, lat: LatIdx, lon: LonIdx) -> [usize; 4] {
+    // (time, Z, YC, XC) variables
+    [time.0, level.0, lat.0, lon.0]
+}
+
+fn grid_value(var: &netcdf::Variable, lat: LatIdx, lon: LonIdx, interior: bool) -> Result<f64, Box<dyn Error>> {
+    // a 2D grid value, NaN on land or where the file stores its fill value
+    if !interior {
+        return Ok(f64::NAN);
+    }
+    let value = var.value::<f64, _>(cell(lat, lon))?;
+    if fill_value(var) == Some(value) {
+        return Ok(f64::NAN);
+    }
+    Ok(value)
+}
+
+fn dimension(var: &netcdf::Variable) -> String {
+    // the dimension a coordinate variable runs along
+    var.dimensions().first().map(|d| d.name()).unwrap_or_default()
+}
+
+pub struct CellCenters<'f> {
+    // the lat and lon coordinate variables, either 1D along their own dimensions on a rectilinear grid, or
+    // both 2D over [lat, lon] on a curvilinear one such as MITgcm's, where every cell has its own center
+    lat: netcdf::Variable<'f>,
+    lon: netcdf::Variable<'f>,
+    pub curvilinear: bool
+}
+
+impl<'f> CellCenters<'f> {
+    pub fn new(file: &'f netcdf::File, names: &VarMap) -> Result<CellCenters<'f>, Box<dyn Error>> {
+        let lat = names.variable(file, "lat")?;
+        let lon = names.variable(file, "lon")?;
+        let lat_dims: Vec<String> = lat.dimensions().iter().map(|d| d.name()).collect();
+        let lon_dims: Vec<String> = lon.dimensions().iter().map(|d| d.name()).collect();
+        let curvilinear = match (lat_dims.len(), lon_dims.len()) {
+            (1, 1) if lat_dims != lon_dims => false,
+            (2, 2) if lat_dims == lon_dims && lat_dims[0] != lat_dims[1] => true,
+            _ => return Err(SyncError::Validation(format!("can't tell the grid type from {} [{}] and {} [{}]; expected both 1D along their own dimensions, or both 2D over the same [lat, lon]",
+                lat.name(), lat_dims.join(", "), lon.name(), lon_dims.join(", "))).into())
+        };
+        Ok(CellCenters{lat: lat, lon: lon, curvilinear: curvilinear})
+    }
+
+    pub fn dimensions(&self) -> [String; 2] {
+        // the [lat, lon] dimensions data variables run along
+        let lon = self.lon.dimensions().last().map(|d| d.name()).unwrap_or_default();
+        [dimension(&self.lat), lon]
+    }
+
+    pub fn center(&self, latidx: LatIdx, lonidx: LonIdx) -> Result<(f64, f64), Box<dyn Error>> {
+        // a cell's longitude and latitude as stored in the file, before a LonConvention
+        if self.curvilinear {
+            Ok((self.lon.value::<f64, _>(cell(latidx, lonidx))?, self.lat.value::<f64, _>(cell(latidx, lonidx))?))
+        } else {
+            Ok((self.lon.value::<f64, _>([lonidx.0])?, self.lat.value::<f64, _>([latidx.0])?))
+        }
+    }
+
+    pub fn axes(&self) -> Result<(Vec<f64>, Vec<f64>), Box<dyn Error>> {
+        // every longitude and latitude of a rectilinear grid; a curvilinear grid has no such axes
+        if self.curvilinear {
+            return Err(SyncError::Validation(format!("{} and {} are 2D, a curvilinear grid without longitude and latitude axes", self.lon.name(), self.lat.name())).into());
+        }
+        let read = |var: &netcdf::Variable| -> Result<Vec<f64>, Box<dyn Error>> {
+            let mut values = Vec::with_capacity(var.len());
+            for i in 0..var.len() {
+                values.push(var.value::<f64, _>([i])?);
+            }
+            Ok(values)
+        };
+        Ok((read(&self.lon)?, read(&self.lat)?))
+    }
+}
+
+pub fn is_gridded(dims: &[String], grid: &[String; 3]) -> bool {
+    // data variables run along [time, lat, lon], with an optional level between time and lat
+    let [time, lat, lon] = grid;
+    matches!(dims.len(), 3 | 4) && dims[0] == *time && dims[dims.len() - 2] == *lat && dims[dims.len() - 1] == *lon
+}
+
+pub fn misordered(dims: &[String], grid: &[String; 3]) -> Option<String> {
+    // the order extraction reads a variable along the time and lat/lon grid dimensions in, when the variable has
+    // all three but not in that order; reading it as if it had would scramble the cells without any error
+    if !matches!(dims.len(), 3 | 4) || !grid.iter().all(|g| dims.contains(g)) || is_gridded(dims, grid) {
+        return None;
+    }
+    let mut expected = vec!(grid[0].clone());
+    expected.extend(dims.iter().filter(|d| !grid.contains(d)).cloned());
+    expected.extend([grid[1].clone(), grid[2].clone()]);
+    Some(expected.join(", "))
+}
+
+fn data_variables(file: &netcdf::File, names: &VarMap, grid: &[String; 3]) -> String {
+    // the variables that could be passed as a data variable, for error messages
+    let candidates: Vec<String> = file.variables()
+        .filter(|v| !names.is_grid_variable(&v.name()))
+        .filter(|v| is_gridded(&v.dimensions().iter().map(|d| d.name()).collect::<Vec<String>>(), grid))
+        .map(|v| v.name())
+        .collect();
+    if candidates.is_empty() {
+        String::from("this file has no variables along time and the lat/lon grid")
+    } else {
+        format!("data variables in this file: {}", candidates.join(", "))
+    }
+}
+
+// metadoc data_type labels for 3D and surface variables
+pub const PROFILE_DATA_TYPE: &str = "BSOSE-profile";
+pub const SURFACE_DATA_TYPE: &str = "BSOSE-surface";
+
+pub struct Extractor<'f> {
+    // document construction from one BSOSE file and data variable, independent of any database
+    pub dv: String,
+    pub units: String,
+    pub long_name: String,
+    pub surface: bool,
+    // metadoc data_type, PROFILE_DATA_TYPE or SURFACE_DATA_TYPE unless --data-type replaces it
+    pub data_type: String,
+    // cell bounds from --geolocation polygon, as the raw longitude and latitude edges; None for points
+    cell_edges: Option<(Vec<f64>, Vec<f64>)>,
+    lon_convention: LonConvention,
+    // the data variable's valid_range, or valid_min and valid_max, and what --out-of-range does outside it
+    pub valid_range: Option<(f64, f64)>,
+    out_of_range: OutOfRange,
+    pub timeseries: Vec<DateTime>,
+    // indexes into the file's time dimension matching timeseries
+    timesteps: Vec<TimeIdx>,
+    // see z_sign
+    z_sign: f64,
+    pub staggering: Staggering,
+    centers: CellCenters<'f>,
+    depth: netcdf::Variable<'f>,
+    cell_area: netcdf::Variable<'f>,
+    ocean_depth: netcdf::Variable<'f>,
+    depth_r0_to_bottom: netcdf::Variable<'f>,
+    interior_2d_mask: netcdf::Variable<'f>,
+    depth_r0_to_ref_surface: netcdf::Variable<'f>,
+    cell_vertical_fraction: netcdf::Variable<'f>,
+    sea_binary_mask_at_t_location: netcdf::Variable<'f>,
+    ctrl_vector_3d_mask: netcdf::Variable<'f>,
+    cell_z_size: netcdf::Variable<'f>,
+    reference_density_profile: netcdf::Variable<'f>,
+    datavar: netcdf::Variable<'f>,
+    // set once a units/long_name conflict has been reported, so a file warns once rather than per document
+    units_warned: std::sync::atomic::AtomicBool,
+    // --reference-variable, whose zeros rather than the data variable's decide whether a level is dry
+    reference: Option<Reference<'f>>,
+    // --qc-var, the data variable's quality flags, stored under qc_column(dv) at the timesteps its values are
+    qc: Option<netcdf::Variable<'f>>,
+}
+
+struct Reference<'f> {
+    var: netcdf::Variable<'f>,
+    // whether it runs along time and along levels, ahead of [lat, lon]
+    timed: bool,
+    levels: bool
+}
+
+impl<'f> Extractor<'f> {
+    pub fn new(file: &'f netcdf::File, dv: &str) -> Result<Extractor<'f>, Box<dyn Error>> {
+        Extractor::with_var_map(file, dv, &VarMap::default())
+    }
+
+    pub fn with_var_map(file: &'f netcdf::File, dv: &str, names: &VarMap) -> Result<Extractor<'f>, Box<dyn Error>> {
+        // variable extraction, with grid variables looked up through names
+        let time = names.variable(file, "time")?;
+        let centers = CellCenters::new(file, names)?;
+        let [lat, lon] = centers.dimensions();
+        let grid = [dimension(&time), lat, lon];
+        let datavar = match file.variable(dv) {
+            Some(var) => var,
+            None => return Err(SyncError::missing_variable(dv, format!("Could not find data variable {}; {}", dv, data_variables(file, names, &grid))).into())
+        };
+        let datavar_dims: Vec<String> = datavar.dimensions().iter().map(|d| d.name()).collect();
+        if let Some(expected) = misordered(&datavar_dims, &grid) {
+            return Err(SyncError::Validation(format!("{} has dimensions [{}], but values are read along [{}]; reorder its dimensions, e.g. with ncpdq -a {}",
+                dv, datavar_dims.join(", "), expected, expected.replace(", ", ","))).into());
+        }
+        if names.is_grid_variable(dv) || !is_gridded(&datavar_dims, &grid) {
+            return Err(SyncError::Validation(format!("{} has dimensions [{}] and isn't a data variable; {}",
+                dv, datavar_dims.join(", "), data_variables(file, names, &grid))).into());
+        }
+        let surface = is_surface_variable(dv, &datavar_dims)?;
+
+        // stored as empty strings when absent; --require-attrs makes that an error instead
+        let units = string_attribute(&datavar, "units")?.unwrap_or_default();
+        let long_name = string_attribute(&datavar, "long_name")?.unwrap_or_default();
+        let valid_range = valid_range(&datavar)?;
+
+        let timeseries: Vec<DateTime> = cf_times(&time)?.into_iter().map(DateTime::from_chrono).collect();
+        check_unique_times(&timeseries)?;
+
+        let depth = names.variable(file, "depth")?;
+        let positive = match depth.attribute_value("positive") {
+            Some(Ok(netcdf::AttrValue::Str(p))) => Some(p),
+            _ => None
+        };
+        let z_sign = z_sign(positive.as_deref())?;
+        let shift = match depth.attribute_value("c_grid_axis_shift") {
+            Some(Ok(netcdf::AttrValue::Double(v))) => Some(v),
+            Some(Ok(netcdf::AttrValue::Float(v))) => Some(v as f64),
+            _ => None
+        };
+        let staggering = Staggering::of(&depth.name(), shift);
+        if !surface {
+            // the depth variable has to be the one the data variable's levels run along; Z and Zl have
+            // the same length, so the dimension names are compared too
+            let levels = datavar.dimensions()[1].len();
+            if depth.len() != levels || dimension(&depth) != datavar_dims[1] {
+                return Err(SyncError::Validation(format!("depth variable {} has {} levels along {}, but {} has {} along {}; choose its vertical coordinate with --depth-var",
+                    depth.name(), depth.len(), dimension(&depth), dv, levels, datavar_dims[1])).into());
+            }
+        }
+        // the spacing that goes with the staggering, of which a face needs one entry more below it
+        let cell_z_size = if staggering == Staggering::Center {
+            names.variable(file, "cell_z_size")?
+        } else {
+            names.variable(file, "interface_z_size")?
+        };
+        if staggering != Staggering::Center && !surface && cell_z_size.len() < depth.len() + (staggering == Staggering::Lower) as usize {
+            return Err(SyncError::Validation(format!("{} has {} entries, too few for the {} levels of {}", cell_z_size.name(), cell_z_size.len(), depth.len(), depth.name())).into());
+        }
+
+        Ok(Extractor {
+            dv: dv.to_string(),
+            units: units,
+            long_name: long_name,
+            surface: surface,
+            data_type: String::from(if surface { SURFACE_DATA_TYPE } else { PROFILE_DATA_TYPE }),
+            cell_edges: None,
+            lon_convention: LonConvention::Pm180,
+            valid_range: valid_range,
+            out_of_range: OutOfRange::Keep,
+            timesteps: (0..timeseries.len()).map(TimeIdx).collect(),
+            z_sign: z_sign,
+            staggering: staggering,
+            timeseries: timeseries,
+            centers: centers,
+            depth: depth,
+            cell_area: names.variable(file, "cell_area")?,
+            ocean_depth: names.variable(file, "ocean_depth")?,
+            depth_r0_to_bottom: names.variable(file, "depth_r0_to_bottom")?,
+            interior_2d_mask: names.variable(file, "interior_2d_mask")?,
+            depth_r0_to_ref_surface: names.variable(file, "depth_r0_to_ref_surface")?,
+            cell_vertical_fraction: names.variable(file, "cell_vertical_fraction")?,
+            sea_binary_mask_at_t_location: names.variable(file, "sea_binary_mask_at_t_location")?,
+            ctrl_vector_3d_mask: names.variable(file, "ctrl_vector_3d_mask")?,
+            cell_z_size: cell_z_size,
+            reference_density_profile: names.variable(file, "reference_density_profile")?,
+            datavar: datavar,
+            units_warned: std::sync::atomic::AtomicBool::new(false),
+            reference: None,
+            qc: None,
+        })
+    }
+
+    pub fn year(&mut self, year: i32) -> Result<(), Box<dyn Error>> {
+        // restrict extraction to the timesteps in one calendar year, for a {year} partition
+        let keep = partition_by_year(&self.timeseries).remove(&year).unwrap_or_default();
+        if keep.is_empty() {
+            return Err(SyncError::Validation(format!("no timesteps in {}", year)).into());
+        }
+        self.timeseries = select(&self.timeseries, &keep);
+        self.timesteps = select(&self.timesteps, &keep);
+        Ok(())
+    }
+
+    pub fn since(&mut self, cutoff: DateTime) -> Result<(), Box<dyn Error>> {
+        // restrict extraction to the timesteps at or after cutoff
+        let keep = timesteps_since(&self.timeseries, cutoff);
+        if keep.is_empty() {
+            return Err(SyncError::Validation(format!("no timesteps at or after {}", cutoff)).into());
+        }
+        self.timeseries = select(&self.timeseries, &keep);
+        self.timesteps = select(&self.timesteps, &keep);
+        Ok(())
+    }
+
+    pub fn cell_polygons(&mut self) -> Result<(), Box<dyn Error>> {
+        // documents get the cell's bounds as a Polygon rather than its center as a Point, from the whole
+        // XC and YC axes, so cells at the edge of a tile are bounded by their neighbours outside it
+        let (lons, lats) = self.centers.axes().map_err(|e| SyncError::Validation(format!("cell polygons need a rectilinear grid: {}", e)))?;
+        for (values, axis) in [(&lons, "longitudes"), (&lats, "latitudes")] {
+            if values.len() < 2 || values.windows(2).any(|pair| pair[1] <= pair[0]) {
+                return Err(SyncError::Validation(format!("cell polygons need at least two ascending {}", axis)).into());
+            }
+        }
+        // the longitudes go all the way round when the gap from the last back to the first is no wider than the first spacing
+        let wrap = lons[lons.len() - 1] - lons[0] + (lons[1] - lons[0]) >= 360.0 - 1e-6;
+        self.cell_edges = Some((cell_edges(&lons, wrap), cell_edges(&lats, false)));
+        Ok(())
+    }
+
+    fn geolocation(&self, latidx: LatIdx, lonidx: LonIdx, lon_val: f64, lat_val: f64) -> Geolocation {
+        match &self.cell_edges {
+            Some((lons, lats)) => Geolocation{
+                location_type: String::from("Polygon"),
+                coordinates: cell_polygon(lons[lonidx.0], lons[lonidx.0 + 1], lats[latidx.0], lats[latidx.0 + 1], self.lon_convention)
+            },
+            None => Geolocation{
+                location_type: String::from("Point"),
+                coordinates: Coordinates::Point([lon_val, lat_val])
+            }
+        }
+    }
+
+    pub fn qc(&mut self, file: &'f netcdf::File, name: &str) -> Result<(), Box<dyn Error>> {
+        // the flags are read at the same points as the data, so they need the same dimensions
+        let var = file.variable(name).ok_or_else(|| SyncError::missing_variable(name, format!("Could not find flag variable {}", name)))?;
+        let dims: Vec<String> = var.dimensions().iter().map(|d| d.name()).collect();
+        let datavar_dims: Vec<String> = self.datavar.dimensions().iter().map(|d| d.name()).collect();
+        if dims != datavar_dims {
+            return Err(SyncError::Validation(format!("flag variable {} has dimensions [{}], but {} has [{}]", name, dims.join(", "), self.dv, datavar_dims.join(", "))).into());
+        }
+        self.qc = Some(var);
+        Ok(())
+    }
+
+    pub fn lon_convention(&mut self, convention: LonConvention) {
+        // the range of every longitude the documents get, see LonConvention
+        self.lon_convention = convention;
+    }
+
+    pub fn out_of_range(&mut self, mode: OutOfRange) {
+        // applied by profile() to every value it reads; without a valid range nothing is out of range
+        self.out_of_range = mode;
+    }
+
+    pub fn reference(&mut self, file: &'f netcdf::File, name: &str, names: &VarMap) -> Result<(), Box<dyn Error>> {
+        // any variable ending in [lat, lon], optionally with time first and a level before lat; one without
+        // levels applies to every level, and a surface data variable reads the top level like the masks
+        let var = file.variable(name).ok_or_else(|| SyncError::missing_variable(name, format!("Could not find reference variable {}", name)))?;
+        let dims: Vec<String> = var.dimensions().iter().map(|d| d.name()).collect();
+        let time = dimension(&names.variable(file, "time")?);
+        let [lat, lon] = CellCenters::new(file, names)?.dimensions();
+        let timed = dims.first() == Some(&time);
+        let rest = &dims[timed as usize..];
+        if !matches!(rest.len(), 2 | 3) || rest[rest.len() - 2] != lat || rest[rest.len() - 1] != lon {
+            return Err(SyncError::Validation(format!("reference variable {} has dimensions [{}], expected [{}, {}] with optional time and level dimensions ahead",
+                name, dims.join(", "), lat, lon)).into());
+        }
+        let levels = rest.len() == 3;
+        self.reference = Some(Reference{var: var, timed: timed, levels: levels});
+        Ok(())
+    }
+
+    fn reference_values(&self, reference: &Reference, level: LevelIdx, latidx: LatIdx, lonidx: LonIdx) -> Result<Vec<f64>, Box<dyn Error>> {
+        // the reference variable at one cell and level, over this run's timesteps if it has a time dimension
+        let mut index = Vec::new();
+        if reference.levels {
+            index.push(level.0);
+        }
+        index.extend(cell(latidx, lonidx));
+        if !reference.timed {
+            return Ok(vec!(reference.var.value::<f64, _>(index.as_slice())?));
+        }
+        let mut values = Vec::new();
+        for &timeidx in &self.timesteps {
+            let mut at = vec!(timeidx.0);
+            at.extend(&index);
+            values.push(reference.var.value::<f64, _>(at.as_slice())?);
+        }
+        Ok(values)
+    }
+
+    pub fn check_times(&mut self, earliest: DateTime, latest: DateTime, mode: OnBadTime) -> Result<Vec<DateTime>, Box<dyn Error>> {
+        // returns the timestamps outside [earliest, latest]; skip also drops them from extraction
+        let bad = times_outside(&self.timeseries, earliest, latest);
+        if bad.is_empty() {
+            return Ok(Vec::new());
+        }
+        let found = select(&self.timeseries, &bad);
+        let message = format!("{} of {} timesteps fall outside {} to {}, the first being {}; check the units and origin of the time variable",
+            found.len(), self.timeseries.len(), earliest, latest, found[0]);
+        match mode {
+            OnBadTime::Warn => {},
+            OnBadTime::Skip => {
+                if found.len() == self.timeseries.len() {
+                    return Err(SyncError::Validation(message).into());
+                }
+                let keep: Vec<usize> = (0..self.timeseries.len()).filter(|i| !bad.contains(i)).collect();
+                self.timeseries = select(&self.timeseries, &keep);
+                self.timesteps = select(&self.timesteps, &keep);
+            }
+            OnBadTime::Error => return Err(SyncError::Validation(message).into())
+        }
+        Ok(found)
+    }
+
+    pub fn center(&self, latidx: LatIdx, lonidx: LonIdx) -> Result<(f64, f64), Box<dyn Error>> {
+        // a cell's longitude and latitude, as they go into ids and documents
+        let (lon, lat) = self.centers.center(latidx, lonidx)?;
+        Ok((self.lon_convention.apply(lon), lat))
+    }
+
+    pub fn curvilinear(&self) -> bool {
+        self.centers.curvilinear
+    }
+
+    pub fn levels(&self) -> Vec<Option<LevelIdx>> {
+        // surface fields get a single document per cell at level 0, marked by None
+        if self.surface { vec![None] } else { (0..self.depth.len()).map(|l| Some(LevelIdx(l))).collect() }
+    }
+
+    pub fn level_range(&self, min: Option<usize>, max: Option<usize>) -> Result<Vec<Option<LevelIdx>>, Box<dyn Error>> {
+        // levels min..max, defaulting to every level; a surface field's one document counts as level 0
+        let n = self.depth.len();
+        let (min, max) = (min.unwrap_or(0), max.unwrap_or(n));
+        if min >= max || max > n {
+            return Err(SyncError::Args(format!("level range {}..{} is empty or outside the file's {} levels of {}", min, max, n, self.depth.name())).into());
+        }
+        if self.surface {
+            return Ok(if min == 0 { vec![None] } else { Vec::new() });
+        }
+        Ok((min..max).map(|l| Some(LevelIdx(l))).collect())
+    }
+
+    pub fn depth(&self, level: Option<LevelIdx>) -> Result<f64, Box<dyn Error>> {
+        // height of a level, negative below the surface whichever way the file's Z points;
+        // ids carry this value and documents store its negation as a positive-down level
+        match level {
+            Some(levelidx) => Ok(self.z_sign * self.depth.value::<f64, _>([levelidx.0])?),
+            None => Ok(0.0)
+        }
+    }
+
+    fn z_size(&self, level: LevelIdx) -> Result<f64, Box<dyn Error>> {
+        // drF at a center; at a face, drC between the centers above and below it, drC[k] being the one
+        // above center k
+        let k = if self.staggering == Staggering::Lower { level.0 + 1 } else { level.0 };
+        Ok(self.cell_z_size.value::<f64, _>([k])?)
+    }
+
+    fn reference_density(&self, level: LevelIdx) -> Result<f64, Box<dyn Error>> {
+        // rhoRef is only given at centers, so a face gets the mean of the centers either side, or the
+        // nearest center at the top and bottom faces
+        let k = level.0;
+        let (above, below) = match self.staggering {
+            Staggering::Center => return Ok(self.reference_density_profile.value::<f64, _>([k])?),
+            Staggering::Upper => (k.saturating_sub(1), k),
+            Staggering::Lower => (k, (k + 1).min(self.reference_density_profile.len() - 1))
+        };
+        let above = self.reference_density_profile.value::<f64, _>([above])?;
+        let below = self.reference_density_profile.value::<f64, _>([below])?;
+        Ok((above + below) / 2.0)
+    }
+
+    pub fn metadoc(&self, id_precision: usize, latidx: LatIdx, lonidx: LonIdx, sourcedoc: Sourcedoc) -> Result<BsoseMetadoc, Box<dyn Error>> {
+        let (lon_val, lat_val) = self.center(latidx, lonidx)?;
+        let metaid = format_id(id_precision, &[lon_val, lat_val]);
+        self.lon_convention.check(&metaid, lon_val, lat_val)?;
+        let interior = self.interior_2d_mask.value::<i8, _>(cell(latidx, lonidx))? != 0;
+        Ok(BsoseMetadoc{
+            _id: metaid,
+            latitude: lat_val,
+            longitude: lon_val,
+            data_type: self.data_type.clone(),
+            date_updated_argovis: DateTime::now(),
+            timeseries: self.timeseries.clone(),
+            source: vec!(sourcedoc),
+            cell_area: grid_value(&self.cell_area, latidx, lonidx, interior)?,
+            ocean_depth: grid_value(&self.ocean_depth, latidx, lonidx, interior)?,
+            depth_r0_to_bottom: grid_value(&self.depth_r0_to_bottom, latidx, lonidx, interior)?,
+            interior_2d_mask: interior,
+            depth_r0_to_ref_surface: grid_value(&self.depth_r0_to_ref_surface, latidx, lonidx, interior)?,
+            chunked: Vec::new()
+        })
+    }
+
+    pub fn missing_attributes(&self) -> Result<Vec<&'static str>, Box<dyn Error>> {
+        // the data_info attributes the data variable doesn't carry as text
+        let mut missing = Vec::new();
+        for name in ["units", "long_name"] {
+            if string_attribute(&self.datavar, name)?.is_none() {
+                missing.push(name);
+            }
+        }
+        Ok(missing)
+    }
+
+    fn timeseries_at(&self, var: &netcdf::Variable, level: Option<LevelIdx>, latidx: LatIdx, lonidx: LonIdx) -> Result<Vec<f64>, Box<dyn Error>> {
+        let mut values = Vec::new();
+        for &timeidx in &self.timesteps {
+            values.push(match level {
+                Some(levelidx) => var.value::<f64, _>(point(timeidx, levelidx, latidx, lonidx))?,
+                None => var.value::<f64, _>(surface_point(timeidx, latidx, lonidx))?
+            });
+        }
+        Ok(values)
+    }
+
+    pub fn qc_profile(&self, level: Option<LevelIdx>, latidx: LatIdx, lonidx: LonIdx) -> Result<Option<Vec<f64>>, Box<dyn Error>> {
+        // the flags going with profile(), as stored in the file
+        match &self.qc {
+            Some(var) => Ok(Some(self.timeseries_at(var, level, latidx, lonidx)?)),
+            None => Ok(None)
+        }
+    }
+
+    pub fn profile(&self, level: Option<LevelIdx>, latidx: LatIdx, lonidx: LonIdx) -> Result<Vec<f64>, Box<dyn Error>> {
+        // the data variable's timeseries at one lon/lat/level triple
+        let mut datavar_profile = self.timeseries_at(&self.datavar, level, latidx, lonidx)?;
+        if let Some(range) = self.valid_range {
+            apply_valid_range(&mut datavar_profile, range, self.out_of_range);
+        }
+        Ok(datavar_profile)
+    }
+
+    pub fn document(&self, id_precision: usize, level: Option<LevelIdx>, latidx: LatIdx, lonidx: LonIdx, basin: i32, profile: Vec<f64>) -> Result<BsoseDocument, Box<dyn Error>> {
+        let (lon_val, lat_val) = self.center(latidx, lonidx)?;
+        let depth_val = self.depth(level)?;
+        let id = format_id(id_precision, &[lon_val, lat_val, depth_val]);
+        self.lon_convention.check(&id, lon_val, lat_val)?;
+        // 3D masks are read at the top level for surface fields
+        let maskidx = level.unwrap_or(LevelIdx(0));
+        Ok(BsoseDocument {
+            _id: id,
+            metadata: vec![format_id(id_precision, &[lon_val, lat_val])],
+            basin: basin,
+            geolocation: self.geolocation(latidx, lonidx, lon_val, lat_val),
+            level: -depth_val,
+            data: vec![profile],
+            data_info: (
+                vec!(self.dv.clone()),
+                vec!(String::from("units"), String::from("long_name")),
+                vec!(
+                    vec!(self.units.clone(), self.long_name.clone())
+                )
+            ),
+            cell_vertical_fraction: self.cell_vertical_fraction.value::<f64, _>(cell_at_level(maskidx, latidx, lonidx))?,
+            sea_binary_mask_at_t_location: self.sea_binary_mask_at_t_location.value::<i8, _>(cell_at_level(maskidx, latidx, lonidx))? != 0,
+            ctrl_vector_3d_mask:  self.ctrl_vector_3d_mask.value::<i8, _>(cell_at_level(maskidx, latidx, lonidx))? != 0,
+            cell_z_size: match level {
+                Some(levelidx) => self.z_size(levelidx)?,
+                None => SURFACE_SENTINEL
+            },
+            reference_density_profile: match level {
+                Some(levelidx) => self.reference_density(levelidx)?,
+                None => SURFACE_SENTINEL
+            },
+            // for the file's own timesteps; ingest records the metadoc's when the profile is placed on a longer one
+            timeline: Some(TimelineRef::of(&self.timeseries)),
+            chunk: None
+        })
+    }
+
+    pub fn is_dry(&self, level: Option<LevelIdx>, latidx: LatIdx, lonidx: LonIdx, profile: &[f64], mode: DryCells) -> Result<bool, Box<dyn Error>> {
+        // 3D masks are read at the top level for surface fields, as in document
+        let maskidx = level.unwrap_or(LevelIdx(0));
+        match mode {
+            DryCells::Zeros => match &self.reference {
+                Some(reference) => Ok(self.reference_values(reference, maskidx, latidx, lonidx)?.iter().all(|&x| x == 0.0)),
+                None => Ok(profile.iter().all(|&x| x == 0.0))
+            },
+            DryCells::Mask => Ok(self.sea_binary_mask_at_t_location.value::<i8, _>(cell_at_level(maskidx, latidx, lonidx))? == 0),
+            DryCells::VerticalFraction => Ok(self.cell_vertical_fraction.value::<f64, _>(cell_at_level(maskidx, latidx, lonidx))? == 0.0)
+        }
+    }
+
+    pub fn overwrites(&self, doc: &BsoseDocument, profile: &[f64]) -> Vec<usize> {
+        // timestep indexes where an overwriting merge would change a stored, non-NaN value
+        self.overwrites_at(doc, profile, &(0..profile.len()).collect::<Vec<usize>>())
+    }
+
+    pub fn overwrites_at(&self, doc: &BsoseDocument, profile: &[f64], covered: &[usize]) -> Vec<usize> {
+        // as overwrites, for a profile placed on a longer timeline that only the covered indexes came from
+        match doc.data_info.0.iter().position(|v| *v == self.dv) {
+            None => Vec::new(),
+            Some(dv_idx) => covered.iter().copied()
+                .filter(|&i| matches!((doc.data[dv_idx].get(i), profile.get(i)), (Some(stored), Some(new)) if !stored.is_nan() && stored != new))
+                .collect()
+        }
+    }
+
+    pub fn units_conflict(&self, doc: &BsoseDocument) -> Option<String> {
+        // how the units or long_name stored for this variable differ from this file's, if they do
+        let dv_idx = doc.data_info.0.iter().position(|v| *v == self.dv)?;
+        let stored = doc.data_info.2.get(dv_idx)?;
+        let mut conflicts = Vec::new();
+        for (name, ours) in [("units", &self.units), ("long_name", &self.long_name)] {
+            let theirs = doc.data_info.1.iter().position(|n| n == name).and_then(|i| stored.get(i));
+            if let Some(theirs) = theirs.filter(|t| *t != ours) {
+                conflicts.push(format!("{} {:?} stored, {:?} in this file", name, theirs, ours));
+            }
+        }
+        if conflicts.is_empty() {
+            None
+        } else {
+            Some(format!("document {} has {} {}", doc._id, self.dv, conflicts.join(" and ")))
+        }
+    }
+
+    pub fn first_units_conflict(&self) -> bool {
+        // true the first time it's called, for reporting a conflict once per file and variable
+        !self.units_warned.swap(true, std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn merge(&self, doc: &mut BsoseDocument, profile: Vec<f64>, overwrite: bool) -> Vec<usize> {
+        // fold this variable's profile into a document written by an earlier ingest,
+        // returning the timestep indexes that were written
+        let covered: Vec<usize> = (0..profile.len()).collect();
+        self.merge_at(doc, profile, overwrite, &covered)
+    }
+
+    pub fn merge_at(&self, doc: &mut BsoseDocument, profile: Vec<f64>, overwrite: bool, covered: &[usize]) -> Vec<usize> {
+        // as merge, for a profile placed on the document's longer timeline: only the covered indexes came
+        // from the file, so an overwrite leaves the stored values at every other index alone
+        match doc.data_info.0.iter().position(|v| *v == self.dv) {
+            None => {
+                // a new variable is appended as a new column
+                let written = (0..profile.len()).collect();
+                doc.data.push(profile);
+                doc.data_info.0.push(self.dv.clone());
+                doc.data_info.2.push(vec!(self.units.clone(), self.long_name.clone()));
+                written
+            }
+            Some(dv_idx) if overwrite && covered.len() == profile.len() => {
+                // re-ingesting a corrected file replaces the whole column
+                let written = (0..profile.len()).collect();
+                doc.data[dv_idx] = profile;
+                doc.data_info.2[dv_idx] = vec!(self.units.clone(), self.long_name.clone());
+                written
+            }
+            Some(dv_idx) if overwrite => {
+                // a file covering part of the stored timeseries replaces just its own timesteps
+                let column = &mut doc.data[dv_idx];
+                let written: Vec<usize> = covered.iter().copied().filter(|&i| i < column.len() && i < profile.len()).collect();
+                for &i in &written {
+                    column[i] = profile[i];
+                }
+                doc.data_info.2[dv_idx] = vec!(self.units.clone(), self.long_name.clone());
+                written
+            }
+            Some(dv_idx) => {
+                // incremental merge only fills gaps, previously stored values win
+                let mut written = Vec::new();
+                for (i, (stored, new)) in doc.data[dv_idx].iter_mut().zip(profile.iter()).enumerate() {
+                    if stored.is_nan() {
+                        *stored = *new;
+                        written.push(i);
+                    }
+                }
+                written
+            }
+        }
+    }
+
+    pub fn merge_qc_at(&self, doc: &mut BsoseDocument, flags: &[f64], written: &[usize]) {
+        // the flags follow the data: they're written at exactly the timestep indexes merge_at wrote values to,
+        // into a column added as NaN throughout if the document has none yet
+        let Some(qc) = &self.qc else { return };
+        let name = qc_column(&self.dv);
+        let n_timesteps = doc.data.first().map(|d| d.len()).unwrap_or(flags.len());
+        let idx = match doc.data_info.0.iter().position(|v| *v == name) {
+            Some(idx) => idx,
+            None => {
+                doc.data.push(vec!(f64::NAN; n_timesteps));
+                doc.data_info.0.push(name);
+                doc.data_info.2.push(Vec::new());
+                doc.data.len() - 1
+            }
+        };
+        let column = &mut doc.data[idx];
+        for &i in written {
+            if let (Some(stored), Some(flag)) = (column.get_mut(i), flags.get(i)) {
+                *stored = *flag;
+            }
+        }
+        let long_name = string_attribute(qc, "long_name").ok().flatten().unwrap_or_default();
+        doc.data_info.2[idx] = vec!(String::new(), long_name);
+    }
+}
@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// how much a run prints. Errors always go to stderr; warnings go to stderr, and progress and debug's per-document
+// logging to stdout, at the levels that allow them. Summaries, the counts a run or maintenance mode ends with, are separate: they print at every
+// level unless turned off. Both are process-wide and set once at startup, so nothing has to thread them through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static SUMMARIES: AtomicBool = AtomicBool::new(true);
+
+impl Level {
+    pub fn parse(level: &str) -> Result<Level, Box<dyn Error>> {
+        match level {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("invalid log level {}, expected error, warn, info or debug", level).into())
+        }
+    }
+}
+
+pub fn configure(level: Level, summaries: bool) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    SUMMARIES.store(summaries, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn summaries() -> bool {
+    SUMMARIES.load(Ordering::Relaxed)
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Warn) {
+            eprintln!("warning: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! summary {
+    ($($arg:tt)*) => {
+        if $crate::log::summaries() {
+            println!($($arg)*);
+        }
+    };
+}
@@ -1,268 +1,543 @@
-use netcdf;
-use tokio;
 use std::error::Error;
-use chrono::Utc;
-use chrono::TimeZone;
-use chrono::Duration;
 use std::env;
 use mongodb::bson::{doc};
 use mongodb::bson::DateTime;
-use mongodb::{Client, options::{ClientOptions, ResolverConfig}};
-use serde::{Deserialize, Serialize};
-use mongodb::bson::Bson;
+use mongodb::{Client, options::{ClientOptions, ResolverConfig, CollectionOptions, ReplaceOptions, Tls, TlsOptions, WriteConcern}};
+use std::path::{Path, PathBuf};
+use bsose_sync::{Sourcedoc, BsoseMetadoc, BsoseDocument, Extractor, CellCenters, format_id, check_data_lengths, check_timeline, TimelineRef, CellRecord, parse_date, remove_variable, qc_column, timesteps_since, select, populated_timesteps, first_occurrences, degree_range, global_attributes, validate_document, extend_timeline, LonConvention, LatIdx, LonIdx};
+use mongodb::Collection;
+use bsose_sync::sink::{CellTransaction, MongoSink, JsonSink, write_concern};
+use bsose_sync::input;
+use bsose_sync::diff::{self, DiffSink};
+use bsose_sync::error::SyncError;
+use bsose_sync::args::{self, Args};
+use bsose_sync::ingest::{ingest_all, partitions, watch_signals, Stop};
+use bsose_sync::{debug, info, summary, warn};
+use futures::stream::TryStreamExt;
 
-fn tidylon(longitude: f64) -> f64{
-    // map longitude on [0,360] to [-180,180], required for mongo indexing
-    if longitude <= 180.0{
-        return longitude;
+// process exit codes, see args::USAGE
+const EXIT_RUNTIME: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NETCDF: i32 = 3;
+const EXIT_MONGO: i32 = 4;
+const EXIT_INTERRUPTED: i32 = 5;
+const EXIT_DEADLINE: i32 = 6;
+
+fn exit_code(e: &SyncError) -> i32 {
+    match e {
+        SyncError::Args(_) | SyncError::Validation(_) | SyncError::MissingVariable{..} => EXIT_USAGE,
+        SyncError::Netcdf(_) => EXIT_NETCDF,
+        SyncError::Mongo(_) => EXIT_MONGO,
+        SyncError::Interrupted => EXIT_INTERRUPTED,
+        SyncError::Deadline => EXIT_DEADLINE,
+        SyncError::Other(_) => EXIT_RUNTIME
+    }
+}
+
+fn tile_metaids(args: &Args, file: &netcdf::File) -> Result<Vec<String>, Box<dyn Error>> {
+    // metadata ids of the requested tile, for modes that work on stored documents rather than the file's data
+    let centers = CellCenters::new(file, &args.var_map)?;
+    let convention = LonConvention::parse(&args.lon_convention)?;
+    let mut metaids = Vec::new();
+    for latidx in args.lolat..args.hilat {
+        for lonidx in args.lolong..args.hilong {
+            let (lon, lat) = centers.center(LatIdx(latidx), LonIdx(lonidx))?;
+            metaids.push(format_id(args.id_precision, &[convention.apply(lon), lat]));
+        }
+    }
+    Ok(metaids)
+}
+
+async fn delete(args: &Args, file: &netcdf::File, bsose: &Collection<BsoseDocument>, bsose_meta: &Collection<BsoseMetadoc>) -> Result<(), Box<dyn Error>> {
+    // undo part of an ingest within the tile: drop a variable, trim early timesteps, or collapse repeated ones
+    let cutoff = match &args.delete_before {
+        Some(date) => Some(parse_date(date).map_err(SyncError::args)?),
+        None => None
+    };
+    let mut docs_changed = 0;
+    let mut docs_deleted = 0;
+    let mut metadocs_changed = 0;
+    let mut cells_skipped = 0;
+
+    for metaid in tile_metaids(args, file)? {
+        // timesteps to keep are decided by the cell's metadoc, and applied to every data document in the cell
+        let mut trim = None;
+        if cutoff.is_some() || args.repair_duplicates {
+            if let Some(meta) = bsose_meta.find_one(doc! {"_id": metaid.clone()}, None).await? {
+                let mut kept: Vec<usize> = (0..meta.timeseries.len()).collect();
+                if args.repair_duplicates {
+                    kept = first_occurrences(&meta.timeseries);
+                    if kept.len() < meta.timeseries.len() {
+                        info!("meta {}: {} repeated timestamps {} collapsed", metaid, meta.timeseries.len() - kept.len(),
+                            if args.dry_run { "would be" } else { "were" });
+                    }
+                }
+                if let Some(cutoff) = cutoff {
+                    let since = timesteps_since(&meta.timeseries, cutoff);
+                    kept.retain(|i| since.contains(i));
+                }
+                if kept.len() < meta.timeseries.len() {
+                    let timeseries = select(&meta.timeseries, &kept);
+                    trim = Some((meta, kept, timeseries));
+                }
+            }
+        }
+
+        let docs: Vec<BsoseDocument> = bsose.find(doc! {"metadata": metaid.clone()}, None).await?.try_collect().await?;
+        if let Some((meta, _, _)) = &trim {
+            // indexes picked from the metadoc only mean the same timesteps in arrays of its length
+            if let Some(e) = docs.iter().find_map(|doc| check_data_lengths(doc, meta.timeseries.len()).err()) {
+                warn!("meta {}: {}; cell skipped", metaid, e);
+                cells_skipped += 1;
+                continue;
+            }
+        }
+
+        for mut doc in docs {
+            let mut changed = false;
+            if let Some(variable) = &args.delete_var {
+                changed |= remove_variable(&mut doc, variable);
+                changed |= remove_variable(&mut doc, &qc_column(variable));
+            }
+            if let Some((_, kept, timeseries)) = &trim {
+                doc.data = doc.data.iter().map(|d| select(d, kept)).collect();
+                doc.timeline = Some(TimelineRef::of(timeseries));
+                changed = true;
+            }
+            if !changed {
+                continue;
+            }
+            if doc.data.is_empty() {
+                docs_deleted += 1;
+                if !args.dry_run {
+                    bsose.delete_one(doc! {"_id": doc._id.clone()}, None).await?;
+                }
+            } else {
+                docs_changed += 1;
+                if !args.dry_run {
+                    bsose.replace_one(doc! {"_id": doc._id.clone()}, doc, None).await?;
+                }
+            }
+        }
+
+        // trimmed data documents already point at the new timeseries; until this write lands they disagree
+        // with the stored metadoc, which check_timeline refuses, instead of lining up with the old timestamps
+        if let Some((mut meta, _, timeseries)) = trim {
+            metadocs_changed += 1;
+            if !args.dry_run {
+                meta.timeseries = timeseries;
+                meta.date_updated_argovis = DateTime::now();
+                bsose_meta.replace_one(doc! {"_id": metaid.clone()}, meta, None).await?;
+            }
+        }
+    }
+
+    let verb = if args.dry_run { "would be" } else { "were" };
+    summary!("{} metadata documents {} trimmed", metadocs_changed, verb);
+    summary!("{} data documents {} updated, {} {} deleted as empty", docs_changed, verb, docs_deleted, verb);
+    if cells_skipped > 0 {
+        summary!("{} cells were skipped because their data didn't match their metadoc's timeseries", cells_skipped);
     }
-    else{
-        return longitude-360.0;
+    Ok(())
+}
+
+async fn compact(args: &Args, file: &netcdf::File, bsose: &Collection<BsoseDocument>, bsose_meta: &Collection<BsoseMetadoc>) -> Result<(), Box<dyn Error>> {
+    // drop timesteps none of a cell's data documents has a value for, from its metadoc and every data array alike;
+    // the whole tile is planned before anything is written so the count can be held to --confirm
+    let mut plans = Vec::new();
+    let mut removed = 0;
+    for metaid in tile_metaids(args, file)? {
+        let meta = match bsose_meta.find_one(doc! {"_id": metaid.clone()}, None).await? {
+            Some(meta) => meta,
+            None => continue
+        };
+        let docs: Vec<BsoseDocument> = bsose.find(doc! {"metadata": metaid.clone()}, None).await?.try_collect().await?;
+        if docs.is_empty() {
+            // land cells have no data to judge their timesteps by
+            continue;
+        }
+        for doc in &docs {
+            check_data_lengths(doc, meta.timeseries.len()).map_err(|e| format!("--compact: {}", e))?;
+        }
+        let kept = populated_timesteps(&docs, meta.timeseries.len());
+        if kept.len() < meta.timeseries.len() {
+            info!("meta {}: {} of {} timesteps have no values", metaid, meta.timeseries.len() - kept.len(), meta.timeseries.len());
+            removed += meta.timeseries.len() - kept.len();
+            plans.push((meta, docs, kept));
+        }
+    }
+    if args.dry_run {
+        summary!("{} timesteps would be removed from {} metadata documents; rerun with --confirm {} to remove them", removed, plans.len(), removed);
+        return Ok(());
+    }
+    if args.confirm != Some(removed) {
+        return Err(SyncError::Validation(format!("--confirm {} doesn't match the {} timesteps --compact would remove now; nothing was changed, rerun with --dry-run",
+            args.confirm.unwrap_or(0), removed)).into());
     }
+
+    let mut docs_changed = 0;
+    for (mut meta, docs, kept) in plans {
+        let timeseries = select(&meta.timeseries, &kept);
+        for mut doc in docs {
+            doc.data = doc.data.iter().map(|d| select(d, &kept)).collect();
+            doc.timeline = Some(TimelineRef::of(&timeseries));
+            bsose.replace_one(doc! {"_id": doc._id.clone()}, doc, None).await?;
+            docs_changed += 1;
+        }
+        // the metadoc goes last, so an interrupted compaction is found by check_timeline rather than misread
+        let metaid = meta._id.clone();
+        meta.timeseries = timeseries;
+        meta.date_updated_argovis = DateTime::now();
+        bsose_meta.replace_one(doc! {"_id": metaid}, meta, None).await?;
+    }
+    summary!("{} timesteps were removed, {} data documents updated", removed, docs_changed);
+    Ok(())
 }
 
-fn find_basin(basins: &netcdf::Variable, longitude: f64, latitude: f64) -> i32 {    
-    let lonplus = (longitude-0.5).ceil()+0.5;
-    let lonminus = (longitude-0.5).floor()+0.5;
-    let latplus = (latitude-0.5).ceil()+0.5;
-    let latminus = (latitude-0.5).floor()+0.5;
-
-    let lonplus_idx = (lonplus - -179.5) as usize;
-    let lonminus_idx = (lonminus - -179.5) as usize;
-    let latplus_idx = (latplus - -77.5) as usize;
-    let latminus_idx = (latminus - -77.5) as usize;
-
-    let corners_idx = [
-        // bottom left corner, clockwise
-        [latminus_idx, lonminus_idx],
-        [latplus_idx, lonminus_idx],
-        [latplus_idx, lonplus_idx],
-        [latminus_idx, lonplus_idx]
-    ];
-
-    let distances = [
-        (f64::powi(longitude-lonminus, 2) + f64::powi(latitude-latminus, 2)).sqrt(),
-        (f64::powi(longitude-lonminus, 2) + f64::powi(latitude-latplus, 2)).sqrt(),
-        (f64::powi(longitude-lonplus, 2) + f64::powi(latitude-latplus, 2)).sqrt(),
-        (f64::powi(longitude-lonplus, 2) + f64::powi(latitude-latminus, 2)).sqrt()
-    ];
-
-    let mut closecorner_idx = corners_idx[0];
-    let mut closedist = distances[0];
-    for i in 1..4 {
-        if distances[i] < closedist{
-            closecorner_idx = corners_idx[i];
-            closedist = distances[i];
+async fn rebuild_meta(args: &Args, files: &[String], bsose: &Collection<BsoseDocument>, bsose_meta: &Collection<BsoseMetadoc>) -> Result<(), Box<dyn Error>> {
+    // data documents hold no times of their own, so the timeline comes from the files that were ingested,
+    // in order, and a cell is only rebuilt if every stored data column has that many timesteps
+    let mut timeline = Vec::new();
+    let mut sources = Vec::new();
+    for path in files {
+        let file = input::open(path).map_err(SyncError::netcdf)?;
+        let extractor = Extractor::with_var_map(&file, &args.variables[0], &args.var_map).map_err(SyncError::netcdf)?;
+        timeline = extend_timeline(&timeline, &extractor.timeseries).map_err(|e| format!("{}: {}", path, e))?;
+        sources.push(Sourcedoc{
+            source: vec!(args.source.clone()),
+            iter: args.iter.clone(),
+            file: Path::new(path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or(path.clone()),
+            attributes: global_attributes(&file, &args.global_attributes)?
+        });
+    }
+
+    // everything but the timeseries and provenance comes from the first file's grid
+    let file = input::open(&files[0]).map_err(SyncError::netcdf)?;
+    let mut extractor = Extractor::with_var_map(&file, &args.variables[0], &args.var_map).map_err(SyncError::netcdf)?;
+    if let Some(data_type) = &args.data_type {
+        extractor.data_type = data_type.clone();
+    }
+    // the ids the ingest stored under
+    extractor.lon_convention(LonConvention::parse(&args.lon_convention).map_err(SyncError::args)?);
+    let mut rebuilt = 0;
+    let mut mismatched = 0;
+    for latidx in args.lolat..args.hilat {
+        for lonidx in args.lolong..args.hilong {
+            let mut meta = extractor.metadoc(args.id_precision, LatIdx(latidx), LonIdx(lonidx), sources[0].clone())?;
+            meta.timeseries = timeline.clone();
+            meta.source = sources.clone();
+
+            let mut wrong = Vec::new();
+            let mut cursor = bsose.find(doc! {"metadata": meta._id.clone()}, None).await?;
+            while cursor.advance().await? {
+                let doc = cursor.deserialize_current()?;
+                if let Some(data) = doc.data.iter().find(|d| d.len() != timeline.len()) {
+                    wrong.push(format!("{} has {}", doc._id, data.len()));
+                } else if let Err(e) = check_timeline(&doc, &timeline, timeline.len()) {
+                    wrong.push(e.to_string());
+                }
+            }
+            if !wrong.is_empty() {
+                mismatched += 1;
+                info!("meta {}: not rebuilt, the files give {} timesteps but {}", meta._id, timeline.len(), wrong.join(", "));
+                continue;
+            }
+            rebuilt += 1;
+            debug!("meta {}: {} timesteps from {} files", meta._id, timeline.len(), files.len());
+            if !args.dry_run {
+                let upsert = ReplaceOptions::builder().upsert(true).build();
+                bsose_meta.replace_one(doc! {"_id": meta._id.clone()}, meta, upsert).await?;
+            }
         }
     }
 
-    match basins.value::<i64,_>(closecorner_idx){
-        Ok(idx) => idx as i32,
-        Err(e) => panic!("basin problems: {:?} {:#?}", e, closecorner_idx)
-    }   
+    let verb = if args.dry_run { "would be" } else { "were" };
+    summary!("{} metadata documents {} rebuilt, {} left alone because their data documents don't match the files", rebuilt, verb, mismatched);
+    if mismatched > 0 {
+        return Err(format!("{} metadata documents could not be rebuilt", mismatched).into());
+    }
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn fix_mask_key(args: &Args, file: &netcdf::File, bsose: &Collection<BsoseDocument>) -> Result<(), Box<dyn Error>> {
+    // rename the misspelled mask key in the tile's stored data documents
+    let filter = doc! {
+        "metadata": {"$in": tile_metaids(args, file)?},
+        "sea_binary_mask_at_t_locaiton": {"$exists": true}
+    };
+    if args.dry_run {
+        let n = bsose.count_documents(filter, None).await?;
+        summary!("{} data documents would have sea_binary_mask_at_t_locaiton renamed", n);
+        return Ok(());
+    }
+    let update = doc! {"$rename": {"sea_binary_mask_at_t_locaiton": "sea_binary_mask_at_t_location"}};
+    let n = bsose.update_many(filter, update, None).await?.modified_count;
+    summary!("{} data documents had sea_binary_mask_at_t_locaiton renamed", n);
+    Ok(())
+}
+
+async fn validate(args: &Args, file: &netcdf::File, bsose: &Collection<BsoseDocument>, bsose_meta: &Collection<BsoseMetadoc>) -> Result<(), Box<dyn Error>> {
+    // read-only audit of the tile's stored documents
+    let mut checked = 0;
+    let mut violations = Vec::new();
+    for metaid in tile_metaids(args, file)? {
+        let meta = bsose_meta.find_one(doc! {"_id": metaid.clone()}, None).await?;
+        let mut cursor = bsose.find(doc! {"metadata": metaid.clone()}, None).await?;
+        while cursor.advance().await? {
+            let doc = cursor.deserialize_current()?;
+            checked += 1;
+            violations.extend(validate_document(&doc, meta.as_ref(), args.id_precision));
+        }
+    }
+
+    for v in &violations {
+        summary!("{}", v);
+    }
+    summary!("{} data documents checked, {} violations", checked, violations.len());
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(SyncError::Validation(format!("validation found {} violations", violations.len())).into())
+    }
+}
+
+fn main() {
+    let argv: Vec<String> = env::args().collect();
+    if argv.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", args::USAGE);
+        return;
+    }
+    let result = if argv.iter().any(|a| a == "--print-schema") {
+        print_schema()
+    } else if argv.iter().any(|a| a == "--self-test") {
+        std::process::exit(self_test());
+    } else {
+        run(&argv)
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+fn print_schema() -> Result<(), SyncError> {
+    // the validators for the default collection names, as JSON
+    let schemas = serde_json::to_string_pretty(&bsose_sync::schema::schemas("bsose", "timeseriesMeta")).map_err(|e| SyncError::Other(e.to_string()))?;
+    println!("{}", schemas);
+    Ok(())
+}
+
+fn self_test() -> i32 {
+    // prints each check and returns the exit code, failing if any check did
+    let checks = bsose_sync::selftest::run();
+    for check in &checks {
+        match &check.failure {
+            None => println!("  ok      {}", check.name),
+            Some(failure) => println!("  FAILED  {}: {}", check.name, failure)
+        }
+    }
+    let failed = checks.iter().filter(|c| c.failure.is_some()).count();
+    println!("{} of {} checks passed", checks.len() - failed, checks.len());
+    if failed > 0 { EXIT_RUNTIME } else { 0 }
+}
+
+fn run(argv: &[String]) -> Result<(), SyncError> {
+    // the runtime is built by hand so --threads can bound its worker pool
+    let args = Args::parse(argv).map_err(SyncError::args)?;
+    bsose_sync::log::configure(args.log_level, !args.quiet_summary);
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if let Some(threads) = args.threads {
+        builder.worker_threads(threads);
+    }
+    let runtime = builder.enable_all().build()?;
+    runtime.block_on(routine(args)).map_err(SyncError::from)
+}
+
+fn resolve_degree_bounds(args: &mut Args, file: &netcdf::File) -> Result<(), Box<dyn Error>> {
+    // a degree range is a range of indexes only along the axes of a rectilinear grid
+    let (lons, lats) = CellCenters::new(file, &args.var_map)?.axes().map_err(|e| SyncError::args(format!("--lat-deg and --lon-deg need a rectilinear grid: {}", e)))?;
+    (args.lolat, args.hilat) = degree_range(&lats, args.lat_deg.0, args.lat_deg.1, false)?;
+    (args.lolong, args.hilong) = degree_range(&lons, args.lon_deg.0, args.lon_deg.1, true)?;
+    info!("degree bounds resolve to latitude indexes {}..{} and longitude indexes {}..{}", args.lolat, args.hilat, args.lolong, args.hilong);
+    Ok(())
+}
+
+fn input_files(spec: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    // <file> may be a single file or URL, a directory of .nc (and .nc.gz) files, or a glob pattern
+    let mut files = Vec::new();
+    if input::is_remote(spec) {
+        files.push(spec.to_string());
+    } else if Path::new(spec).is_dir() {
+        for entry in std::fs::read_dir(spec)? {
+            let path = entry?.path();
+            let name = path.to_string_lossy().to_string();
+            if path.is_file() && (name.ends_with(".nc") || (cfg!(feature = "gzip") && name.ends_with(".nc.gz"))) {
+                files.push(name);
+            }
+        }
+    } else if spec.contains(['*', '?', '[']) {
+        for path in glob::glob(spec)? {
+            let path = path?;
+            if path.is_file() {
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+    } else {
+        files.push(spec.to_string());
+    }
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("no NetCDF files found for {}", spec).into());
+    }
+    Ok(files)
+}
+
+fn degree_bounds(args: &mut Args, files: &[String]) -> Result<(), Box<dyn Error>> {
+    // degree bounds become index bounds on the first file's grid
+    if args.lat_deg != (None, None) || args.lon_deg != (None, None) {
+        let file = input::open(&files[0]).map_err(SyncError::netcdf)?;
+        resolve_degree_bounds(args, &file).map_err(SyncError::args)?;
+    }
+    Ok(())
+}
+
+async fn ping(client: &Client, args: &Args) -> Result<(), Box<dyn Error>> {
+    // the client connects lazily, so without this a bad URI or unreachable server only shows up at the first write
+    match tokio::time::timeout(args.ping_timeout, client.database(&args.db).run_command(doc!{"ping": 1}, None)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(format!("could not reach MongoDB: {}", e).into()),
+        Err(_) => Err(format!("could not reach MongoDB: no answer to ping within {:?}", args.ping_timeout).into())
+    }
+}
+
+async fn supports_transactions(client: &Client) -> Result<bool, Box<dyn Error>> {
+    // transactions need a replica set member or a mongos; a standalone server reports neither
+    let hello = client.database("admin").run_command(doc!{"hello": 1}, None).await?;
+    Ok(hello.contains_key("setName") || hello.get_str("msg").is_ok_and(|msg| msg == "isdbgrid"))
+}
+
+async fn routine(mut args: Args) -> Result<(), Box<dyn Error>> {
 
     // setup /////////////////////////////////////////////////
 
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
-    let dv = &args[2];
-    let lolat = args[3].parse::<usize>()?;
-    let hilat = args[4].parse::<usize>()?;
-    let lolong = args[5].parse::<usize>()?;
-    let hilong = args[6].parse::<usize>()?;
+    let files = input_files(&args.filename).map_err(SyncError::args)?;
+    if args.output_json.is_some() {
+        degree_bounds(&mut args, &files)?;
+    }
+    if let Some(dir) = &args.output_json {
+        // nothing is read from or written to MongoDB; whatever was built is written even if a file failed
+        let stop = Stop::new(watch_signals(), args.max_runtime);
+        for (args, files) in partitions(&args, &files)? {
+            let sink = JsonSink::new(dir, &args.meta_collection, &args.data_collection, args.compress);
+            let result = ingest_all(&args, &files, &sink, &stop).await;
+            sink.write()?;
+            result?;
+        }
+        return Ok(());
+    }
 
     // mongodb setup
     // Load the MongoDB connection string from an environment variable:
     let client_uri =
-       env::var("MONGODB_URI").expect("You must set the MONGODB_URI environment var!"); 
+       env::var("MONGODB_URI").map_err(|_| SyncError::Args(String::from("You must set the MONGODB_URI environment var!")))?;
 
     // A Client is needed to connect to MongoDB:
     // An extra line of code to work around a DNS issue on Windows:
-    let options =
+    let mut options =
        ClientOptions::parse_with_resolver_config(&client_uri, ResolverConfig::cloudflare())
-          .await?;
-    let client = Client::with_options(options)?; 
-
-    // Rust structs to describe documents in the "bsose" collections
-    #[derive(Serialize, Deserialize, Debug, Clone)]
-    struct Sourcedoc {
-        source: Vec<String>,
-        file: String
-    }
-
-    #[derive(Serialize, Deserialize, Debug, Clone)]
-    struct BsoseMetadoc {
-        _id: String,
-        latitude: f64,
-        longitude: f64,
-        data_type: String,
-        date_updated_argovis: DateTime,
-        timeseries: Vec<DateTime>,
-        source: Vec<Sourcedoc>,
-        cell_area: f64,
-        ocean_depth: f64,
-        depth_r0_to_bottom: f64,
-        interior_2d_mask: bool,
-        depth_r0_to_ref_surface: f64
-    }
-
-    #[derive(Serialize, Deserialize, Debug, Clone)]
-    struct BsoseDocument {
-        _id: String,
-        metadata: Vec<String>,
-        basin: i32,
-        geolocation: Geolocation,
-        level: f64,
-        data: Vec<Vec<f64>>,
-        data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>),
-        cell_vertical_fraction: f64,
-        sea_binary_mask_at_t_locaiton: bool,
-        ctrl_vector_3d_mask: bool,
-        cell_z_size: f64,
-        reference_density_profile: f64,
-    }
-
-    #[derive(Serialize, Deserialize, Debug, Clone)]
-    struct Geolocation {
-        #[serde(rename = "type")]
-        location_type: String,
-        coordinates: [f64; 2],
+          .await.map_err(SyncError::mongo)?;
+
+    // deployment-specific settings that don't fit in the URI
+    if let Some(ca) = &args.tls_ca_file {
+        let mut tls_options = match options.tls.take() {
+            Some(Tls::Enabled(t)) => t,
+            _ => TlsOptions::default()
+        };
+        tls_options.ca_file_path = Some(PathBuf::from(ca));
+        options.tls = Some(Tls::Enabled(tls_options));
+    }
+    if let Some(source) = &args.auth_source {
+        match options.credential.as_mut() {
+            Some(credential) => credential.source = Some(source.clone()),
+            None => return Err(SyncError::Args(String::from("--auth-source requires credentials in MONGODB_URI")).into())
+        }
+    }
+    if let Some(timeout) = args.connect_timeout {
+        options.connect_timeout = Some(timeout);
     }
+    if let Some(timeout) = args.server_selection_timeout {
+        options.server_selection_timeout = Some(timeout);
+    }
+    let client = Client::with_options(options).map_err(SyncError::mongo)?; 
+    ping(&client, &args).await.map_err(SyncError::mongo)?;
+    if args.transactions && !supports_transactions(&client).await.map_err(SyncError::mongo)? {
+        warn!("MongoDB is a standalone server, which can't run transactions; writing cells without --transactions");
+        args.transactions = false;
+    }
+    degree_bounds(&mut args, &files)?;
 
     // collection objects
-    let bsose = client.database("argo").collection::<BsoseDocument>("bsose");
-    let bsose_meta = client.database("argo").collection::<BsoseMetadoc>("timeseriesMeta");
+    let concern = write_concern(&args.write_concern, args.journal, args.wtimeout).map_err(SyncError::args)?;
+    let collection_options = CollectionOptions::builder().write_concern(concern.clone()).build();
+    let bsose = client.database(&args.db).collection_with_options::<BsoseDocument>(&args.data_collection, collection_options.clone());
+    let bsose_meta = client.database(&args.db).collection_with_options::<BsoseMetadoc>(&args.meta_collection, collection_options.clone());
   
-    let file = netcdf::open(filename)?;
-
-    // basin lookup
-    let basinfile = netcdf::open("/tmp/basinmask_01.nc")?;
-    let basins = &basinfile.variable("BASIN_TAG").expect("Could not find variable 'BASIN_TAG'");
-
-    // all times recorded as days since Dec 1 2012
-    let t0 = Utc.with_ymd_and_hms(2012, 12, 1, 0, 0, 0).unwrap();
-
-    // document construction //////////////////////////////////////
-
-    // variable extraction
-    let lat = &file.variable("YC").expect("Could not find variable 'YC'");
-    let lon = &file.variable("XC").expect("Could not find variable 'XC'");
-    let depth = &file.variable("Z").expect("Could not find variable 'Z'");
-    let time = &file.variable("time").expect("Could not find variable 'time'");
-    let cell_area = &file.variable("rA").expect("Could not find variable 'rA'");
-    let ocean_depth = &file.variable("Depth").expect("Could not find variable 'Depth'");
-    let depth_r0_to_bottom = &file.variable("rLowC").expect("Could not find variable 'rLowC'");
-    let interior_2d_mask = &file.variable("maskInC").expect("Could not find variable 'maskInC'");
-    let depth_r0_to_ref_surface = &file.variable("rSurfC").expect("Could not find variable 'rSurfC'");
-    let cell_vertical_fraction = &file.variable("hFacC").expect("Could not find variable 'hFacC'");
-    let sea_binary_mask_at_t_locaiton = &file.variable("maskC").expect("Could not find variable 'maskC'");
-    let ctrl_vector_3d_mask = &file.variable("maskCtrlC").expect("Could not find variable 'maskCtrlC'");
-    let cell_z_size = &file.variable("drF").expect("Could not find variable 'drF'");
-    let reference_density_profile = &file.variable("rhoRef").expect("Could not find variable 'rhoRef'");
-    let datavar = &file.variable(dv).expect("Could not find data variable");
-    let mut units: String = String::from("");
-    let mut long_name: String = String::from("");
-    if let netcdf::AttrValue::Str(u) = datavar.attribute_value("units").unwrap()? {
-        units = u;
-    }
-    if let netcdf::AttrValue::Str(u) = datavar.attribute_value("long_name").unwrap()? {
-        long_name = u;
-    }
-
-    // construct metadata
-    let n_timesteps = time.len();
-    let mut timeseries = Vec::new();
-    for timeidx in 0..n_timesteps {
-        timeseries.push(bson::DateTime::parse_rfc3339_str((t0 + Duration::seconds(time.value::<i64, _>(timeidx)?)).to_rfc3339().replace("+00:00", "Z")).unwrap());
-    }
-
-    for latidx in lolat..hilat {
-        for lonidx in lolong..hilong {
-            let lon_val = tidylon(lon.value::<f64, _>([lonidx])?);
-
-            // construct metadata documents
-            let metaid = format!("{:.3}_{:.3}", lon_val.clone(), lat.value::<f64, _>([latidx])?);
-            bsose_meta.insert_one(BsoseMetadoc{
-                _id: metaid.clone(),
-                latitude: lat.value::<f64, _>([latidx])?,
-                longitude: lon_val,
-                data_type: String::from("BSOSE-profile"),
-                date_updated_argovis: DateTime::now(),
-                timeseries: timeseries.clone(),
-                source: vec!(
-                    Sourcedoc{
-                        source: vec!(String::from("BSOSE")),
-                        file: String::from("O2_bsoseI139_2013to2021_5dy.nc")
-                    }
-                ),
-                cell_area: cell_area.value::<f64, _>((latidx, lonidx))?,
-                ocean_depth: ocean_depth.value::<f64, _>((latidx, lonidx))?,
-                depth_r0_to_bottom: depth_r0_to_bottom.value::<f64, _>((latidx, lonidx))?,
-                interior_2d_mask: interior_2d_mask.value::<i8, _>((latidx, lonidx))? != 0,
-                depth_r0_to_ref_surface: depth_r0_to_ref_surface.value::<f64, _>((latidx, lonidx))?,
-            }, None).await?;
-
+    if args.rebuild_meta {
+        return rebuild_meta(&args, &files, &bsose, &bsose_meta).await;
+    }
+    if args.validate_only || args.fix_mask_key || args.compact || args.delete_var.is_some() || args.delete_before.is_some() || args.repair_duplicates {
+        // the tile's coordinates come from the first file
+        let file = input::open(&files[0]).map_err(SyncError::netcdf)?;
+        if args.validate_only {
+            return validate(&args, &file, &bsose, &bsose_meta).await;
+        }
+        if args.fix_mask_key {
+            return fix_mask_key(&args, &file, &bsose).await;
+        }
+        if args.compact {
+            return compact(&args, &file, &bsose, &bsose_meta).await;
         }
+        return delete(&args, &file, &bsose, &bsose_meta).await;
     }
 
+    // one deadline and signal watcher for every partition
+    let stop = Stop::new(watch_signals(), args.max_runtime);
+    for (args, files) in partitions(&args, &files)? {
+        ingest_partition(&client, &args, &files, &collection_options, &concern, &stop).await?;
+    }
+    Ok(())
+}
 
-    for latidx in lolat..hilat {
-        let lat_val = lat.value::<f64, _>([latidx])?;
-        for lonidx in lolong..hilong {
-            let lon_val = tidylon(lon.value::<f64, _>([lonidx])?);
-            // construct data documents, one timeseries per lon/lat/level triple
-            let basin = find_basin(&basins, lon_val, lat_val);
-            for levelidx in 0..depth.len() {
-                let mut datavar_profile = Vec::new();
-                for timeidx in 0..n_timesteps {
-                    datavar_profile.push(datavar.value::<f64, _>([timeidx, levelidx, latidx, lonidx])? as f64);
-                }
-                let id = format!("{:.3}_{:.3}_{:.3}", lon_val, lat_val, depth.value::<f64, _>(levelidx)?);
-
-                // Check if a document with property "_id" matching id exists
-                let existing_doc = bsose.find_one(doc! { "_id": id.clone() }, None).await?;
-
-                if let Some(mut doc) = existing_doc {
-                    // Append the value of datavar_profile to the existing "data" property
-                    doc.data.push(datavar_profile.clone());
-                    doc.data_info.0.push(dv.to_string());
-                    doc.data_info.2.push(vec!(units.clone(), long_name.clone()));
-                    let filter = doc! {"_id": id };
-                    bsose.replace_one(filter, doc, None).await?;
-                } else {
-                    if !datavar_profile.iter().all(|&x| x == 0.0) {
-                        bsose.insert_one(BsoseDocument {
-                            _id: id,
-                            metadata: vec![format!("{:.3}_{:.3}", lon_val, lat_val)],
-                            basin: basin,
-                            geolocation: Geolocation{
-                                location_type: String::from("Point"),
-                                coordinates: [lon_val, lat_val]
-                            },
-                            level: -1.0 * depth.value::<f64, _>(levelidx)?,
-                            data: vec![datavar_profile.clone()],
-                            data_info: (
-                                vec!(dv.to_string()), 
-                                vec!(String::from("units"), String::from("long_name")),
-                                vec!(
-                                    vec!(units.clone(), long_name.clone())
-                                )
-                            ),
-                            cell_vertical_fraction: cell_vertical_fraction.value::<f64, _>((levelidx, latidx, lonidx))?,
-                            sea_binary_mask_at_t_locaiton: sea_binary_mask_at_t_locaiton.value::<i8, _>((levelidx, latidx, lonidx))? != 0,
-                            ctrl_vector_3d_mask:  ctrl_vector_3d_mask.value::<i8, _>((levelidx, latidx, lonidx))? != 0,
-                            cell_z_size: cell_z_size.value::<f64, _>(levelidx)?,
-                            reference_density_profile: reference_density_profile.value::<f64, _>(levelidx)?
-                        }, None).await?;
-                    }
-                }
-            }
+async fn ingest_partition(client: &Client, args: &Args, files: &[String], collection_options: &CollectionOptions, concern: &WriteConcern, stop: &Stop) -> Result<(), Box<dyn Error>> {
+    // the ingest into one pair of collections
+    if let Some(year) = args.partition_year {
+        info!("{}: {} and {}", year, args.meta_collection, args.data_collection);
+    }
+    let bsose = client.database(&args.db).collection_with_options::<BsoseDocument>(&args.data_collection, collection_options.clone());
+    let bsose_meta = client.database(&args.db).collection_with_options::<BsoseMetadoc>(&args.meta_collection, collection_options.clone());
+    let ingest_progress = client.database(&args.db).collection::<CellRecord>("ingest_progress");
+    if args.diff {
+        // reads only, so no transactions; the run's writes stay in the diff sink
+        let sink = MongoSink{bsose: bsose, bsose_meta: bsose_meta, ingest_progress: ingest_progress, transaction: None};
+        let diff = DiffSink::new(&sink);
+        let result = ingest_all(args, files, &diff, stop).await;
+        let changes = diff.changes();
+        summary!("{}", diff::report(&changes));
+        if let Some(path) = &args.diff_json {
+            std::fs::write(path, serde_json::to_string_pretty(&changes)?)?;
         }
+        return result;
     }
-
-    Ok(())
+    if args.transactions {
+        // writes inside a transaction take their write concern from its commit rather than the collection
+        let session = client.start_session(None).await.map_err(SyncError::mongo)?;
+        let sink = MongoSink{
+            bsose: client.database(&args.db).collection::<BsoseDocument>(&args.data_collection),
+            bsose_meta: client.database(&args.db).collection::<BsoseMetadoc>(&args.meta_collection),
+            ingest_progress: ingest_progress,
+            transaction: Some(tokio::sync::Mutex::new(CellTransaction::new(session, concern.clone())))
+        };
+        return ingest_all(args, files, &sink, stop).await;
+    }
+    ingest_all(args, files, &MongoSink{bsose: bsose, bsose_meta: bsose_meta, ingest_progress: ingest_progress, transaction: None}, stop).await
 }
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+use crate::Summary;
+
+// live view of the run's Summary counters, for --metrics-addr
+pub struct Progress {
+    started: Instant,
+    // files already ingested, and the running summaries of the variables in progress
+    finished: Mutex<Summary>,
+    current: Mutex<BTreeMap<String, Summary>>
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress {
+            started: Instant::now(),
+            finished: Mutex::new(Summary::default()),
+            current: Mutex::new(BTreeMap::new())
+        }
+    }
+}
+
+impl Progress {
+    pub fn set_current(&self, variable: &str, summary: &Summary) {
+        self.current.lock().unwrap().insert(variable.to_string(), summary.clone());
+    }
+
+    pub fn finish(&self, variable: &str, summary: &Summary) {
+        // a variable's final summary for a file moves from current to finished; a failed one passes an empty summary
+        self.finished.lock().unwrap().add(summary);
+        self.current.lock().unwrap().remove(variable);
+    }
+
+    pub fn total(&self) -> Summary {
+        let mut total = self.finished.lock().unwrap().clone();
+        for summary in self.current.lock().unwrap().values() {
+            total.add(summary);
+        }
+        total
+    }
+
+    pub fn render(&self) -> String {
+        // Prometheus text exposition format
+        let total = self.total();
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let mut out = String::new();
+        let counters = [
+            ("bsose_cells_processed_total", "lat/lon cells whose data documents were written", total.cells),
+            ("bsose_cells_failed_total", "lat/lon cells skipped after an error with --continue-on-error", total.cells_failed),
+            ("bsose_metadocs_inserted_total", "metadata documents inserted", total.metadocs_inserted),
+            ("bsose_metadocs_updated_total", "metadata documents updated", total.metadocs_updated),
+            ("bsose_data_inserted_total", "data documents inserted", total.data_inserted),
+            ("bsose_data_updated_total", "data documents updated", total.data_updated),
+            ("bsose_data_skipped_total", "data documents skipped", total.data_skipped),
+            ("bsose_timesteps_merged_total", "timesteps written into data documents", total.timesteps_merged)
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+        let rate = if elapsed > 0.0 { total.cells as f64 / elapsed } else { 0.0 };
+        let _ = writeln!(out, "# HELP bsose_cells_per_second cells processed per second since the run started");
+        let _ = writeln!(out, "# TYPE bsose_cells_per_second gauge\nbsose_cells_per_second {}", rate);
+        out
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub struct MetricsServer {
+    task: tokio::task::JoinHandle<()>
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsServer {
+    pub async fn shutdown(self) {
+        // stops accepting scrapes; called once the run's final summary is known
+        self.task.abort();
+        let _ = self.task.await;
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub async fn serve(addr: &str, progress: std::sync::Arc<Progress>) -> Result<MetricsServer, Box<dyn std::error::Error>> {
+    // any request gets the metrics; binding happens here so a bad address fails before ingesting
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind(addr).await.map_err(|e| format!("could not listen on --metrics-addr {}: {}", addr, e))?;
+    let task = tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(_) => continue
+            };
+            let progress = progress.clone();
+            tokio::spawn(async move {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let body = progress.render();
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(MetricsServer{task: task})
+}
@@ -0,0 +1,154 @@
+use serde_json::{json, Value};
+
+// the shape of the documents an ingest writes, for --print-schema. Written in MongoDB's $jsonSchema dialect,
+// so each can also be installed as a collection validator. They're kept by hand next to the structs in lib.rs;
+// tests/schema.rs checks them against serialized documents so the two can't drift apart.
+
+fn date() -> Value {
+    json!({"bsonType": "date"})
+}
+
+fn strings() -> Value {
+    json!({"bsonType": "array", "items": {"bsonType": "string"}})
+}
+
+pub fn sourcedoc() -> Value {
+    json!({
+        "bsonType": "object",
+        "required": ["source", "iter", "file"],
+        "properties": {
+            "source": strings(),
+            "iter": {"bsonType": "string"},
+            "file": {"bsonType": "string", "description": "file name without its directory"},
+            "attributes": {
+                "bsonType": "object",
+                "description": "global attributes of the file named by --global-attributes; absent on documents from older runs",
+                "additionalProperties": {"bsonType": "string"}
+            }
+        },
+        "additionalProperties": false
+    })
+}
+
+pub fn metadoc() -> Value {
+    // BsoseMetadoc, one per lon/lat cell
+    json!({
+        "title": "BsoseMetadoc",
+        "bsonType": "object",
+        "required": ["_id", "latitude", "longitude", "data_type", "date_updated_argovis", "timeseries", "source",
+            "cell_area", "ocean_depth", "depth_r0_to_bottom", "interior_2d_mask", "depth_r0_to_ref_surface"],
+        "properties": {
+            "_id": {"bsonType": "string", "description": "<longitude>_<latitude>"},
+            "latitude": {"bsonType": "double"},
+            "longitude": {"bsonType": "double"},
+            "data_type": {"bsonType": "string", "description": "BSOSE-profile, BSOSE-surface or the --data-type label"},
+            "date_updated_argovis": date(),
+            "timeseries": {"bsonType": "array", "items": date(), "description": "the timestamps every data array of the cell is indexed by"},
+            "source": {"bsonType": "array", "items": sourcedoc(), "description": "one entry per (source, iter) pair ingested"},
+            "cell_area": {"bsonType": "double"},
+            "ocean_depth": {"bsonType": "double"},
+            "depth_r0_to_bottom": {"bsonType": "double"},
+            "interior_2d_mask": {"bsonType": "bool"},
+            "depth_r0_to_ref_surface": {"bsonType": "double"},
+            "chunked": {"bsonType": "array", "items": {"bsonType": "string"}, "description": "ids of the cell's data documents stored as chunks <id>_c0, <id>_c1, ...; absent when there are none"}
+        },
+        "additionalProperties": false
+    })
+}
+
+pub fn data_document() -> Value {
+    // BsoseDocument, one per lon/lat/level
+    json!({
+        "title": "BsoseDocument",
+        "bsonType": "object",
+        "required": ["_id", "metadata", "basin", "geolocation", "level", "data", "data_info", "cell_vertical_fraction",
+            "ctrl_vector_3d_mask", "cell_z_size", "reference_density_profile"],
+        "properties": {
+            "_id": {"bsonType": "string", "description": "<longitude>_<latitude>_<level>, with a suffix _c<index> on a chunk"},
+            "metadata": {"bsonType": "array", "items": {"bsonType": "string"}, "minItems": 1, "maxItems": 1, "description": "the cell's metadoc _id"},
+            "basin": {"bsonType": "int"},
+            "geolocation": {
+                "bsonType": "object",
+                "required": ["type", "coordinates"],
+                "properties": {
+                    "type": {"enum": ["Point", "Polygon"]},
+                    "coordinates": {"bsonType": "array", "description": "[longitude, latitude] of the cell center for a Point; for a Polygon, one closed ring of the cell's corners, each [longitude, latitude]"}
+                },
+                "additionalProperties": false
+            },
+            "level": {"bsonType": "double", "description": "negative of the depth in meters"},
+            "data": {
+                "bsonType": "array",
+                "items": {"bsonType": "array", "items": {"bsonType": "double"}},
+                "description": "one array per variable in data_info[0], each indexed like the metadoc timeseries; NaN for missing values. A <variable>_qc array holds the quality flags stored with --qc-var"
+            },
+            "data_info": {
+                "bsonType": "array",
+                "minItems": 3,
+                "maxItems": 3,
+                "description": "[variable names, attribute names, per-variable attribute values]: data_info[2][i][j] is attribute data_info[1][j] of variable data_info[0][i]",
+                "items": [
+                    strings(),
+                    strings(),
+                    {"bsonType": "array", "items": strings()}
+                ]
+            },
+            "cell_vertical_fraction": {"bsonType": "double"},
+            "sea_binary_mask_at_t_locaiton": {"bsonType": "bool", "description": "sea_binary_mask_at_t_location as stored before the key was corrected"},
+            "sea_binary_mask_at_t_location": {"bsonType": "bool"},
+            "ctrl_vector_3d_mask": {"bsonType": "bool"},
+            "cell_z_size": {"bsonType": "double", "description": "-9999 for surface fields"},
+            "reference_density_profile": {"bsonType": "double", "description": "-9999 for surface fields"},
+            "timeline": {
+                "bsonType": "object",
+                "description": "the metadoc timeseries the data arrays were last written against; absent on documents from older runs",
+                "required": ["length", "first", "last"],
+                "properties": {
+                    "length": {"bsonType": "long"},
+                    "first": {"bsonType": ["date", "null"]},
+                    "last": {"bsonType": ["date", "null"]}
+                },
+                "additionalProperties": false
+            },
+            "chunk": {
+                "bsonType": "object",
+                "description": "on one time-range piece of a document too large to store whole: data holds the metadoc timesteps from start on",
+                "required": ["index", "count", "start"],
+                "properties": {
+                    "index": {"bsonType": "long"},
+                    "count": {"bsonType": "long"},
+                    "start": {"bsonType": "long"}
+                },
+                "additionalProperties": false
+            }
+        },
+        "additionalProperties": false
+    })
+}
+
+pub fn cell_record() -> Value {
+    // CellRecord, in ingest_progress
+    json!({
+        "title": "CellRecord",
+        "bsonType": "object",
+        "required": ["_id", "run_id", "file", "variable", "cell", "completed"],
+        "properties": {
+            "_id": {"bsonType": "string", "description": "<run_id>/<file>/<variable>/<cell>"},
+            "run_id": {"bsonType": "string"},
+            "file": {"bsonType": "string"},
+            "variable": {"bsonType": "string"},
+            "cell": {"bsonType": "string", "description": "the cell's metadoc _id"},
+            "completed": date()
+        },
+        "additionalProperties": false
+    })
+}
+
+pub fn schemas(data_collection: &str, meta_collection: &str) -> Value {
+    // every schema, keyed by the collection it describes
+    let mut all = serde_json::Map::new();
+    all.insert(data_collection.to_string(), data_document());
+    all.insert(meta_collection.to_string(), metadoc());
+    all.insert(String::from("ingest_progress"), cell_record());
+    Value::Object(all)
+}
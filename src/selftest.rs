@@ -0,0 +1,64 @@
+use std::fmt::Debug;
+use mongodb::bson::DateTime;
+use crate::basin::{find_basin, find_basin_containing, BasinGrid};
+use crate::{covered, extend_timeline, place, tidylon};
+
+// --self-test: the pure functions an ingest depends on, run over fixed cases so a build can be checked
+// without a file or a database. The cases mirror ones in tests/; this is a smoke test, not a replacement.
+
+pub struct Check {
+    pub name: String,
+    // what went wrong, None if the check passed
+    pub failure: Option<String>
+}
+
+fn expect<T: PartialEq + Debug>(name: &str, actual: T, expected: T) -> Check {
+    Check {
+        name: name.to_string(),
+        failure: if actual == expected { None } else { Some(format!("expected {:?}, got {:?}", expected, actual)) }
+    }
+}
+
+fn day(n: i64) -> DateTime {
+    DateTime::from_millis(n * 86_400_000)
+}
+
+fn grid() -> BasinGrid {
+    // two rows of four 90 degree cells, all the way round the globe
+    BasinGrid {
+        tags: vec!(1, 2, 3, 4, 5, 6, 7, 8),
+        lats: vec!(-70.0, -60.0),
+        lons: vec!(45.0, 135.0, 225.0, 315.0)
+    }
+}
+
+pub fn run() -> Vec<Check> {
+    let grid = grid();
+    let basin = |found: Result<i32, Box<dyn std::error::Error>>| found.map_err(|e| e.to_string());
+    vec!(
+        expect("tidylon keeps the western hemisphere", tidylon(0.5), 0.5),
+        expect("tidylon keeps 180", tidylon(180.0), 180.0),
+        expect("tidylon wraps the eastern hemisphere", tidylon(190.5), -169.5),
+        expect("tidylon wraps 359.5", tidylon(359.5), -0.5),
+        expect("extend_timeline appends new times",
+            extend_timeline(&[day(1), day(2)], &[day(2), day(3)]).map_err(|e| e.to_string()),
+            Ok(vec!(day(1), day(2), day(3)))),
+        expect("extend_timeline keeps a covered timeline",
+            extend_timeline(&[day(1), day(2)], &[day(1)]).map_err(|e| e.to_string()),
+            Ok(vec!(day(1), day(2)))),
+        expect("extend_timeline sorts unsorted new times",
+            extend_timeline(&[day(1)], &[day(3), day(2)]).map_err(|e| e.to_string()),
+            Ok(vec!(day(1), day(2), day(3)))),
+        expect("extend_timeline refuses a time inside the timeline",
+            extend_timeline(&[day(1), day(3)], &[day(2)]).is_err(), true),
+        expect("place spreads a profile by timestamp",
+            format!("{:?}", place(&[day(1), day(2), day(3)], &[day(3), day(1)], &[30.0, 10.0])),
+            String::from("[10.0, NaN, 30.0]")),
+        expect("covered finds the placed timesteps", covered(&[day(1), day(2), day(3)], &[day(3), day(1)]), vec!(0, 2)),
+        expect("find_basin takes the nearest center", basin(find_basin(&grid, 50.0, -69.0)), Ok(1)),
+        expect("find_basin reads the second row", basin(find_basin(&grid, 300.0, -61.0)), Ok(8)),
+        expect("find_basin wraps across 0 degrees", basin(find_basin(&grid, -10.0, -70.0)), Ok(4)),
+        expect("find_basin_containing puts an edge in the cell above", basin(find_basin_containing(&grid, 90.0, -65.0)), Ok(6)),
+        expect("find_basin refuses NaN", find_basin(&grid, f64::NAN, -65.0).is_err(), true)
+    )
+}
@@ -0,0 +1,525 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+use mongodb::bson::{doc, DateTime};
+use mongodb::{ClientSession, Collection};
+use mongodb::options::{Acknowledgment, ReplaceOptions, TransactionOptions, WriteConcern};
+use futures::stream::TryStreamExt;
+use crate::{BsoseDocument, BsoseMetadoc, CellRecord, Sourcedoc};
+
+// where an ingest reads previously stored documents from and writes new ones to. Metadocs are only ever
+// changed by extending their timeseries or adding provenance, so those are separate operations rather
+// than whole-document replaces, which lets the MongoDB sink keep them atomic.
+#[async_trait(?Send)]
+pub trait DocumentSink {
+    async fn find_meta(&self, id: &str) -> Result<Option<BsoseMetadoc>, Box<dyn Error>>;
+    async fn insert_meta(&self, meta: BsoseMetadoc) -> Result<(), Box<dyn Error>>;
+    // replaces the metadoc's timeseries, refreshing date_updated_argovis unless it was already this one
+    async fn set_meta_timeseries(&self, id: &str, timeseries: &[DateTime]) -> Result<(), Box<dyn Error>>;
+    // records a (source, iter) pair unless it's already present, returning whether it was added
+    async fn add_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>>;
+    // makes source the metadoc's only provenance; false if it already was, by (source, iter) as above
+    async fn replace_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>>;
+    // records that a data document of the cell is stored in chunks
+    async fn add_meta_chunked(&self, id: &str, data_id: &str) -> Result<(), Box<dyn Error>>;
+    async fn find_data(&self, id: &str) -> Result<Option<BsoseDocument>, Box<dyn Error>>;
+    async fn insert_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>>;
+    async fn replace_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>>;
+    // ids of the cells recorded as done for a run, file and variable, for --resume
+    async fn completed_cells(&self, run_id: &str, file: &str, variable: &str) -> Result<HashSet<String>, Box<dyn Error>>;
+    async fn record_cell(&self, record: CellRecord) -> Result<(), Box<dyn Error>>;
+    // bracket one cell's metadoc and data document writes, for sinks that can make them all-or-nothing
+    async fn begin_cell(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    async fn commit_cell(&self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct IdLocks {
+    // an async mutex per document id being read and rewritten, so concurrent writers of one document take
+    // turns instead of the later replace dropping the earlier one's changes; unused entries are removed
+    held: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>
+}
+
+pub struct IdGuard<'a> {
+    locks: &'a IdLocks,
+    id: String,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>
+}
+
+impl IdLocks {
+    pub async fn lock(&self, id: &str) -> IdGuard<'_> {
+        // waits for any other holder of id; the guard releases it when dropped
+        let mutex = self.held.lock().unwrap().entry(id.to_string()).or_default().clone();
+        let guard = mutex.lock_owned().await;
+        IdGuard{locks: self, id: id.to_string(), guard: Some(guard)}
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for IdGuard<'_> {
+    fn drop(&mut self) {
+        // waiters hold a clone of the mutex, so an entry nobody else references is free to go
+        self.guard.take();
+        let mut held = self.locks.held.lock().unwrap();
+        if held.get(&self.id).is_some_and(|m| Arc::strong_count(m) == 1) {
+            held.remove(&self.id);
+        }
+    }
+}
+
+pub fn write_concern(w: &str, journal: bool, wtimeout: Option<Duration>) -> Result<WriteConcern, Box<dyn Error>> {
+    // w is majority or a node count; production default is majority + journal, standalone dev instances need 1 or 0
+    let w = match w {
+        "majority" => Acknowledgment::Majority,
+        n => Acknowledgment::Nodes(n.parse::<u32>().map_err(|_| format!("invalid write concern {}, expected majority or a number of nodes", n))?)
+    };
+    let unacknowledged = matches!(w, Acknowledgment::Nodes(0));
+    if unacknowledged && wtimeout.is_some() {
+        return Err("a write concern timeout needs acknowledged writes, not w:0".into());
+    }
+    // journaling can't be requested on unacknowledged writes
+    Ok(WriteConcern::builder().w(w).journal(journal && !unacknowledged).w_timeout(wtimeout).build())
+}
+
+pub struct CellTransaction {
+    // the session a cell's writes share under --transactions; the write concern applies to the commit,
+    // since operations inside a transaction can't carry their own
+    session: ClientSession,
+    options: TransactionOptions,
+    open: bool
+}
+
+impl CellTransaction {
+    pub fn new(session: ClientSession, concern: WriteConcern) -> CellTransaction {
+        CellTransaction {
+            session: session,
+            options: TransactionOptions::builder().write_concern(concern).build(),
+            open: false
+        }
+    }
+}
+
+pub struct MongoSink {
+    pub bsose: Collection<BsoseDocument>,
+    pub bsose_meta: Collection<BsoseMetadoc>,
+    pub ingest_progress: Collection<CellRecord>,
+    // set with --transactions; a transaction left open when the sink is dropped, e.g. by an error partway
+    // through a cell, is aborted by the driver
+    pub transaction: Option<tokio::sync::Mutex<CellTransaction>>
+}
+
+#[async_trait(?Send)]
+impl DocumentSink for MongoSink {
+    async fn find_meta(&self, id: &str) -> Result<Option<BsoseMetadoc>, Box<dyn Error>> {
+        match &self.transaction {
+            Some(t) => Ok(self.bsose_meta.find_one_with_session(doc! {"_id": id}, None, &mut t.lock().await.session).await?),
+            None => Ok(self.bsose_meta.find_one(doc! {"_id": id}, None).await?)
+        }
+    }
+
+    async fn insert_meta(&self, meta: BsoseMetadoc) -> Result<(), Box<dyn Error>> {
+        match &self.transaction {
+            Some(t) => self.bsose_meta.insert_one_with_session(meta, None, &mut t.lock().await.session).await?,
+            None => self.bsose_meta.insert_one(meta, None).await?
+        };
+        Ok(())
+    }
+
+    async fn set_meta_timeseries(&self, id: &str, timeseries: &[DateTime]) -> Result<(), Box<dyn Error>> {
+        let timeseries = mongodb::bson::to_bson(timeseries)?;
+        let filter = doc! {"_id": id, "timeseries": {"$ne": timeseries.clone()}};
+        let update = doc! {"$set": {"timeseries": timeseries, "date_updated_argovis": DateTime::now()}};
+        match &self.transaction {
+            Some(t) => self.bsose_meta.update_one_with_session(filter, update, None, &mut t.lock().await.session).await?,
+            None => self.bsose_meta.update_one(filter, update, None).await?
+        };
+        Ok(())
+    }
+
+    async fn add_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        // the $elemMatch guard gives $addToSet semantics on the tuple rather than the whole subdocument
+        let filter = doc! {
+            "_id": id,
+            "source": {"$not": {"$elemMatch": {"source": source.source.clone(), "iter": source.iter.clone()}}}
+        };
+        let update = doc! {
+            "$push": {"source": mongodb::bson::to_bson(source)?},
+            "$set": {"date_updated_argovis": DateTime::now()}
+        };
+        let result = match &self.transaction {
+            Some(t) => self.bsose_meta.update_one_with_session(filter, update, None, &mut t.lock().await.session).await?,
+            None => self.bsose_meta.update_one(filter, update, None).await?
+        };
+        Ok(result.modified_count > 0)
+    }
+
+    async fn replace_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        // skipped when source already is the only entry, so a rerun or a second variable leaves it alone
+        let filter = doc! {
+            "_id": id,
+            "$nor": [{"source": {"$size": 1, "$elemMatch": {"source": source.source.clone(), "iter": source.iter.clone()}}}]
+        };
+        let update = doc! {
+            "$set": {"source": [mongodb::bson::to_bson(source)?], "date_updated_argovis": DateTime::now()}
+        };
+        let result = match &self.transaction {
+            Some(t) => self.bsose_meta.update_one_with_session(filter, update, None, &mut t.lock().await.session).await?,
+            None => self.bsose_meta.update_one(filter, update, None).await?
+        };
+        Ok(result.modified_count > 0)
+    }
+
+    async fn add_meta_chunked(&self, id: &str, data_id: &str) -> Result<(), Box<dyn Error>> {
+        let update = doc! {"$addToSet": {"chunked": data_id}};
+        match &self.transaction {
+            Some(t) => self.bsose_meta.update_one_with_session(doc! {"_id": id}, update, None, &mut t.lock().await.session).await?,
+            None => self.bsose_meta.update_one(doc! {"_id": id}, update, None).await?
+        };
+        Ok(())
+    }
+
+    async fn find_data(&self, id: &str) -> Result<Option<BsoseDocument>, Box<dyn Error>> {
+        match &self.transaction {
+            Some(t) => Ok(self.bsose.find_one_with_session(doc! {"_id": id}, None, &mut t.lock().await.session).await?),
+            None => Ok(self.bsose.find_one(doc! {"_id": id}, None).await?)
+        }
+    }
+
+    async fn insert_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        match &self.transaction {
+            Some(t) => self.bsose.insert_one_with_session(doc, None, &mut t.lock().await.session).await?,
+            None => self.bsose.insert_one(doc, None).await?
+        };
+        Ok(())
+    }
+
+    async fn replace_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        let filter = doc! {"_id": doc._id.clone()};
+        match &self.transaction {
+            Some(t) => self.bsose.replace_one_with_session(filter, doc, None, &mut t.lock().await.session).await?,
+            None => self.bsose.replace_one(filter, doc, None).await?
+        };
+        Ok(())
+    }
+
+    async fn completed_cells(&self, run_id: &str, file: &str, variable: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        let records: Vec<CellRecord> = self.ingest_progress.find(doc! {"run_id": run_id, "file": file, "variable": variable}, None).await?.try_collect().await?;
+        Ok(records.into_iter().map(|r| r.cell).collect())
+    }
+
+    async fn record_cell(&self, record: CellRecord) -> Result<(), Box<dyn Error>> {
+        // inside the cell's transaction when there is one, so a cell is only recorded done if its writes commit
+        let options = ReplaceOptions::builder().upsert(true).build();
+        let filter = doc! {"_id": record._id.clone()};
+        match &self.transaction {
+            Some(t) => self.ingest_progress.replace_one_with_session(filter, record, options, &mut t.lock().await.session).await?,
+            None => self.ingest_progress.replace_one(filter, record, options).await?
+        };
+        Ok(())
+    }
+
+    async fn begin_cell(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(t) = &self.transaction {
+            let mut t = t.lock().await;
+            if t.open {
+                // a cell that never reached its commit
+                t.session.abort_transaction().await?;
+            }
+            let options = t.options.clone();
+            t.session.start_transaction(options).await?;
+            t.open = true;
+        }
+        Ok(())
+    }
+
+    async fn commit_cell(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(t) = &self.transaction {
+            let mut t = t.lock().await;
+            if t.open {
+                t.session.commit_transaction().await?;
+                t.open = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySink {
+    // documents by id, for tests and as the staging area of JsonSink
+    pub metadocs: Mutex<BTreeMap<String, BsoseMetadoc>>,
+    pub docs: Mutex<BTreeMap<String, BsoseDocument>>,
+    pub cells: Mutex<BTreeMap<String, CellRecord>>,
+    // find_meta calls, to tell what the run's metadoc cache saved
+    pub meta_lookups: AtomicUsize,
+    // a data document whose insert fails, to test how a run handles a failed write
+    pub fail_insert: Option<String>
+}
+
+#[async_trait(?Send)]
+impl DocumentSink for MemorySink {
+    async fn find_meta(&self, id: &str) -> Result<Option<BsoseMetadoc>, Box<dyn Error>> {
+        self.meta_lookups.fetch_add(1, Ordering::SeqCst);
+        Ok(self.metadocs.lock().unwrap().get(id).cloned())
+    }
+
+    async fn insert_meta(&self, meta: BsoseMetadoc) -> Result<(), Box<dyn Error>> {
+        let mut metadocs = self.metadocs.lock().unwrap();
+        if metadocs.contains_key(&meta._id) {
+            return Err(format!("duplicate metadata document {}", meta._id).into());
+        }
+        metadocs.insert(meta._id.clone(), meta);
+        Ok(())
+    }
+
+    async fn set_meta_timeseries(&self, id: &str, timeseries: &[DateTime]) -> Result<(), Box<dyn Error>> {
+        match self.metadocs.lock().unwrap().get_mut(id) {
+            Some(meta) if meta.timeseries != timeseries => {
+                meta.timeseries = timeseries.to_vec();
+                meta.date_updated_argovis = DateTime::now();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn add_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        match self.metadocs.lock().unwrap().get_mut(id) {
+            Some(meta) if !meta.source.iter().any(|s| s.source == source.source && s.iter == source.iter) => {
+                meta.source.push(source.clone());
+                meta.date_updated_argovis = DateTime::now();
+                Ok(true)
+            }
+            _ => Ok(false)
+        }
+    }
+
+    async fn replace_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        match self.metadocs.lock().unwrap().get_mut(id) {
+            Some(meta) if !matches!(meta.source.as_slice(), [only] if only.source == source.source && only.iter == source.iter) => {
+                meta.source = vec!(source.clone());
+                meta.date_updated_argovis = DateTime::now();
+                Ok(true)
+            }
+            _ => Ok(false)
+        }
+    }
+
+    async fn add_meta_chunked(&self, id: &str, data_id: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(meta) = self.metadocs.lock().unwrap().get_mut(id) {
+            if !meta.chunked.iter().any(|c| c == data_id) {
+                meta.chunked.push(data_id.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    async fn find_data(&self, id: &str) -> Result<Option<BsoseDocument>, Box<dyn Error>> {
+        Ok(self.docs.lock().unwrap().get(id).cloned())
+    }
+
+    async fn insert_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        if self.fail_insert.as_deref() == Some(doc._id.as_str()) {
+            return Err(format!("injected failure inserting {}", doc._id).into());
+        }
+        let mut docs = self.docs.lock().unwrap();
+        if docs.contains_key(&doc._id) {
+            return Err(format!("duplicate data document {}", doc._id).into());
+        }
+        docs.insert(doc._id.clone(), doc);
+        Ok(())
+    }
+
+    async fn replace_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        self.docs.lock().unwrap().insert(doc._id.clone(), doc);
+        Ok(())
+    }
+
+    async fn completed_cells(&self, run_id: &str, file: &str, variable: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        Ok(self.cells.lock().unwrap().values()
+            .filter(|r| r.run_id == run_id && r.file == file && r.variable == variable)
+            .map(|r| r.cell.clone())
+            .collect())
+    }
+
+    async fn record_cell(&self, record: CellRecord) -> Result<(), Box<dyn Error>> {
+        self.cells.lock().unwrap().insert(record._id.clone(), record);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    // --compress, for the JSON export
+    None,
+    Gzip,
+    Zstd
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Result<Compression, Box<dyn Error>> {
+        // each needs the build feature of the same name
+        let compression = match name {
+            "gzip" => Compression::Gzip,
+            "zstd" => Compression::Zstd,
+            _ => return Err(format!("invalid --compress {}, expected gzip or zstd", name).into())
+        };
+        if (compression == Compression::Gzip && !cfg!(feature = "gzip")) || (compression == Compression::Zstd && !cfg!(feature = "zstd")) {
+            return Err(format!("--compress {} needs a build with --features {}", name, name).into());
+        }
+        Ok(compression)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "ndjson",
+            Compression::Gzip => "ndjson.gz",
+            Compression::Zstd => "ndjson.zst"
+        }
+    }
+}
+
+fn write_lines<'a, W: Write, T: serde::Serialize + 'a>(out: &mut W, items: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    for item in items {
+        serde_json::to_writer(&mut *out, item)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+fn write_ndjson<'a, T: serde::Serialize + 'a>(path: &Path, compression: Compression, items: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    // one document per line, streamed through the compressor so only its buffer is held besides the documents
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    match compression {
+        Compression::None => {
+            write_lines(&mut file, items)?;
+            file.flush()?;
+        }
+        Compression::Gzip => gzip_lines(file, items)?,
+        Compression::Zstd => zstd_lines(file, items)?
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gzip")]
+fn gzip_lines<'a, T: serde::Serialize + 'a>(file: BufWriter<fs::File>, items: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    let mut out = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    write_lines(&mut out, items)?;
+    out.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_lines<'a, T: serde::Serialize + 'a>(_: BufWriter<fs::File>, _: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    Err("gzip output needs a build with --features gzip".into())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_lines<'a, T: serde::Serialize + 'a>(file: BufWriter<fs::File>, items: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    let mut out = zstd::Encoder::new(file, 0)?;
+    write_lines(&mut out, items)?;
+    out.finish()?.flush()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_lines<'a, T: serde::Serialize + 'a>(_: BufWriter<fs::File>, _: impl Iterator<Item = &'a T>) -> Result<(), Box<dyn Error>> {
+    Err("zstd output needs a build with --features zstd".into())
+}
+
+pub struct JsonSink {
+    // documents collect in memory, starting empty, and are written as newline-delimited JSON by write()
+    memory: MemorySink,
+    compression: Compression,
+    meta_path: PathBuf,
+    data_path: PathBuf
+}
+
+impl JsonSink {
+    pub fn new(dir: &str, meta_collection: &str, data_collection: &str, compression: Compression) -> JsonSink {
+        JsonSink {
+            memory: MemorySink::default(),
+            compression: compression,
+            meta_path: Path::new(dir).join(format!("{}.{}", meta_collection, compression.extension())),
+            data_path: Path::new(dir).join(format!("{}.{}", data_collection, compression.extension()))
+        }
+    }
+
+    pub fn write(&self) -> Result<(), Box<dyn Error>> {
+        // one file per collection, in id order so output can be diffed across runs
+        if let Some(dir) = self.meta_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let metadocs = self.memory.metadocs.lock().unwrap();
+        write_ndjson(&self.meta_path, self.compression, metadocs.values())?;
+        let docs = self.memory.docs.lock().unwrap();
+        write_ndjson(&self.data_path, self.compression, docs.values())?;
+        crate::info!("wrote {} metadata documents to {} and {} data documents to {}",
+            metadocs.len(), self.meta_path.display(), docs.len(), self.data_path.display());
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl DocumentSink for JsonSink {
+    async fn find_meta(&self, id: &str) -> Result<Option<BsoseMetadoc>, Box<dyn Error>> {
+        self.memory.find_meta(id).await
+    }
+
+    async fn insert_meta(&self, meta: BsoseMetadoc) -> Result<(), Box<dyn Error>> {
+        self.memory.insert_meta(meta).await
+    }
+
+    async fn set_meta_timeseries(&self, id: &str, timeseries: &[DateTime]) -> Result<(), Box<dyn Error>> {
+        self.memory.set_meta_timeseries(id, timeseries).await
+    }
+
+    async fn add_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        self.memory.add_meta_source(id, source).await
+    }
+
+    async fn replace_meta_source(&self, id: &str, source: &Sourcedoc) -> Result<bool, Box<dyn Error>> {
+        self.memory.replace_meta_source(id, source).await
+    }
+
+    async fn add_meta_chunked(&self, id: &str, data_id: &str) -> Result<(), Box<dyn Error>> {
+        self.memory.add_meta_chunked(id, data_id).await
+    }
+
+    async fn find_data(&self, id: &str) -> Result<Option<BsoseDocument>, Box<dyn Error>> {
+        self.memory.find_data(id).await
+    }
+
+    async fn insert_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        self.memory.insert_data(doc).await
+    }
+
+    async fn replace_data(&self, doc: BsoseDocument) -> Result<(), Box<dyn Error>> {
+        self.memory.replace_data(doc).await
+    }
+
+    async fn completed_cells(&self, run_id: &str, file: &str, variable: &str) -> Result<HashSet<String>, Box<dyn Error>> {
+        self.memory.completed_cells(run_id, file, variable).await
+    }
+
+    async fn record_cell(&self, record: CellRecord) -> Result<(), Box<dyn Error>> {
+        self.memory.record_cell(record).await
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use crate::error::SyncError;
+
+// the NetCDF variables read from a BSOSE file, by the name used in --var-map and their
+// default name in the MITgcm output this tool was written against
+const DEFAULTS: &[(&str, &str)] = &[
+    ("time", "time"),
+    ("lat", "YC"),
+    ("lon", "XC"),
+    ("depth", "Z"),
+    ("cell_area", "rA"),
+    ("ocean_depth", "Depth"),
+    ("depth_r0_to_bottom", "rLowC"),
+    ("interior_2d_mask", "maskInC"),
+    ("depth_r0_to_ref_surface", "rSurfC"),
+    ("cell_vertical_fraction", "hFacC"),
+    ("sea_binary_mask_at_t_location", "maskC"),
+    ("ctrl_vector_3d_mask", "maskCtrlC"),
+    ("cell_z_size", "drF"),
+    // cell_z_size for data on cell faces rather than centers, the spacing between the centers either side
+    ("interface_z_size", "drC"),
+    ("reference_density_profile", "rhoRef"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VarMap {
+    // overrides of the default variable names; anything not listed keeps its default
+    overrides: HashMap<String, String>
+}
+
+impl VarMap {
+    pub fn from_json(text: &str) -> Result<VarMap, Box<dyn Error>> {
+        // a flat JSON object such as {"lat": "YG", "lon": "XG"}
+        let overrides: HashMap<String, String> = serde_json::from_str(text).map_err(|e| format!("invalid variable map: {}", e))?;
+        for key in overrides.keys() {
+            if !DEFAULTS.iter().any(|(k, _)| k == key) {
+                let known: Vec<&str> = DEFAULTS.iter().map(|(k, _)| *k).collect();
+                return Err(format!("unknown variable map key {}, expected one of {}", key, known.join(", ")).into());
+            }
+        }
+        Ok(VarMap{overrides: overrides})
+    }
+
+    pub fn load(path: &str) -> Result<VarMap, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|e| format!("could not read --var-map {}: {}", path, e))?;
+        VarMap::from_json(&text).map_err(|e| format!("--var-map {}: {}", path, e).into())
+    }
+
+    pub fn set(&mut self, key: &str, name: &str) -> Result<(), Box<dyn Error>> {
+        // an override from its own command line flag, which mustn't contradict the --var-map file
+        match self.overrides.get(key) {
+            Some(mapped) if mapped != name => Err(format!("{} is {} in --var-map but {} on the command line", key, mapped, name).into()),
+            _ => {
+                self.overrides.insert(key.to_string(), name.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn name<'a>(&'a self, key: &'a str) -> &'a str {
+        match self.overrides.get(key) {
+            Some(name) => name,
+            None => DEFAULTS.iter().find(|(k, _)| *k == key).map(|(_, v)| *v).unwrap_or(key)
+        }
+    }
+
+    pub fn is_grid_variable(&self, name: &str) -> bool {
+        // whether name is one of the coordinate or grid metadata variables, under this mapping
+        DEFAULTS.iter().any(|(key, _)| self.name(key) == name)
+    }
+
+    pub fn variable<'f>(&self, file: &'f netcdf::File, key: &str) -> Result<netcdf::Variable<'f>, Box<dyn Error>> {
+        // look a variable up by its mapped name, saying where a remapped name came from if it's missing
+        let name = self.name(key);
+        match file.variable(name) {
+            Some(var) => Ok(var),
+            None if self.overrides.contains_key(key) => Err(SyncError::missing_variable(name, format!("Could not find variable '{}' (mapped from {} by --var-map)", name, key)).into()),
+            None => Err(SyncError::missing_variable(name, format!("Could not find variable '{}'", name)).into())
+        }
+    }
+}
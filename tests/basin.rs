@@ -0,0 +1,174 @@
+use bsose_sync::basin::{BasinGrid, BasinLookup, BasinMode, BasinTags, UNASSIGNED_BASIN, find_basin, find_basin_containing};
+
+fn grid() -> BasinGrid {
+    // 4 rows from -77.5N and a full ring of 360 longitudes from -179.5E;
+    // each cell's tag encodes its position as 1000 * latidx + lonidx
+    let mut tags = Vec::new();
+    for latidx in 0..4 {
+        for lonidx in 0..360 {
+            tags.push(1000 * latidx + lonidx);
+        }
+    }
+    BasinGrid::regular(tags, 4, 360, -77.5, -179.5)
+}
+
+#[test]
+fn nearest_corner_is_chosen() {
+    let grid = grid();
+    // cell centers map to themselves
+    assert_eq!(find_basin(&grid, -179.5, -77.5).unwrap(), 0);
+    assert_eq!(find_basin(&grid, 0.5, -76.5).unwrap(), 1180);
+    // just off a center snaps back to it
+    assert_eq!(find_basin(&grid, 0.6, -76.6).unwrap(), 1180);
+    // closer to the upper right corner
+    assert_eq!(find_basin(&grid, 1.3, -75.7).unwrap(), 2181);
+}
+
+#[test]
+fn antimeridian_wraps() {
+    let grid = grid();
+    // on either side of the antimeridian the nearest centers are 179.5 and -179.5
+    assert_eq!(find_basin(&grid, 179.9, -76.5).unwrap(), 1359);
+    assert_eq!(find_basin(&grid, -179.9, -76.5).unwrap(), 1000);
+    // -180 ties between -180.5 and -179.5; -180.5 is the last column, 179.5
+    assert_eq!(find_basin(&grid, -180.0, -76.5).unwrap(), 1359);
+    // unnormalized longitudes past 180 wrap to the first column
+    assert_eq!(find_basin(&grid, 180.2, -76.5).unwrap(), 1000);
+}
+
+#[test]
+fn antimeridian_inside_the_grid() {
+    // a ring starting at 0.5E puts the antimeridian between columns 179 (179.5) and 180 (180.5, or -179.5)
+    let grid = BasinGrid::regular((0..360).collect(), 1, 360, -70.5, 0.5);
+    // just west of 180
+    assert_eq!(find_basin(&grid, 179.6, -70.5).unwrap(), 179);
+    assert_eq!(find_basin(&grid, 179.99, -70.5).unwrap(), 179);
+    // just east of 180, given as negative longitudes
+    assert_eq!(find_basin(&grid, -179.6, -70.5).unwrap(), 180);
+    assert_eq!(find_basin(&grid, -179.99, -70.5).unwrap(), 180);
+}
+
+#[test]
+fn poleward_of_the_grid_clamps_to_the_edge_rows() {
+    let grid = grid();
+    assert_eq!(find_basin(&grid, 0.5, -78.4).unwrap(), 180);
+    assert_eq!(find_basin(&grid, 0.5, -74.0).unwrap(), 3180);
+    assert_eq!(find_basin(&grid, 0.5, 89.9).unwrap(), 3180);
+}
+
+#[test]
+fn bad_input_is_an_error_not_a_panic() {
+    let grid = grid();
+    assert!(find_basin(&grid, f64::NAN, -76.5).is_err());
+    let empty = BasinGrid::regular(vec!(), 0, 0, -77.5, -179.5);
+    assert!(find_basin(&empty, 0.5, -76.5).is_err());
+}
+
+#[test]
+fn origin_is_read_from_the_mask_file() {
+    // this mask starts at 0.5E, -70.5N rather than -179.5E, -77.5N
+    let file = netcdf::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_offset_fixture.nc")).unwrap();
+    let grid = BasinGrid::from_file(&file).unwrap();
+    assert_eq!((grid.lats[0], grid.lons[0]), (-70.5, 0.5));
+    assert_eq!(find_basin(&grid, 10.5, -68.5).unwrap(), 2010);
+    assert_eq!(find_basin(&grid, -0.5, -70.5).unwrap(), 359);
+}
+
+#[test]
+fn default_mask_origin() {
+    let file = netcdf::open(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc")).unwrap();
+    let grid = BasinGrid::from_file(&file).unwrap();
+    assert_eq!((grid.lats.len(), grid.lons.len(), grid.lats[0], grid.lons[0]), (20, 360, -77.5, -179.5));
+}
+
+#[test]
+fn modes_agree_away_from_cell_edges() {
+    let grid = grid();
+    for (lon, lat) in [(0.6, -76.6), (1.3, -75.7), (-120.2, -77.1), (179.9, -76.5)] {
+        assert_eq!(find_basin(&grid, lon, lat).unwrap(), find_basin_containing(&grid, lon, lat).unwrap());
+    }
+}
+
+#[test]
+fn modes_differ_on_a_cell_edge() {
+    let grid = grid();
+    // 0.0E lies on the edge between the -0.5E and 0.5E cells
+    assert_eq!(BasinMode::NearestCorner.find(&grid, 0.0, -76.5).unwrap(), 1179);
+    assert_eq!(BasinMode::ContainingCell.find(&grid, 0.0, -76.5).unwrap(), 1180);
+    // -76.0N lies on the edge between the -76.5N and -75.5N rows
+    assert_eq!(BasinMode::NearestCorner.find(&grid, 0.5, -76.0).unwrap(), 1180);
+    assert_eq!(BasinMode::ContainingCell.find(&grid, 0.5, -76.0).unwrap(), 2180);
+}
+
+#[test]
+fn basin_mode_names() {
+    assert_eq!(BasinMode::parse("nearest").unwrap(), BasinMode::NearestCorner);
+    assert_eq!(BasinMode::parse("cell").unwrap(), BasinMode::ContainingCell);
+    assert!(BasinMode::parse("bilinear").is_err());
+}
+
+#[test]
+fn known_basin_tags_pass() {
+    let tags = BasinTags::parse("1-58").unwrap();
+    assert_eq!(tags.check(1, 0.5, -76.5).unwrap(), 1);
+    assert_eq!(tags.check(58, 0.5, -76.5).unwrap(), 58);
+    let tags = BasinTags::parse("1-10, 12").unwrap();
+    assert_eq!(tags.check(12, 0.5, -76.5).unwrap(), 12);
+    assert_eq!(tags.to_string(), "1-10,12");
+}
+
+#[test]
+fn unexpected_basin_tags_are_errors() {
+    let tags = BasinTags::parse("1-10,12").unwrap();
+    assert!(tags.check(11, 0.5, -76.5).is_err());
+    assert!(tags.check(59, 0.5, -76.5).is_err());
+    // -1 is no longer special, so a mask still using it for land is caught
+    assert!(tags.check(-1, 0.5, -76.5).is_err());
+    let error = tags.check(1180, 0.5, -76.5).unwrap_err().to_string();
+    assert!(error.contains("1180"), "{}", error);
+}
+
+#[test]
+fn unassigned_points_pass_any_tag_set() {
+    let tags = BasinTags::parse("1-58").unwrap();
+    assert_eq!(tags.check(UNASSIGNED_BASIN, 0.5, -76.5).unwrap(), UNASSIGNED_BASIN);
+    let grid = BasinGrid::regular(vec!(UNASSIGNED_BASIN, 7), 1, 2, -70.5, 0.5);
+    assert_eq!(tags.check(find_basin(&grid, 0.5, -70.5).unwrap(), 0.5, -70.5).unwrap(), UNASSIGNED_BASIN);
+    assert_eq!(tags.check(find_basin(&grid, 1.5, -70.5).unwrap(), 1.5, -70.5).unwrap(), 7);
+}
+
+#[test]
+fn basin_tag_lists() {
+    assert!(BasinTags::parse("").is_err());
+    assert!(BasinTags::parse("10-1").is_err());
+    assert!(BasinTags::parse("one").is_err());
+    // 0 can't be a real basin
+    assert!(BasinTags::parse("0-58").is_err());
+    assert!(BasinTags::parse("-5").unwrap().contains(-5));
+}
+
+#[test]
+fn lookups_combine_mode_and_tag_checks() {
+    let lookup = BasinLookup{grid: grid(), mode: BasinMode::ContainingCell, tags: BasinTags::parse("1-2000").unwrap()};
+    // on the edge between two centers the containing cell is the north-east one
+    assert_eq!(lookup.basin(1.0, -76.5).unwrap(), 1181);
+    // 2181 is a real tag in this grid, just not one the lookup accepts
+    let e = lookup.basin(1.3, -75.7).unwrap_err().to_string();
+    assert!(e.contains("2181"), "{}", e);
+}
+
+#[test]
+fn latitude_rows_can_be_unevenly_spaced() {
+    // rows closing in toward the pole, as on the native BSOSE grid, on a ring of 1 degree columns
+    let mut grid = BasinGrid::regular((0..4).flat_map(|row| (0..360).map(move |col| 1000 * row + col)).collect(), 4, 360, 0.0, -179.5);
+    grid.lats = vec!(-78.0, -77.6, -77.0, -75.0);
+    assert_eq!(find_basin(&grid, 0.5, -77.75).unwrap(), 1180);
+    assert_eq!(find_basin(&grid, 0.5, -77.35).unwrap(), 1180);
+    assert_eq!(find_basin(&grid, 0.5, -75.9).unwrap(), 3180);
+    // containing rows run between the midpoints -77.8, -77.3 and -76.0
+    assert_eq!(find_basin_containing(&grid, 0.5, -77.79).unwrap(), 1180);
+    assert_eq!(find_basin_containing(&grid, 0.5, -77.31).unwrap(), 1180);
+    assert_eq!(find_basin_containing(&grid, 0.5, -77.29).unwrap(), 2180);
+    assert_eq!(find_basin_containing(&grid, 0.5, -75.99).unwrap(), 3180);
+    assert_eq!(find_basin_containing(&grid, 0.5, -79.0).unwrap(), 180);
+}
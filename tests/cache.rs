@@ -0,0 +1,76 @@
+use bsose_sync::BsoseMetadoc;
+use bsose_sync::cache::MetaCache;
+use bson::DateTime;
+
+fn timeseries(n: i64) -> Vec<DateTime> {
+    (0..n).map(|i| DateTime::from_millis(i * 86400000)).collect()
+}
+
+fn meta(id: &str, n: i64) -> BsoseMetadoc {
+    BsoseMetadoc {
+        _id: id.to_string(),
+        latitude: -70.0,
+        longitude: 0.5,
+        data_type: String::from("BSOSE-profile"),
+        date_updated_argovis: DateTime::from_millis(0),
+        timeseries: timeseries(n),
+        source: Vec::new(),
+        cell_area: 0.0,
+        ocean_depth: 0.0,
+        depth_r0_to_bottom: 0.0,
+        interior_2d_mask: true,
+        depth_r0_to_ref_surface: 0.0,
+        chunked: Vec::new()
+    }
+}
+
+#[test]
+fn hits_return_what_was_stored() {
+    let mut cache = MetaCache::new(2);
+    cache.put(meta("0.500_-70.000", 3));
+    assert_eq!(cache.get("0.500_-70.000"), Some(&meta("0.500_-70.000", 3)));
+    assert_eq!(cache.get("1.500_-70.000"), None);
+    assert_eq!((cache.hits, cache.misses), (1, 1));
+}
+
+#[test]
+fn least_recently_used_is_evicted() {
+    let mut cache = MetaCache::new(2);
+    cache.put(meta("a", 1));
+    cache.put(meta("b", 2));
+    cache.get("a");
+    cache.put(meta("c", 3));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get("b").is_none());
+    assert_eq!(cache.get("a"), Some(&meta("a", 1)));
+    assert_eq!(cache.get("c"), Some(&meta("c", 3)));
+}
+
+#[test]
+fn put_replaces_a_rewritten_metadoc() {
+    let mut cache = MetaCache::new(2);
+    cache.put(meta("a", 3));
+    cache.put(meta("a", 5));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get("a"), Some(&meta("a", 5)));
+}
+
+#[test]
+fn extended_timeseries_replace_the_cached_one() {
+    let mut cache = MetaCache::new(2);
+    cache.put(meta("a", 3));
+    cache.set_timeseries("a", &timeseries(5));
+    cache.set_timeseries("b", &timeseries(5));
+    assert_eq!(cache.get("a"), Some(&meta("a", 5)));
+    assert!(cache.get("b").is_none());
+    cache.remove("a");
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn zero_capacity_caches_nothing() {
+    let mut cache = MetaCache::new(0);
+    cache.put(meta("a", 3));
+    assert!(cache.is_empty());
+    assert!(cache.get("a").is_none());
+}
@@ -0,0 +1,68 @@
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+const BASINS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc");
+
+#[test]
+fn unreachable_server_fails_before_reading_files() {
+    // nothing listens on port 1, and the input doesn't exist: a NetCDF error (3) would mean the files were
+    // opened before the connection was checked
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args(["/nonexistent/bsose.nc", "TRAC02", "0", "2", "0", "3"])
+        .args(["--basin-file", BASINS, "--ping-timeout", "2s"])
+        .env("MONGODB_URI", "mongodb://127.0.0.1:1/?connectTimeoutMS=500")
+        .output()
+        .unwrap();
+    let elapsed = started.elapsed();
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not reach MongoDB"), "{}", stderr);
+    // well short of the driver's 30s server selection timeout
+    assert!(elapsed < Duration::from_secs(15), "took {:?}", elapsed);
+}
+
+#[test]
+fn ping_timeout_must_be_a_duration() {
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args(["/nonexistent/bsose.nc", "TRAC02", "0", "2", "0", "3"])
+        .args(["--ping-timeout", "soon"])
+        .env("MONGODB_URI", "mongodb://127.0.0.1:1")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn server_selection_timeout_bounds_the_wait() {
+    // with the ping allowed far longer, the driver itself gives up after the server selection timeout
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args(["/nonexistent/bsose.nc", "TRAC02", "0", "2", "0", "3"])
+        .args(["--basin-file", BASINS, "--ping-timeout", "60s"])
+        .args(["--connect-timeout", "1s", "--server-selection-timeout", "1s"])
+        .env("MONGODB_URI", "mongodb://127.0.0.1:1/")
+        .output()
+        .unwrap();
+    let elapsed = started.elapsed();
+
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not reach MongoDB") && !stderr.contains("no answer to ping"), "{}", stderr);
+    assert!(elapsed < Duration::from_secs(15), "took {:?}", elapsed);
+}
+
+#[test]
+fn client_timeouts_must_be_durations() {
+    for flag in ["--connect-timeout", "--server-selection-timeout"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args(["/nonexistent/bsose.nc", "TRAC02", "0", "2", "0", "3"])
+            .args([flag, "soon"])
+            .env("MONGODB_URI", "mongodb://127.0.0.1:1")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).contains(flag));
+    }
+}
@@ -0,0 +1,137 @@
+use bsose_sync::{check_coordinates, LonConvention, check_id_collisions, nearest_index, containing_index, degree_range, shuffle};
+use bsose_sync::error::SyncError;
+
+// the fixture grid, see tests/fixtures/bsose_fixture.cdl
+const XC: [f64; 3] = [0.5, 190.5, 359.5];
+const YC: [f64; 2] = [-70.0, -60.0];
+
+#[test]
+fn boundaries_are_accepted() {
+    assert!(check_coordinates("a", -180.0, -90.0).is_ok());
+    assert!(check_coordinates("a", 179.999, 90.0).is_ok());
+    assert!(check_coordinates("a", 0.0, -77.9).is_ok());
+}
+
+#[test]
+fn out_of_range_latitude_is_rejected() {
+    let err = check_coordinates("0.500_-90.500", 0.5, -90.5).unwrap_err();
+    assert!(matches!(err.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))));
+    assert!(err.to_string().contains("0.500_-90.500"));
+    assert!(check_coordinates("a", 0.5, 90.01).is_err());
+}
+
+#[test]
+fn out_of_range_longitude_is_rejected() {
+    // 180 belongs to the other end of the half-open range
+    assert!(check_coordinates("a", 180.0, 0.0).is_err());
+    assert!(check_coordinates("a", -180.5, 0.0).is_err());
+    assert!(check_coordinates("a", 359.5, 0.0).is_err());
+}
+
+#[test]
+fn longitude_conventions_wrap_and_check_their_own_range() {
+    let (pm180, zero360) = (LonConvention::Pm180, LonConvention::Zero360);
+    assert_eq!(LonConvention::parse("zero360").unwrap(), zero360);
+    assert!(LonConvention::parse("0-360").is_err());
+    assert_eq!(XC.map(|lon| pm180.apply(lon)), [0.5, -169.5, -0.5]);
+    assert_eq!(XC.map(|lon| zero360.apply(lon)), XC);
+    assert_eq!(zero360.apply(-4.5), 355.5);
+    assert_eq!(zero360.apply(360.0), 0.0);
+
+    assert!(zero360.check("a", 0.0, -90.0).is_ok());
+    assert!(zero360.check("a", 359.5, YC[0]).is_ok());
+    assert!(zero360.check("a", 360.0, 0.0).is_err());
+    assert!(zero360.check("a", -0.5, 0.0).is_err());
+    assert!(zero360.check("a", 190.5, -90.5).is_err());
+    assert!(zero360.check("a", f64::NAN, 0.0).is_err());
+    assert!(pm180.check("a", 190.5, 0.0).is_err());
+    assert!(pm180.check("a", -169.5, 0.0).is_ok());
+}
+
+#[test]
+fn nan_is_rejected() {
+    assert!(check_coordinates("a", f64::NAN, 0.0).is_err());
+    assert!(check_coordinates("a", 0.0, f64::NAN).is_err());
+}
+
+#[test]
+fn nearest_grid_points() {
+    assert_eq!(nearest_index(&YC, -65.1, false), 0);
+    assert_eq!(nearest_index(&YC, -64.9, false), 1);
+    // -170 is 190 on a 0-360 grid, and -0.2 is closer to 359.5 than to 0.5
+    assert_eq!(nearest_index(&XC, -170.0, true), 1);
+    assert_eq!(nearest_index(&XC, -0.2, true), 2);
+    assert_eq!(nearest_index(&XC, -0.2, false), 0);
+}
+
+#[test]
+fn uneven_latitudes() {
+    // spacing shrinking toward the pole, and the same axis stored north to south
+    let lats = [-78.0, -77.5, -76.8, -75.9, -74.7, -73.2, -71.3, -69.0];
+    assert_eq!(nearest_index(&lats, -80.0, false), 0);
+    assert_eq!(nearest_index(&lats, -77.0, false), 2);
+    assert_eq!(nearest_index(&lats, -75.2, false), 4);
+    assert_eq!(nearest_index(&lats, -72.3, false), 5);
+    assert_eq!(nearest_index(&lats, -60.0, false), 7);
+    let reversed: Vec<f64> = lats.iter().rev().copied().collect();
+    assert_eq!(nearest_index(&reversed, -75.2, false), 3);
+    // every value agrees with a scan of the whole axis
+    for i in 0..=120 {
+        let value = -79.0 + 0.1 * i as f64;
+        let scan = (0..lats.len()).min_by(|&a, &b| (lats[a] - value).abs().partial_cmp(&(lats[b] - value).abs()).unwrap()).unwrap();
+        assert_eq!(nearest_index(&lats, value, false), scan, "{}", value);
+    }
+    assert_eq!(containing_index(&lats, -76.34), 3);
+    assert_eq!(containing_index(&lats, -76.36), 2);
+    assert_eq!(degree_range(&lats, Some(-77.6), Some(-73.0), false).unwrap(), (1, 6));
+}
+
+#[test]
+fn degree_bounds_to_indexes() {
+    assert_eq!(degree_range(&YC, Some(-70.0), Some(-60.0), false).unwrap(), (0, 2));
+    assert_eq!(degree_range(&YC, Some(-61.0), None, false).unwrap(), (1, 2));
+    assert_eq!(degree_range(&YC, None, None, false).unwrap(), (0, 2));
+    assert_eq!(degree_range(&XC, Some(-170.0), Some(359.0), true).unwrap(), (1, 3));
+    assert_eq!(degree_range(&XC, Some(10.0), Some(-170.0), true).unwrap(), (0, 2));
+    // crossing the end of the grid can't be a single index range
+    assert!(degree_range(&XC, Some(200.0), Some(10.0), true).is_err());
+}
+
+#[test]
+fn shuffled_cells_cover_the_tile_once() {
+    let mut tile = Vec::new();
+    for lat in 0..20 {
+        for lon in 0..30 {
+            tile.push((lat, lon));
+        }
+    }
+    let mut cells = tile.clone();
+    shuffle(&mut cells, 42);
+    assert_ne!(cells, tile);
+
+    // the same seed gives the same order, another seed a different one
+    let mut again = tile.clone();
+    shuffle(&mut again, 42);
+    assert_eq!(again, cells);
+    let mut other = tile.clone();
+    shuffle(&mut other, 43);
+    assert_ne!(other, cells);
+
+    cells.sort();
+    assert_eq!(cells, tile);
+}
+
+#[test]
+fn levels_that_round_together_are_rejected() {
+    // closely spaced interfaces, 0.4mm apart
+    let levels = [-2.1, -6.7, -6.7004, -12.15];
+    let e = check_id_collisions(3, &XC, &YC, &levels).unwrap_err();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))));
+    let e = e.to_string();
+    assert!(e.contains("level values -6.7 (index 1) and -6.7004 (index 2)"), "{}", e);
+    assert!(e.contains("-6.700"), "{}", e);
+
+    // one more decimal place tells them apart
+    assert!(check_id_collisions(4, &XC, &YC, &levels).is_ok());
+    assert!(check_id_collisions(3, &XC, &YC, &[-2.1, -6.7, -12.15]).is_ok());
+}
@@ -0,0 +1,86 @@
+use bsose_sync::{Extractor, Sourcedoc, LatIdx, LonIdx, LevelIdx, pad_data};
+use bsose_sync::diff::{Change, DiffSink, report};
+use bsose_sync::sink::{DocumentSink, MemorySink};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+
+fn sourcedoc(iter: &str) -> Sourcedoc {
+    Sourcedoc{
+        source: vec!(String::from("BSOSE")),
+        iter: String::from(iter),
+        file: String::from("bsose_fixture.nc"),
+        attributes: Default::default()
+    }
+}
+
+#[tokio::test]
+async fn diff_reports_changes_without_writing() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+    assert_eq!(profile, vec!(1.0, 2.0, 3.0));
+
+    // stored: the first two timesteps of one cell, the second of them missing and the first since corrected
+    let seeded = MemorySink::default();
+    let mut meta = extractor.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc("156")).unwrap();
+    meta.timeseries.truncate(2);
+    seeded.insert_meta(meta.clone()).await.unwrap();
+    let stored = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, f64::NAN)).unwrap();
+    seeded.insert_data(stored.clone()).await.unwrap();
+
+    // the writes an overwriting ingest of iteration 157 makes
+    let diff = DiffSink::new(&seeded);
+    assert_eq!(diff.find_meta(&meta._id).await.unwrap(), Some(meta.clone()));
+    diff.set_meta_timeseries(&meta._id, &extractor.timeseries).await.unwrap();
+    assert!(diff.add_meta_source(&meta._id, &sourcedoc("157")).await.unwrap());
+    let mut doc = diff.find_data(&stored._id).await.unwrap().unwrap();
+    pad_data(&mut doc, 3);
+    extractor.merge_at(&mut doc, profile.clone(), true, &[0, 1, 2]);
+    doc.data.push(vec!(0.5, 1.0, 1.5));
+    doc.data_info.0.push(String::from("THETA"));
+    doc.data_info.2.push(vec!(String::from("degC"), String::from("Potential Temperature")));
+    diff.replace_data(doc).await.unwrap();
+    let new_doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(0), LonIdx(0), 10, profile).unwrap();
+    diff.insert_data(new_doc.clone()).await.unwrap();
+    // a stored document the run found but left alone isn't reported
+    diff.replace_data(diff.find_data(&stored._id).await.unwrap().unwrap()).await.unwrap();
+
+    let changes = diff.changes();
+    assert_eq!(changes, vec!(
+        Change{
+            id: meta._id.clone(),
+            kind: String::from("meta"),
+            timesteps: (2, 3),
+            sources_added: vec!(String::from("BSOSE/157")),
+            ..Default::default()
+        },
+        Change{
+            id: stored._id.clone(),
+            kind: String::from("data"),
+            timesteps: (2, 3),
+            variables_added: vec!(String::from("THETA")),
+            values_filled: 1,
+            values_overwritten: 1,
+            ..Default::default()
+        },
+        Change{
+            id: new_doc._id.clone(),
+            kind: String::from("data"),
+            new: true,
+            timesteps: (0, 3),
+            variables_added: vec!(String::from("TRAC02")),
+            ..Default::default()
+        }
+    ));
+    let text = report(&changes);
+    assert!(text.contains("data 0.500_-70.000_-2.100: timesteps 2 -> 3; variables added THETA; 1 values filled; 1 values overwritten"), "{}", text);
+    assert!(text.ends_with("would insert 0 metadata and 1 data documents, and change 1 metadata and 1 data documents"), "{}", text);
+    let json: serde_json::Value = serde_json::to_value(&changes).unwrap();
+    assert_eq!(json[2]["new"], true);
+
+    // the seeded sink still holds what it started with
+    assert_eq!(seeded.find_meta(&meta._id).await.unwrap(), Some(meta));
+    let unchanged = seeded.find_data(&stored._id).await.unwrap().unwrap();
+    assert!(unchanged.data[0][1].is_nan() && unchanged.data[0][0] == 9.0 && unchanged.data.len() == 1);
+    assert!(seeded.find_data(&new_doc._id).await.unwrap().is_none());
+}
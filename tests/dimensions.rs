@@ -0,0 +1,23 @@
+use std::process::{Command, Output};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+const BASINS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc");
+
+fn ingest(variable: &str) -> Output {
+    // written to files, so the check runs without a server
+    let dir = std::env::temp_dir().join(format!("bsose-dimensions-{}-{}", variable, std::process::id()));
+    return Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, variable, "0", "2", "0", "3", "--basin-file", BASINS])
+        .arg("--output-json")
+        .arg(&dir)
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn grid_variables_are_rejected() {
+    let out = ingest("rA");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(out.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("rA has dimensions [YC, XC] and isn't a data variable"), "{}", stderr);
+}
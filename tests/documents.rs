@@ -0,0 +1,1014 @@
+use bsose_sync::{Extractor, Coordinates, qc_column, check_center_collisions, cell_edges, Sourcedoc, Chunk, chunk_id, document_size, split_document, check_meta_coordinates, SURFACE_SENTINEL, check_data_lengths, check_timeline, set_metadata, TimelineRef, remove_variable, populated_timesteps, valid_fraction, timesteps_since, select, parse_date, validate_document, extend_timeline, covered, place, placements, pad_data, reconcile, OnInconsistent, OnBadTime, OutOfRange, LonConvention, apply_valid_range, DryCells, Reconciled, LatIdx, LonIdx, LevelIdx, z_sign, global_attributes, Staggering};
+use bsose_sync::varmap::VarMap;
+use bsose_sync::error::SyncError;
+use bson::DateTime;
+
+// see tests/fixtures/bsose_fixture.cdl for the fixture's contents
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+// the same grid with Z stored as positive-down depths
+const POSITIVE_DOWN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_positive_down_fixture.nc");
+// the same grid with a first time offset of -40000000 seconds, in August 2011
+// hFacC is 0.5 at (Z 1, YC 0, XC 0) and 0 at (Z 1, YC 0, XC 1), where TRAC02 still holds values, as well as
+// on the land cell; TRAC02 is all zero at (Z 0, YC 1, XC 0), which is ocean
+const BATHYMETRY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_bathymetry_fixture.nc");
+const NEGATIVE_TIME: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_negative_time_fixture.nc");
+// the same as FIXTURE except for TRAC02's units, mmol O/m^3
+const UNITS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_units_fixture.nc");
+// the base fixture's values on a curvilinear grid: XC and YC run over [j, i], each row shifted 5 degrees west
+// and each column 2 degrees north of the last
+const CURVILINEAR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_curvilinear_fixture.nc");
+// the base fixture plus WVEL on the top cell faces Zl = 0, -4.2, with drC = 2.1, 4.6, 2.5 along Zp1
+const WPOINT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_wpoint_fixture.nc");
+// the base fixture plus BARE, numbered 0 to 35 in storage order, with no attributes at all
+const BARE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_bare_fixture.nc");
+// the base fixture plus LEVEL_LAST over [time, YC, XC, Z] and SWAPPED over [time, XC, YC]
+const PERMUTED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_permuted_fixture.nc");
+// the same as FIXTURE except that TRAC02 declares valid_max 1002, below its (Z 0, YC 0, XC 1) profile's last value
+const VALID_RANGE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_valid_range_fixture.nc");
+// the base fixture plus TRAC02_FLAG, numbered 0 to 35 in storage order, and SURFACE_FLAG on the [time, YC, XC] grid
+const QC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_qc_fixture.nc");
+// the base fixture plus a 2D latitude YC2 over [YC, XC] next to the 1D XC
+const MIXED_GRID: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_mixed_grid_fixture.nc");
+
+fn sourcedoc() -> Sourcedoc {
+    Sourcedoc{
+        source: vec!(String::from("BSOSE")),
+        iter: String::from("156"),
+        file: String::from("bsose_fixture.nc"),
+        attributes: Default::default()
+    }
+}
+
+#[test]
+fn metadoc_for_known_cell() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), sourcedoc()).unwrap();
+
+    assert_eq!(meta._id, "-169.500_-70.000");
+    assert_eq!(meta.longitude, -169.5);
+    assert_eq!(meta.latitude, -70.0);
+    assert_eq!(meta.data_type, "BSOSE-profile");
+    assert_eq!(meta.timeseries, vec!(
+        DateTime::parse_rfc3339_str("2012-12-06T00:00:00Z").unwrap(),
+        DateTime::parse_rfc3339_str("2012-12-11T00:00:00Z").unwrap(),
+        DateTime::parse_rfc3339_str("2012-12-16T00:00:00Z").unwrap()
+    ));
+    assert_eq!(meta.source, vec!(sourcedoc()));
+    assert_eq!(meta.cell_area, 1.0e8 + 1.0);
+    assert_eq!(meta.ocean_depth, 4001.0);
+    assert_eq!(meta.depth_r0_to_bottom, -4001.0);
+    assert!(meta.interior_2d_mask);
+    assert_eq!(meta.depth_r0_to_ref_surface, 0.0);
+}
+
+#[test]
+fn longitude_convention_sets_stored_ids_and_geolocation() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap();
+
+    // pm180 is the default
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(1), 10, profile.clone()).unwrap();
+    assert_eq!(doc._id, "-169.500_-70.000_-2.100");
+    assert_eq!(doc.geolocation.coordinates, Coordinates::Point([-169.5, -70.0]));
+
+    extractor.lon_convention(LonConvention::Zero360);
+    let meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), sourcedoc()).unwrap();
+    assert_eq!(meta._id, "190.500_-70.000");
+    assert_eq!(meta.longitude, 190.5);
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(1), 10, profile).unwrap();
+    assert_eq!(doc._id, "190.500_-70.000_-2.100");
+    assert_eq!(doc.metadata, vec!(String::from("190.500_-70.000")));
+    assert_eq!(doc.geolocation.coordinates, Coordinates::Point([190.5, -70.0]));
+    assert!(validate_document(&doc, Some(&meta), 3).is_empty());
+    // the cell at 0.5 is the same under both
+    assert_eq!(extractor.center(LatIdx(0), LonIdx(0)).unwrap(), (0.5, -70.0));
+}
+
+#[test]
+fn coordinates_are_not_data_variables() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    for name in ["XC", "time", "Depth", "hFacC"] {
+        let err = Extractor::new(&file, name).err().unwrap().to_string();
+        assert!(err.contains("isn't a data variable"), "{}: {}", name, err);
+        assert!(err.contains("data variables in this file: TRAC02, THETA, ETAN"), "{}: {}", name, err);
+    }
+    let err = Extractor::new(&file, "SALT").err().unwrap().to_string();
+    assert!(err.contains("Could not find data variable SALT; data variables in this file: TRAC02, THETA, ETAN"));
+}
+
+#[test]
+fn missing_variables_are_reported_by_name() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let e = Extractor::new(&file, "SALT").err().unwrap();
+    match e.downcast_ref::<SyncError>() {
+        Some(SyncError::MissingVariable{name, ..}) => assert_eq!(name, "SALT"),
+        other => panic!("expected MissingVariable, got {:?}", other)
+    }
+
+    // grid variables renamed to something the file doesn't have
+    let names = VarMap::from_json(r#"{"lat": "YG"}"#).unwrap();
+    let e = Extractor::with_var_map(&file, "TRAC02", &names).err().unwrap();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::MissingVariable{name, ..}) if name == "YG"), "{}", e);
+
+    // a variable that exists but isn't gridded fails validation
+    let e = Extractor::new(&file, "XC").err().unwrap();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))), "{}", e);
+}
+
+#[test]
+fn netcdf_failures_keep_their_category() {
+    // a read error passed up unwrapped is a NetCDF failure, even where the caller expected bad arguments
+    let missing = netcdf::open("tests/fixtures/missing.nc").err().unwrap();
+    assert!(matches!(SyncError::args(missing), SyncError::Netcdf(_)));
+    assert!(matches!(SyncError::args("bad flag"), SyncError::Args(_)));
+
+    // while lib errors carry their own
+    let e = parse_date("2012-13-01").unwrap_err();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Args(_))), "{}", e);
+    let e = OnBadTime::parse("sometimes").unwrap_err();
+    assert!(matches!(SyncError::netcdf(e), SyncError::Args(_)));
+}
+
+#[test]
+fn land_metadoc_stores_nan() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let land = extractor.metadoc(3, LatIdx(1), LonIdx(2), sourcedoc()).unwrap();
+    assert!(!land.interior_2d_mask);
+    assert!(land.cell_area.is_nan());
+    assert!(land.ocean_depth.is_nan());
+    assert!(land.depth_r0_to_bottom.is_nan());
+    assert!(land.depth_r0_to_ref_surface.is_nan());
+
+    // its ocean neighbour keeps real values
+    let ocean = extractor.metadoc(3, LatIdx(1), LonIdx(1), sourcedoc()).unwrap();
+    assert!(ocean.interior_2d_mask);
+    assert_eq!(ocean.cell_area, 2.0e8 + 1.0);
+    assert_eq!(ocean.ocean_depth, 4101.0);
+}
+
+#[test]
+fn new_document_for_known_cell() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap();
+    assert_eq!(profile, vec!(111.0, 112.0, 113.0));
+
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, profile).unwrap();
+    assert_eq!(doc._id, "0.500_-60.000_-6.700");
+    assert_eq!(doc.metadata, vec!(String::from("0.500_-60.000")));
+    assert_eq!(doc.basin, 10);
+    assert_eq!(doc.geolocation.location_type, "Point");
+    assert_eq!(doc.geolocation.coordinates, Coordinates::Point([0.5, -60.0]));
+    assert_eq!(doc.level, 6.7);
+    assert_eq!(doc.data, vec!(vec!(111.0, 112.0, 113.0)));
+    assert_eq!(doc.data_info.0, vec!(String::from("TRAC02")));
+    assert_eq!(doc.data_info.1, vec!(String::from("units"), String::from("long_name")));
+    assert_eq!(doc.data_info.2, vec!(vec!(String::from("mol O/m"), String::from("Dissolved Oxygen"))));
+    assert_eq!(doc.cell_vertical_fraction, 0.75);
+    assert!(doc.sea_binary_mask_at_t_location);
+    assert!(doc.ctrl_vector_3d_mask);
+    assert_eq!(doc.cell_z_size, 5.0);
+    assert_eq!(doc.reference_density_profile, 1027.6);
+}
+
+#[test]
+fn merge_into_existing_document() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let oxygen = Extractor::new(&file, "TRAC02").unwrap();
+    let ssh = Extractor::new(&file, "ETAN").unwrap();
+
+    let mut doc = oxygen.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, oxygen.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap()).unwrap();
+    ssh.merge(&mut doc, ssh.profile(None, LatIdx(0), LonIdx(0)).unwrap(), false);
+
+    assert_eq!(doc._id, "0.500_-70.000_-2.100");
+    assert_eq!(doc.data, vec!(vec!(1.0, 2.0, 3.0), vec!(0.1, 0.2, 0.3)));
+    assert_eq!(doc.data_info.0, vec!(String::from("TRAC02"), String::from("ETAN")));
+    assert_eq!(doc.data_info.2[1], vec!(String::from("m"), String::from("Surface Height Anomaly")));
+}
+
+#[test]
+fn updates_keep_a_single_metadata_reference() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let oxygen = Extractor::new(&file, "TRAC02").unwrap();
+    let ssh = Extractor::new(&file, "ETAN").unwrap();
+    let meta = oxygen.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc()).unwrap();
+    let mut doc = oxygen.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, oxygen.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap()).unwrap();
+
+    // an update of a document that's already right leaves it alone, and merging doesn't touch the reference
+    assert_eq!(set_metadata(&mut doc, &meta._id), None);
+    ssh.merge(&mut doc, ssh.profile(None, LatIdx(0), LonIdx(0)).unwrap(), false);
+    assert_eq!(doc.metadata, vec!(String::from("0.500_-70.000")));
+    assert!(validate_document(&doc, Some(&meta), 3).is_empty());
+
+    // duplicated and stale references are reported by validation, and an update resets them
+    doc.metadata = vec!(meta._id.clone(), meta._id.clone(), String::from("0.500_-60.000"));
+    let violations = validate_document(&doc, Some(&meta), 3);
+    assert_eq!(violations.len(), 1);
+    assert!(violations[0].contains("exactly one"), "{}", violations[0]);
+    let warning = set_metadata(&mut doc, &meta._id).unwrap();
+    assert!(warning.contains("0.500_-60.000"), "{}", warning);
+    assert_eq!(doc.metadata, vec!(meta._id.clone()));
+    assert!(validate_document(&doc, Some(&meta), 3).is_empty());
+
+    // a reference to a metadoc that doesn't exist
+    assert!(validate_document(&doc, None, 3)[0].contains("does not reference an existing metadata document"));
+}
+
+#[test]
+fn surface_document_uses_sentinels() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "ETAN").unwrap();
+    assert!(extractor.surface);
+    assert_eq!(extractor.levels(), vec!(None));
+
+    let doc = extractor.document(3, None, LatIdx(0), LonIdx(2), 10, extractor.profile(None, LatIdx(0), LonIdx(2)).unwrap()).unwrap();
+    assert_eq!(doc._id, "-0.500_-70.000_0.000");
+    assert_eq!(doc.level, 0.0);
+    assert_eq!(doc.cell_z_size, SURFACE_SENTINEL);
+    assert_eq!(doc.reference_density_profile, SURFACE_SENTINEL);
+}
+
+#[test]
+fn land_cell_profile_is_empty() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert!(extractor.profile(Some(LevelIdx(0)), LatIdx(1), LonIdx(2)).unwrap().iter().all(|&x| x == 0.0));
+}
+
+#[test]
+fn data_length_mismatch_is_rejected() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap()).unwrap();
+    assert!(check_data_lengths(&doc, 3).is_ok());
+
+    // a profile from a file with a different number of timesteps
+    extractor.merge(&mut doc, vec!(1.0, 2.0), true);
+    let err = check_data_lengths(&doc, 3).unwrap_err();
+    assert!(err.to_string().contains("0.500_-70.000_-2.100"));
+}
+
+#[test]
+fn incremental_merge_only_fills_nans() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, f64::NAN, 9.0)).unwrap();
+
+    extractor.merge(&mut doc, extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap(), false);
+    assert_eq!(doc.data, vec!(vec!(9.0, 2.0, 9.0)));
+    assert_eq!(doc.data_info.0, vec!(String::from("TRAC02")));
+}
+
+#[test]
+fn overwrite_merge_replaces_the_column() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, f64::NAN, 9.0)).unwrap();
+
+    extractor.merge(&mut doc, extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap(), true);
+    assert_eq!(doc.data, vec!(vec!(1.0, 2.0, 3.0)));
+    assert_eq!(doc.data_info.0, vec!(String::from("TRAC02")));
+}
+
+#[test]
+fn conflicting_units_are_reported() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+    let stored = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, profile).unwrap();
+    assert!(extractor.units_conflict(&stored).is_none());
+
+    let other = netcdf::open(UNITS).unwrap();
+    let other = Extractor::new(&other, "TRAC02").unwrap();
+    let conflict = other.units_conflict(&stored).unwrap();
+    assert!(conflict.contains("\"mol O/m\" stored, \"mmol O/m^3\" in this file"), "{}", conflict);
+    assert!(!conflict.contains("long_name"), "{}", conflict);
+    // reported once per extractor
+    assert!(other.first_units_conflict());
+    assert!(!other.first_units_conflict());
+
+    // a variable the document doesn't have yet has nothing to conflict with
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    assert!(theta.units_conflict(&stored).is_none());
+}
+
+#[test]
+fn append_only_blocks_changing_stored_values() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+
+    // 9.0 would be replaced by 1.0 and 3.0; the NaN is a fill
+    let stored = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, f64::NAN, 9.0)).unwrap();
+    assert_eq!(extractor.overwrites(&stored, &profile), vec!(0, 2));
+
+    // re-ingesting identical values, or filling placeholders, changes nothing stored
+    let same = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, f64::NAN, 3.0)).unwrap();
+    assert!(extractor.overwrites(&same, &profile).is_empty());
+
+    // a variable not yet in the document is an append
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    assert!(theta.overwrites(&stored, &profile).is_empty());
+}
+
+#[test]
+fn removing_a_variable_keeps_columns_aligned() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let oxygen = Extractor::new(&file, "TRAC02").unwrap();
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    let mut doc = oxygen.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, oxygen.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap()).unwrap();
+    theta.merge(&mut doc, theta.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap(), false);
+
+    assert!(remove_variable(&mut doc, "TRAC02"));
+    assert!(!remove_variable(&mut doc, "TRAC02"));
+    assert_eq!(doc.data, vec!(vec!(0.5, 1.0, 1.5)));
+    assert_eq!(doc.data_info.0, vec!(String::from("THETA")));
+    assert_eq!(doc.data_info.2, vec!(vec!(String::from("degC"), String::from("Potential Temperature"))));
+}
+
+#[test]
+fn trimming_before_a_date() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let keep = timesteps_since(&extractor.timeseries, parse_date("2012-12-10").unwrap());
+    assert_eq!(keep, vec!(1, 2));
+    assert_eq!(select(&extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap(), &keep), vec!(2.0, 3.0));
+}
+
+#[test]
+fn validation_accepts_freshly_built_documents() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let meta = extractor.metadoc(3, LatIdx(1), LonIdx(0), sourcedoc()).unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap()).unwrap();
+    assert!(validate_document(&doc, Some(&meta), 3).is_empty());
+}
+
+#[test]
+fn cell_polygons_wind_counterclockwise_around_the_center() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    extractor.cell_polygons().unwrap();
+    let meta = extractor.metadoc(3, LatIdx(1), LonIdx(0), sourcedoc()).unwrap();
+
+    for lonidx in 0..3 {
+        let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(lonidx), 10, vec!(1.0, 2.0, 3.0)).unwrap();
+        assert_eq!(doc.geolocation.location_type, "Polygon");
+        let ring = match &doc.geolocation.coordinates {
+            Coordinates::Polygon(rings) => {
+                assert_eq!(rings.len(), 1);
+                rings[0].clone()
+            }
+            other => panic!("expected a polygon, got {:?}", other)
+        };
+        assert_eq!(ring.len(), 5);
+        assert_eq!(ring[0], ring[4]);
+        // 2dsphere takes the short way between corners, so no edge may span 180 degrees of longitude
+        let unwrapped: Vec<[f64; 2]> = ring.iter().map(|&[lon, lat]| [if lon < ring[0][0] { lon + 360.0 } else { lon }, lat]).collect();
+        for pair in unwrapped.windows(2) {
+            assert!((pair[1][0] - pair[0][0]).abs() < 180.0, "{:?}", ring);
+            assert!((-90.0..=90.0).contains(&pair[0][1]));
+        }
+        // counterclockwise, i.e. a positive shoelace area, as RFC 7946 asks of an exterior ring
+        let area: f64 = unwrapped.windows(2).map(|p| p[0][0] * p[1][1] - p[1][0] * p[0][1]).sum();
+        assert!(area > 0.0, "{:?}", ring);
+        let center = extractor.center(LatIdx(1), LonIdx(lonidx)).unwrap().0;
+        let center = if center < ring[0][0] { center + 360.0 } else { center };
+        assert!(unwrapped[0][0] < center && center < unwrapped[1][0], "{} outside {:?}", center, ring);
+        assert!(unwrapped[0][1] < -60.0 && -60.0 < unwrapped[2][1]);
+    }
+
+    // the grid's longitudes go round the globe, so the first cell's western edge is half way to the last center
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, vec!(1.0, 2.0, 3.0)).unwrap();
+    assert_eq!(doc.geolocation.coordinates, Coordinates::Polygon(vec!(vec!([0.0, -65.0], [95.5, -65.0], [95.5, -55.0], [0.0, -55.0], [0.0, -65.0]))));
+    assert!(validate_document(&doc, Some(&meta), 3).iter().all(|v| !v.contains("geolocation")));
+    let stored: bsose_sync::BsoseDocument = bson::from_document(bson::to_document(&doc).unwrap()).unwrap();
+    assert_eq!(stored.geolocation, doc.geolocation);
+
+    assert_eq!(cell_edges(&[-70.0, -60.0, -55.0], false), vec!(-75.0, -65.0, -57.5, -52.5));
+}
+
+#[test]
+fn validation_reports_each_violation() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap()).unwrap();
+    doc.data_info.0.push(String::from("THETA"));
+    doc.geolocation.coordinates = Coordinates::Point([1.5, -60.0]);
+
+    let violations = validate_document(&doc, None, 3);
+    assert_eq!(violations.len(), 4);
+    assert!(violations.iter().all(|v| v.contains("0.500_-60.000_-6.700")));
+}
+
+#[test]
+fn since_only_emits_later_timesteps() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    extractor.since(parse_date("2012-12-11").unwrap()).unwrap();
+    assert_eq!(extractor.timeseries, vec!(
+        DateTime::parse_rfc3339_str("2012-12-11T00:00:00Z").unwrap(),
+        DateTime::parse_rfc3339_str("2012-12-16T00:00:00Z").unwrap()
+    ));
+    assert_eq!(extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap(), vec!(112.0, 113.0));
+    assert_eq!(extractor.metadoc(3, LatIdx(1), LonIdx(0), sourcedoc()).unwrap().timeseries, extractor.timeseries);
+
+    let err = extractor.since(parse_date("2013-01-01").unwrap()).unwrap_err();
+    assert!(err.to_string().contains("no timesteps"));
+}
+
+#[test]
+fn since_appends_to_a_stored_timeseries() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let full = Extractor::new(&file, "TRAC02").unwrap();
+    let mut later = Extractor::new(&file, "TRAC02").unwrap();
+    later.since(parse_date("2012-12-16").unwrap()).unwrap();
+
+    // a document stored from the first two timesteps
+    let stored = full.timeseries[..2].to_vec();
+    let mut doc = full.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, 2.0)).unwrap();
+
+    let timeline = extend_timeline(&stored, &later.timeseries).unwrap();
+    assert_eq!(timeline, full.timeseries);
+    pad_data(&mut doc, timeline.len());
+    let profile = place(&timeline, &later.timeseries, &later.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap());
+    later.merge(&mut doc, profile, false);
+    assert_eq!(doc.data, vec!(vec!(1.0, 2.0, 3.0)));
+    assert!(check_data_lengths(&doc, timeline.len()).is_ok());
+
+    // a gap inside the stored timeseries can't be appended
+    let gappy = vec!(full.timeseries[0], full.timeseries[2]);
+    assert!(extend_timeline(&gappy, &full.timeseries[1..2]).is_err());
+}
+
+#[test]
+fn consistent_documents_pass_reconciliation() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap()).unwrap();
+    assert_eq!(reconcile(&mut doc, 3, OnInconsistent::Error).unwrap(), Reconciled::Consistent);
+}
+
+#[test]
+fn inconsistent_documents_are_repaired() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let oxygen = Extractor::new(&file, "TRAC02").unwrap();
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    let mut doc = oxygen.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, 2.0)).unwrap();
+    theta.merge(&mut doc, vec!(0.5, 1.0, 1.5, 2.0), false);
+
+    match reconcile(&mut doc, 3, OnInconsistent::Repair).unwrap() {
+        Reconciled::Repaired(warning) => assert!(warning.contains("TRAC02 has 2 values, THETA has 4 values")),
+        other => panic!("expected a repair, got {:?}", other)
+    }
+    assert_eq!(doc.data[0][..2], [1.0, 2.0]);
+    assert!(doc.data[0][2].is_nan());
+    assert_eq!(doc.data[1], vec!(0.5, 1.0, 1.5));
+}
+
+#[test]
+fn inconsistent_documents_are_skipped() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, 2.0)).unwrap();
+    match reconcile(&mut doc, 3, OnInconsistent::Skip).unwrap() {
+        Reconciled::Skipped(warning) => assert!(warning.contains("0.500_-70.000_-2.100")),
+        other => panic!("expected a skip, got {:?}", other)
+    }
+    assert_eq!(doc.data, vec!(vec!(1.0, 2.0)));
+}
+
+#[test]
+fn inconsistent_documents_are_errors() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, 2.0)).unwrap();
+    let err = reconcile(&mut doc, 3, OnInconsistent::Error).unwrap_err();
+    assert!(err.to_string().contains("the timeseries has 3 timesteps"));
+    assert!(OnInconsistent::parse("fix").is_err());
+}
+
+#[test]
+fn vertical_sign_conventions() {
+    assert_eq!(z_sign(None).unwrap(), 1.0);
+    assert_eq!(z_sign(Some("up")).unwrap(), 1.0);
+    assert_eq!(z_sign(Some("down")).unwrap(), -1.0);
+    assert_eq!(z_sign(Some("Down")).unwrap(), -1.0);
+    assert!(z_sign(Some("sideways")).is_err());
+}
+
+#[test]
+fn positive_down_depths_give_the_same_level_and_id() {
+    let up = netcdf::open(FIXTURE).unwrap();
+    let down = netcdf::open(POSITIVE_DOWN).unwrap();
+    let up = Extractor::new(&up, "TRAC02").unwrap();
+    let down = Extractor::new(&down, "TRAC02").unwrap();
+    assert_eq!(down.depth(Some(LevelIdx(1))).unwrap(), -6.7);
+
+    let profile = up.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap();
+    let from_up = up.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, profile.clone()).unwrap();
+    let from_down = down.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, profile).unwrap();
+    assert_eq!(from_up.level, 6.7);
+    assert_eq!(from_down.level, 6.7);
+    assert_eq!(from_down._id, from_up._id);
+}
+
+#[test]
+fn global_attributes_are_read_when_present() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let names = vec!(String::from("title"), String::from("history"));
+    let attributes = global_attributes(&file, &names).unwrap();
+    assert_eq!(attributes.len(), 1);
+    assert_eq!(attributes["title"], "bsose-sync test fixture");
+}
+
+#[test]
+fn provenance_without_attributes_still_deserializes() {
+    // metadocs written before attributes were recorded
+    let older = bson::doc! {"source": ["BSOSE"], "iter": "156", "file": "bsose_fixture.nc"};
+    let source: Sourcedoc = bson::from_document(older).unwrap();
+    assert_eq!(source, sourcedoc());
+}
+
+#[test]
+fn mask_key_is_stored_corrected() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap()).unwrap();
+
+    let mut stored = bson::to_document(&doc).unwrap();
+    assert!(stored.get_bool("sea_binary_mask_at_t_location").unwrap());
+    assert!(!stored.contains_key("sea_binary_mask_at_t_locaiton"));
+    assert_eq!(bson::from_document::<bsose_sync::BsoseDocument>(stored.clone()).unwrap(), doc);
+
+    // documents stored before the key was corrected still load
+    let mask = stored.remove("sea_binary_mask_at_t_location").unwrap();
+    stored.insert("sea_binary_mask_at_t_locaiton", mask);
+    assert_eq!(bson::from_document::<bsose_sync::BsoseDocument>(stored).unwrap(), doc);
+}
+
+#[test]
+fn times_before_the_origin_follow_the_bad_time_mode() {
+    let file = netcdf::open(NEGATIVE_TIME).unwrap();
+    let earliest = parse_date("2012-01-01").unwrap();
+    let latest = DateTime::now();
+
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let err = extractor.check_times(earliest, latest, OnBadTime::Error).unwrap_err().to_string();
+    assert!(err.contains("1 of 3 timesteps"), "{}", err);
+
+    let bad = extractor.check_times(earliest, latest, OnBadTime::Warn).unwrap();
+    assert_eq!(bad, vec!(parse_date("2011-08-26T00:53:20Z").unwrap()));
+    assert_eq!(extractor.timeseries.len(), 3);
+
+    extractor.check_times(earliest, latest, OnBadTime::Skip).unwrap();
+    assert_eq!(extractor.timeseries, vec!(parse_date("2012-12-06").unwrap(), parse_date("2012-12-11").unwrap()));
+    assert_eq!(extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap(), vec!(2.0, 3.0));
+
+    // the fixture's other grid has nothing to flag
+    let file = netcdf::open(FIXTURE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert!(extractor.check_times(earliest, latest, OnBadTime::Error).unwrap().is_empty());
+}
+
+#[test]
+fn dry_cells_by_data_mask_or_vertical_fraction() {
+    let file = netcdf::open(BATHYMETRY).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let dry = |mode| {
+        let mut found = Vec::new();
+        for level in 0..2 {
+            for lat in 0..2 {
+                for lon in 0..3 {
+                    let profile = extractor.profile(Some(LevelIdx(level)), LatIdx(lat), LonIdx(lon)).unwrap();
+                    if extractor.is_dry(Some(LevelIdx(level)), LatIdx(lat), LonIdx(lon), &profile, mode).unwrap() {
+                        found.push((level, lat, lon));
+                    }
+                }
+            }
+        }
+        found
+    };
+
+    assert_eq!(dry(DryCells::Zeros), vec!((0, 1, 0), (0, 1, 2), (1, 1, 2)));
+    // the partial cell stays, the level below the bathymetry goes and zero data is kept
+    assert_eq!(dry(DryCells::VerticalFraction), vec!((0, 1, 2), (1, 0, 1), (1, 1, 2)));
+    assert_eq!(dry(DryCells::Mask), vec!((0, 1, 2), (1, 0, 1), (1, 1, 2)));
+}
+
+#[test]
+fn stale_documents_are_refused() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, profile).unwrap();
+    assert_eq!(doc.timeline, Some(TimelineRef::of(&extractor.timeseries)));
+
+    // written against the metadoc as it stood before this run, or as another variable already extended it
+    let mut timeline = extractor.timeseries.clone();
+    timeline.push(parse_date("2012-12-16").unwrap());
+    assert!(check_timeline(&doc, &extractor.timeseries, 3).is_ok());
+    assert!(check_timeline(&doc, &timeline, 3).is_ok());
+    doc.timeline = Some(TimelineRef::of(&timeline));
+    assert!(check_timeline(&doc, &timeline, 3).is_ok());
+
+    // the metadoc was trimmed after the document was written
+    doc.timeline = Some(TimelineRef::of(&extractor.timeseries));
+    let trimmed = extractor.timeseries[1..].to_vec();
+    let err = check_timeline(&doc, &trimmed, 2).unwrap_err().to_string();
+    assert!(err.contains("refusing to misalign"), "{}", err);
+
+    // documents from before the reference was stored can't be checked
+    doc.timeline = None;
+    assert!(check_timeline(&doc, &trimmed, 2).is_ok());
+}
+
+#[test]
+fn reference_variable_decides_dry_levels() {
+    let file = netcdf::open(BATHYMETRY).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let zero = extractor.profile(Some(LevelIdx(0)), LatIdx(1), LonIdx(0)).unwrap();
+    assert!(extractor.is_dry(Some(LevelIdx(0)), LatIdx(1), LonIdx(0), &zero, DryCells::Zeros).unwrap());
+
+    // maskC says ocean where TRAC02 is all zero, and land where TRAC02 still holds values
+    extractor.reference(&file, "maskC", &VarMap::default()).unwrap();
+    assert!(!extractor.is_dry(Some(LevelIdx(0)), LatIdx(1), LonIdx(0), &zero, DryCells::Zeros).unwrap());
+    let filled = extractor.profile(Some(LevelIdx(1)), LatIdx(0), LonIdx(1)).unwrap();
+    assert_eq!(filled, vec!(1011.0, 1012.0, 1013.0));
+    assert!(extractor.is_dry(Some(LevelIdx(1)), LatIdx(0), LonIdx(1), &filled, DryCells::Zeros).unwrap());
+}
+
+#[test]
+fn time_varying_and_surface_reference_variables() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let zeros = vec!(0.0, 0.0, 0.0);
+    // THETA runs along time, so every timestep of the cell and level is read
+    extractor.reference(&file, "THETA", &VarMap::default()).unwrap();
+    assert!(!extractor.is_dry(Some(LevelIdx(0)), LatIdx(0), LonIdx(0), &zeros, DryCells::Zeros).unwrap());
+    // ETAN has no level dimension and applies at every level
+    extractor.reference(&file, "ETAN", &VarMap::default()).unwrap();
+    assert!(!extractor.is_dry(Some(LevelIdx(1)), LatIdx(0), LonIdx(0), &zeros, DryCells::Zeros).unwrap());
+
+    assert!(extractor.reference(&file, "SALT", &VarMap::default()).is_err());
+    // drF runs along Z only
+    assert!(extractor.reference(&file, "drF", &VarMap::default()).is_err());
+}
+
+#[test]
+fn w_point_variables_read_their_own_depths() {
+    let file = netcdf::open(WPOINT).unwrap();
+    // Z has as many levels as Zl, but isn't what WVEL runs along
+    let error = Extractor::new(&file, "WVEL").err().unwrap().to_string();
+    assert!(error.contains("--depth-var"), "{}", error);
+
+    let mut names = VarMap::default();
+    names.set("depth", "Zl").unwrap();
+    let extractor = Extractor::with_var_map(&file, "WVEL", &names).unwrap();
+    assert_eq!(extractor.staggering, Staggering::Upper);
+    let profile = extractor.profile(Some(LevelIdx(1)), LatIdx(0), LonIdx(0)).unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(0), LonIdx(0), 10, profile).unwrap();
+    assert_eq!(doc._id, "0.500_-70.000_-4.200");
+    assert_eq!(doc.level, 4.2);
+    // the spacing between the centers at -2.1 and -6.7, and the mean of their reference densities
+    assert_eq!(doc.cell_z_size, 4.6);
+    assert!((doc.reference_density_profile - 1027.55).abs() < 1e-9);
+    // the top face has only the first center below it
+    let top = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(0.0, 0.0, 0.0)).unwrap();
+    assert_eq!((top.cell_z_size, top.reference_density_profile), (2.1, 1027.5));
+
+    // and a center variable can't be read against the faces
+    assert!(Extractor::with_var_map(&file, "TRAC02", &names).is_err());
+}
+
+#[test]
+fn staggering_from_depth_variables() {
+    assert_eq!(Staggering::of("Z", None), Staggering::Center);
+    assert_eq!(Staggering::of("Zl", None), Staggering::Upper);
+    assert_eq!(Staggering::of("Zu", None), Staggering::Lower);
+    // the attribute wins over the name
+    assert_eq!(Staggering::of("depth", Some(-0.5)), Staggering::Upper);
+    assert_eq!(Staggering::of("Zl", Some(0.0)), Staggering::Center);
+}
+
+#[test]
+fn valid_fractions_of_sparse_profiles() {
+    let nan = f64::NAN;
+    assert_eq!(valid_fraction(&[1.0, 2.0, 3.0, 4.0]), 1.0);
+    assert_eq!(valid_fraction(&[1.0, nan, 3.0, nan]), 0.5);
+    assert_eq!(valid_fraction(&[nan, nan, nan, 4.0]), 0.25);
+    assert_eq!(valid_fraction(&[nan; 10]), 0.0);
+    assert_eq!(valid_fraction(&[]), 0.0);
+
+    // one value in ten sits exactly on a 0.1 threshold and is kept; one in eleven isn't
+    let mut sparse = vec!(nan; 10);
+    sparse[3] = 7.0;
+    assert!(valid_fraction(&sparse) >= 0.1);
+    sparse.push(nan);
+    assert!(valid_fraction(&sparse) < 0.1);
+}
+
+#[test]
+fn subset_files_overwrite_only_their_timesteps() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let full = Extractor::new(&file, "TRAC02").unwrap();
+    let mut later = Extractor::new(&file, "TRAC02").unwrap();
+    later.since(parse_date("2012-12-16").unwrap()).unwrap();
+
+    // a stored document with all three timesteps, re-ingested with --overwrite from a file holding only the last
+    let mut doc = full.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, 9.0, 9.0)).unwrap();
+    let timeline = extend_timeline(&full.timeseries, &later.timeseries).unwrap();
+    assert_eq!(timeline, full.timeseries);
+    let profile = place(&timeline, &later.timeseries, &later.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap());
+    let last = covered(&timeline, &later.timeseries);
+    assert_eq!(last, vec!(2));
+
+    assert_eq!(later.overwrites_at(&doc, &profile, &last), vec!(2));
+    assert_eq!(later.merge_at(&mut doc, profile, true, &last), vec!(2));
+    assert_eq!(doc.data, vec!(vec!(9.0, 9.0, 3.0)));
+    assert!(check_data_lengths(&doc, timeline.len()).is_ok());
+
+    // a single timestep from the middle of the stored timeseries
+    let middle = covered(&timeline, &full.timeseries[1..2]);
+    let mut doc = full.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(9.0, 9.0, 9.0)).unwrap();
+    full.merge_at(&mut doc, place(&timeline, &full.timeseries[1..2], &[5.0]), true, &middle);
+    assert_eq!(doc.data, vec!(vec!(9.0, 5.0, 9.0)));
+}
+
+#[test]
+fn unsorted_times_are_placed_by_timestamp() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let timeline = Extractor::new(&file, "TRAC02").unwrap().timeseries;
+    // a file listing the last and first timesteps in reverse, plus one the timeline doesn't hold
+    let outside = DateTime::parse_rfc3339_str("2013-01-01T00:00:00Z").unwrap();
+    let times = vec!(timeline[2], outside, timeline[0]);
+
+    assert_eq!(placements(&timeline, &times), vec!((0, 2), (2, 0)));
+    assert_eq!(covered(&timeline, &times), vec!(0, 2));
+    let placed = place(&timeline, &times, &[30.0, 99.0, 10.0]);
+    assert_eq!(placed[0], 10.0);
+    assert!(placed[1].is_nan());
+    assert_eq!(placed[2], 30.0);
+
+    // a profile shorter than its times leaves the timesteps it has no value for empty
+    let placed = place(&timeline, &times, &[30.0]);
+    assert!(placed[0].is_nan());
+    assert_eq!(placed[2], 30.0);
+}
+
+#[test]
+fn unsorted_new_times_are_appended_in_order() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let full = Extractor::new(&file, "TRAC02").unwrap();
+    let day = |d: &str| parse_date(d).unwrap();
+    // a file listing timesteps the stored timeline doesn't hold yet, latest first, alongside one it does
+    let stored = full.timeseries[..1].to_vec();
+    let new = vec!(day("2013-01-01"), full.timeseries[2], full.timeseries[0], full.timeseries[1]);
+
+    let timeline = extend_timeline(&stored, &new).unwrap();
+    assert_eq!(timeline, vec!(full.timeseries[0], full.timeseries[1], full.timeseries[2], day("2013-01-01")));
+    assert_eq!(placements(&timeline, &new), vec!((0, 2), (1, 3), (2, 1), (3, 0)));
+    let placed = place(&timeline, &new, &[40.0, 30.0, 10.0, 20.0]);
+    assert_eq!(placed, vec!(10.0, 20.0, 30.0, 40.0));
+
+    // times repeated in the file are appended once
+    assert_eq!(extend_timeline(&stored, &[day("2013-01-01"), day("2013-01-01")]).unwrap().len(), 2);
+
+    // a new time before the last stored one would shift stored values, so it's still refused
+    let err = extend_timeline(&full.timeseries[1..], &[day("2013-01-01"), full.timeseries[0]]).unwrap_err().to_string();
+    assert!(err.contains("falls inside the stored timeseries"), "{}", err);
+}
+
+#[test]
+fn compaction_keeps_values_with_their_timestamps() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let nan = f64::NAN;
+    let mut upper = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, vec!(1.0, nan, nan)).unwrap();
+    upper.data.push(vec!(nan, nan, 30.0));
+    let lower = extractor.document(3, Some(LevelIdx(1)), LatIdx(0), LonIdx(0), 10, vec!(nan, nan, 3.0)).unwrap();
+    let mut docs = vec!(upper, lower);
+
+    // only the middle timestep is empty in every variable of every level
+    let kept = populated_timesteps(&docs, 3);
+    assert_eq!(kept, vec!(0, 2));
+    let timeseries = select(&extractor.timeseries, &kept);
+    for doc in docs.iter_mut() {
+        doc.data = doc.data.iter().map(|d| select(d, &kept)).collect();
+        assert!(check_data_lengths(doc, timeseries.len()).is_ok());
+    }
+    assert_eq!(timeseries, vec!(extractor.timeseries[0], extractor.timeseries[2]));
+    assert_eq!(docs[0].data[0][0], 1.0);
+    assert_eq!(docs[0].data[1][1], 30.0);
+    assert_eq!(docs[1].data[0][1], 3.0);
+
+    // nothing left to remove, and no data documents means no evidence either way
+    assert_eq!(populated_timesteps(&docs, 2), vec!(0, 1));
+    assert!(populated_timesteps(&[], 3).is_empty());
+}
+
+#[test]
+fn data_type_follows_the_variable() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    assert_eq!(theta.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc()).unwrap().data_type, "BSOSE-profile");
+    let mut etan = Extractor::new(&file, "ETAN").unwrap();
+    assert_eq!(etan.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc()).unwrap().data_type, "BSOSE-surface");
+
+    // --data-type replaces either default
+    etan.data_type = String::from("ECCO-surface");
+    assert_eq!(etan.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc()).unwrap().data_type, "ECCO-surface");
+}
+
+#[test]
+fn reused_ids_must_be_the_same_cell() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), sourcedoc()).unwrap();
+    assert!(check_meta_coordinates(&meta, -169.5, -70.0).is_ok());
+    assert!(check_meta_coordinates(&meta, -169.5 + 1e-9, -70.0).is_ok());
+
+    // stored by an earlier run on a grid offset by less than the id's precision
+    meta.latitude = -70.0004;
+    let e = check_meta_coordinates(&meta, -169.5, -70.0).unwrap_err();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))));
+    assert!(e.to_string().contains("-169.500_-70.000"), "{}", e);
+}
+
+#[test]
+fn level_ranges_are_checked_against_the_depth_variable() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert_eq!(extractor.level_range(None, None).unwrap(), extractor.levels());
+    assert_eq!(extractor.level_range(None, Some(1)).unwrap(), vec!(Some(LevelIdx(0))));
+    assert_eq!(extractor.level_range(Some(1), None).unwrap(), vec!(Some(LevelIdx(1))));
+    for (min, max) in [(Some(0), Some(3)), (Some(1), Some(1)), (Some(2), None)] {
+        assert!(extractor.level_range(min, max).is_err(), "{:?}..{:?}", min, max);
+    }
+
+    // the surface document only belongs to ranges starting at the top
+    let etan = Extractor::new(&file, "ETAN").unwrap();
+    assert_eq!(etan.level_range(None, Some(1)).unwrap(), vec!(None));
+    assert!(etan.level_range(Some(1), None).unwrap().is_empty());
+}
+
+#[test]
+fn variables_without_units_or_long_name_are_stored_with_empty_strings() {
+    let file = netcdf::open(BARE).unwrap();
+    let extractor = Extractor::new(&file, "BARE").unwrap();
+    assert_eq!(extractor.units, "");
+    assert_eq!(extractor.long_name, "");
+    assert_eq!(extractor.missing_attributes().unwrap(), vec!("units", "long_name"));
+    let profile = extractor.profile(Some(LevelIdx(1)), LatIdx(0), LonIdx(2)).unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(0), LonIdx(2), 1, profile).unwrap();
+    assert_eq!(doc.data_info.2, vec!(vec!(String::new(), String::new())));
+
+    assert!(Extractor::new(&file, "TRAC02").unwrap().missing_attributes().unwrap().is_empty());
+}
+
+#[test]
+fn out_of_range_values_follow_the_policy() {
+    let values = vec!(-5.0, 0.0, 3.0, f64::NAN, 10.0, 12.5);
+    let applied = |mode| {
+        let mut values = values.clone();
+        let outside = apply_valid_range(&mut values, (0.0, 10.0), mode);
+        (outside, values)
+    };
+
+    let (outside, masked) = applied(OutOfRange::Mask);
+    assert_eq!(outside, 2);
+    assert!(masked[0].is_nan() && masked[3].is_nan() && masked[5].is_nan());
+    assert_eq!(&masked[1..3], &[0.0, 3.0]);
+    assert_eq!(masked[4], 10.0);
+    let (_, clamped) = applied(OutOfRange::Clamp);
+    assert_eq!(clamped[0], 0.0);
+    assert_eq!(clamped[5], 10.0);
+    assert!(clamped[3].is_nan());
+    let (outside, kept) = applied(OutOfRange::Keep);
+    assert_eq!(outside, 2);
+    assert_eq!(kept[5], 12.5);
+    assert!(OutOfRange::parse("drop").is_err());
+}
+
+#[test]
+fn profiles_apply_the_variable_valid_range() {
+    let file = netcdf::open(VALID_RANGE).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert_eq!(extractor.valid_range, Some((f64::NEG_INFINITY, 1002.0)));
+    let profile = |extractor: &Extractor| extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap();
+    assert_eq!(profile(&extractor), vec!(1001.0, 1002.0, 1003.0));
+    extractor.out_of_range(OutOfRange::Clamp);
+    assert_eq!(profile(&extractor), vec!(1001.0, 1002.0, 1002.0));
+    extractor.out_of_range(OutOfRange::Mask);
+    let masked = profile(&extractor);
+    assert_eq!(&masked[..2], &[1001.0, 1002.0]);
+    assert!(masked[2].is_nan());
+
+    assert_eq!(Extractor::new(&netcdf::open(FIXTURE).unwrap(), "TRAC02").unwrap().valid_range, None);
+}
+
+#[test]
+fn curvilinear_grids_read_each_cell_center() {
+    let file = netcdf::open(CURVILINEAR).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert!(extractor.curvilinear());
+    assert_eq!(extractor.center(LatIdx(0), LonIdx(1)).unwrap(), (-169.5, -68.0));
+    assert_eq!(extractor.center(LatIdx(1), LonIdx(2)).unwrap(), (-5.5, -56.0));
+
+    let meta = extractor.metadoc(3, LatIdx(1), LonIdx(0), sourcedoc()).unwrap();
+    assert_eq!((meta._id.as_str(), meta.longitude, meta.latitude), ("-4.500_-60.000", -4.5, -60.0));
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(1), 1, extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap()).unwrap();
+    assert_eq!(doc._id, "-169.500_-68.000_-2.100");
+    assert_eq!(doc.geolocation.coordinates, Coordinates::Point([-169.5, -68.0]));
+    assert_eq!(doc.data[0], vec!(1001.0, 1002.0, 1003.0));
+    // the same values as on the rectilinear fixture, which only the coordinates differ from
+    assert!(!Extractor::new(&netcdf::open(FIXTURE).unwrap(), "TRAC02").unwrap().curvilinear());
+    assert!(extractor.cell_polygons().is_err());
+
+    // no single axis holds a curvilinear grid's coordinates, so ids are checked per cell
+    assert!(check_center_collisions(3, &[(-4.5, -60.0), (-4.5, -58.0)]).is_ok());
+    assert!(check_center_collisions(0, &[(-4.5, -60.0), (-4.4, -60.0)]).is_err());
+}
+
+#[test]
+fn mixed_coordinate_dimensions_are_ambiguous() {
+    let file = netcdf::open(MIXED_GRID).unwrap();
+    let names = VarMap::from_json(r#"{"lat": "YC2"}"#).unwrap();
+    let e = Extractor::with_var_map(&file, "TRAC02", &names).err().unwrap();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))), "{}", e);
+    assert!(e.to_string().contains("grid type"), "{}", e);
+}
+
+#[test]
+fn quality_flags_follow_the_values_written() {
+    // SURFACE_FLAG is on the wrong grid for TRAC02
+    let file = netcdf::open(QC).unwrap();
+    let mut extractor = Extractor::new(&file, "TRAC02").unwrap();
+    assert_eq!(extractor.qc_profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap(), None);
+    assert!(extractor.qc(&file, "SURFACE_FLAG").is_err());
+    assert!(extractor.qc(&file, "NO_SUCH_FLAG").is_err());
+    extractor.qc(&file, "TRAC02_FLAG").unwrap();
+
+    let flags = extractor.qc_profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap().unwrap();
+    assert_eq!(flags, vec!(1.0, 13.0, 25.0));
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(1)).unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(1), 1, profile).unwrap();
+    extractor.merge_qc_at(&mut doc, &flags, &[0, 1, 2]);
+    assert_eq!(doc.data_info.0, vec!(String::from("TRAC02"), qc_column("TRAC02")));
+    assert_eq!(doc.data_info.2[1], vec!(String::new(), String::from("TRAC02 quality flag")));
+    assert_eq!(doc.data[1], flags);
+    let meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), sourcedoc()).unwrap();
+    assert!(validate_document(&doc, Some(&meta), 3).is_empty());
+
+    // a gap-filling merge writes only the gap, and its flag goes with it
+    doc.data[0][1] = f64::NAN;
+    doc.data[1][1] = f64::NAN;
+    let written = extractor.merge_at(&mut doc, vec!(7.0, 8.0, 9.0), false, &[0, 1, 2]);
+    assert_eq!(written, vec!(1));
+    extractor.merge_qc_at(&mut doc, &[70.0, 80.0, 90.0], &written);
+    assert_eq!(doc.data[0], vec!(1001.0, 8.0, 1003.0));
+    assert_eq!(doc.data[1], vec!(1.0, 80.0, 25.0));
+}
+
+#[test]
+fn permuted_dimensions_are_rejected() {
+    let file = netcdf::open(PERMUTED).unwrap();
+    for (name, expected) in [("LEVEL_LAST", "[time, Z, YC, XC]"), ("SWAPPED", "[time, YC, XC]")] {
+        let e = Extractor::new(&file, name).err().unwrap();
+        assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))), "{}: {}", name, e);
+        assert!(e.to_string().contains(expected), "{}: {}", name, e);
+    }
+    assert!(Extractor::new(&file, "TRAC02").is_ok());
+}
+
+#[test]
+fn oversized_documents_split_into_time_ranges() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    // about 3MB of doubles and their array keys, which fit in three 1MB chunks
+    let profile: Vec<f64> = (0..200_000).map(|i| i as f64).collect();
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 1, profile.clone()).unwrap();
+    assert!(document_size(&doc).unwrap() > 3_000_000);
+
+    let chunks = split_document(&doc, 1_000_000).unwrap();
+    assert_eq!(chunks.len(), 3);
+    let mut joined = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        assert_eq!(chunk._id, chunk_id(&doc._id, index));
+        assert_eq!(chunk.chunk, Some(Chunk{index: index as u64, count: 3, start: joined.len() as u64}));
+        assert_eq!(chunk.metadata, doc.metadata);
+        assert!(document_size(chunk).unwrap() <= 1_000_000);
+        joined.extend_from_slice(&chunk.data[0]);
+    }
+    assert_eq!(joined, profile);
+
+    // a document within the limit is left whole
+    let whole = split_document(&doc, 4_000_000).unwrap();
+    assert_eq!(whole, vec!(doc.clone()));
+    // and one that can't get small enough is a validation error
+    let e = split_document(&doc, 200).unwrap_err();
+    assert!(matches!(e.downcast_ref::<SyncError>(), Some(SyncError::Validation(_))), "{}", e);
+}
@@ -0,0 +1,332 @@
+use std::process::Command;
+use bsose_sync::BsoseDocument;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+// the base fixture with its first timestep in August 2011
+const NEGATIVE_TIME: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_negative_time_fixture.nc");
+// the base fixture with TRAC02 valid up to 2000
+const MASKED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_masked_fixture.nc");
+const BASINS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc");
+
+fn export(dir: &std::path::Path, variable: &str, options: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, variable, "0", "2", "0", "3"])
+        .args(options)
+        .args(["--basin-file", BASINS, "--output-json"])
+        .arg(dir)
+        .env_remove("MONGODB_URI")
+        .status()
+        .unwrap();
+    assert!(status.success(), "export of {} failed with {}", variable, status);
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: std::path::PathBuf) -> Vec<T> {
+    std::fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn export_writes_one_line_per_document() {
+    let dir = std::env::temp_dir().join(format!("bsose-export-{}", std::process::id()));
+    export(&dir, "TRAC02", &[]);
+
+    // land metadocs carry NaN, which JSON writes as null, so these aren't read back as BsoseMetadoc
+    let metadocs: Vec<serde_json::Value> = read(dir.join("timeseriesMeta.ndjson"));
+    let docs: Vec<BsoseDocument> = read(dir.join("bsose.ndjson"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // every cell gets a metadoc, the land cell no data documents, and ids are in sorted order
+    assert_eq!(metadocs.len(), 6);
+    assert_eq!(docs.len(), 10);
+    assert!(metadocs.iter().any(|m| m["_id"] == "-0.500_-60.000" && m["cell_area"].is_null()));
+    assert!(docs.windows(2).all(|pair| pair[0]._id < pair[1]._id));
+    let doc = docs.iter().find(|d| d._id == "0.500_-60.000_-6.700").unwrap();
+    assert_eq!(doc.data, vec!(vec!(111.0, 112.0, 113.0)));
+}
+
+#[test]
+fn concurrent_variables_share_documents() {
+    let dir = std::env::temp_dir().join(format!("bsose-variables-{}", std::process::id()));
+    export(&dir, "TRAC02,THETA", &["--workers-per-variable"]);
+
+    let docs: Vec<BsoseDocument> = read(dir.join("bsose.ndjson"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // the same ten documents as a single variable, each carrying both columns
+    assert_eq!(docs.len(), 10);
+    for doc in &docs {
+        let mut variables = doc.data_info.0.clone();
+        variables.sort();
+        assert_eq!(variables, vec!(String::from("THETA"), String::from("TRAC02")), "{}", doc._id);
+        assert_eq!(doc.data.len(), 2);
+    }
+}
+
+#[test]
+fn max_runtime_stops_before_the_next_cell() {
+    // a deadline that has already passed when ingesting starts leaves every cell for the next run
+    let dir = std::env::temp_dir().join(format!("bsose-deadline-{}", std::process::id()));
+    let summary_path = dir.join("summary.json");
+    std::fs::create_dir_all(&dir).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+        .args(["--basin-file", BASINS, "--max-runtime", "0s", "--summary-json"])
+        .arg(&summary_path)
+        .arg("--output-json")
+        .arg(&dir)
+        .env_remove("MONGODB_URI")
+        .status()
+        .unwrap();
+
+    let summary: bsose_sync::Summary = serde_json::from_str(&std::fs::read_to_string(&summary_path).unwrap()).unwrap();
+    let metadocs: Vec<serde_json::Value> = read(dir.join("timeseriesMeta.ndjson"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(status.code(), Some(6));
+    assert!(metadocs.is_empty());
+    assert_eq!(summary.metadocs_inserted, 0);
+    assert_eq!(summary.data_inserted, 0);
+}
+
+#[test]
+fn strict_units_stops_on_a_units_conflict() {
+    // both files in one run, so the second merges into documents the first wrote with other units
+    let dir = std::env::temp_dir().join(format!("bsose-units-{}", std::process::id()));
+    let inputs = dir.join("inputs");
+    std::fs::create_dir_all(&inputs).unwrap();
+    std::fs::copy(FIXTURE, inputs.join("a.nc")).unwrap();
+    std::fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_units_fixture.nc"), inputs.join("b.nc")).unwrap();
+    let run = |options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .arg(&inputs)
+            .args(["TRAC02", "0", "2", "0", "3", "--basin-file", BASINS])
+            .args(options)
+            .arg("--output-json")
+            .arg(dir.join("out"))
+            .env_remove("MONGODB_URI")
+            .output()
+            .unwrap()
+    };
+
+    let warned = run(&[]);
+    let strict = run(&["--strict-units"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(warned.status.success());
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert_eq!(stderr.matches("mmol O/m^3").count(), 1, "{}", stderr);
+    assert!(!strict.status.success());
+    assert!(String::from_utf8_lossy(&strict.stderr).contains("--strict-units"));
+}
+
+#[test]
+fn shuffled_cells_write_the_same_documents() {
+    let plain = std::env::temp_dir().join(format!("bsose-plain-{}", std::process::id()));
+    let shuffled = std::env::temp_dir().join(format!("bsose-shuffled-{}", std::process::id()));
+    export(&plain, "TRAC02", &[]);
+    export(&shuffled, "TRAC02", &["--shuffle-cells", "7"]);
+
+    for name in ["timeseriesMeta.ndjson", "bsose.ndjson"] {
+        let mut expected: Vec<serde_json::Value> = read(plain.join(name));
+        let mut actual: Vec<serde_json::Value> = read(shuffled.join(name));
+        // date_updated_argovis is the time of each run
+        for doc in expected.iter_mut().chain(actual.iter_mut()) {
+            doc.as_object_mut().unwrap().remove("date_updated_argovis");
+        }
+        assert_eq!(actual, expected, "{}", name);
+    }
+    std::fs::remove_dir_all(&plain).unwrap();
+    std::fs::remove_dir_all(&shuffled).unwrap();
+}
+
+#[test]
+fn level_bounds_limit_the_documents_written() {
+    let dir = std::env::temp_dir().join(format!("bsose-levels-{}", std::process::id()));
+    export(&dir, "TRAC02", &["--level-max", "1"]);
+    let docs: Vec<BsoseDocument> = read(dir.join("bsose.ndjson"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // half of the full export's ten documents, all from the top level
+    assert_eq!(docs.len(), 5);
+    assert!(docs.iter().all(|d| d.level == 2.1), "{:?}", docs.iter().map(|d| d.level).collect::<Vec<f64>>());
+}
+
+#[test]
+fn oversized_documents_stop_the_export_unless_split() {
+    let dir = std::env::temp_dir().join(format!("bsose-oversized-{}", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, "TRAC02", "0", "2", "0", "3", "--max-document-size", "100"])
+        .args(["--basin-file", BASINS, "--output-json"])
+        .arg(&dir)
+        .env_remove("MONGODB_URI")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(2));
+    let docs: Vec<BsoseDocument> = read(dir.join("bsose.ndjson"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(docs.is_empty());
+}
+
+#[test]
+fn quiet_leaves_only_the_summary() {
+    let dir = std::env::temp_dir().join(format!("bsose-quiet-{}", std::process::id()));
+    let run = |options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "TRAC02", "0", "2", "0", "3", "--basin-file", BASINS])
+            .args(options)
+            .arg("--output-json")
+            .arg(&dir)
+            .env_remove("MONGODB_URI")
+            .output()
+            .unwrap()
+    };
+
+    let loud = run(&[]);
+    let quiet = run(&["--quiet"]);
+    let silent = run(&["--quiet", "--quiet-summary"]);
+    let conflicting = run(&["--quiet", "--log-level", "warn"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = |output: &std::process::Output| String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(stdout(&loud).contains("wrote 6 metadata documents"), "{}", stdout(&loud));
+    assert!(quiet.status.success());
+    assert!(stdout(&quiet).starts_with(FIXTURE), "{}", stdout(&quiet));
+    assert!(!stdout(&quiet).contains("wrote"));
+    assert!(silent.status.success());
+    assert!(silent.stdout.is_empty() && silent.stderr.is_empty());
+    assert_eq!(conflicting.status.code(), Some(2));
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn decompressed(path: std::path::PathBuf) -> String {
+    use std::io::Read;
+    let file = std::fs::File::open(&path).unwrap();
+    let mut text = String::new();
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "gzip")]
+        Some("gz") => { flate2::read::GzDecoder::new(file).read_to_string(&mut text).unwrap(); }
+        #[cfg(feature = "zstd")]
+        Some("zst") => { zstd::Decoder::new(file).unwrap().read_to_string(&mut text).unwrap(); }
+        other => panic!("unexpected extension {:?}", other)
+    }
+    text
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[test]
+fn compressed_exports_hold_the_same_documents() {
+    let plain = std::env::temp_dir().join(format!("bsose-uncompressed-{}", std::process::id()));
+    export(&plain, "TRAC02", &[]);
+    let mut formats = Vec::new();
+    if cfg!(feature = "gzip") {
+        formats.push(("gzip", "gz"));
+    }
+    if cfg!(feature = "zstd") {
+        formats.push(("zstd", "zst"));
+    }
+
+    for (format, extension) in formats {
+        let dir = std::env::temp_dir().join(format!("bsose-{}-{}", format, std::process::id()));
+        export(&dir, "TRAC02", &["--compress", format]);
+        assert!(!dir.join("bsose.ndjson").exists());
+        for name in ["timeseriesMeta", "bsose"] {
+            let parse = |text: String| -> Vec<serde_json::Value> {
+                let mut docs: Vec<serde_json::Value> = text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+                // date_updated_argovis is the time of each run
+                for doc in docs.iter_mut() {
+                    doc.as_object_mut().unwrap().remove("date_updated_argovis");
+                }
+                docs
+            };
+            let expected = parse(std::fs::read_to_string(plain.join(format!("{}.ndjson", name))).unwrap());
+            let actual = parse(decompressed(dir.join(format!("{}.ndjson.{}", name, extension))));
+            assert_eq!(actual, expected, "{} {}", format, name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::remove_dir_all(&plain).unwrap();
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn zstd_exports_need_the_feature() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, "TRAC02", "0", "2", "0", "3", "--compress", "zstd", "--output-json"])
+        .arg(std::env::temp_dir().join(format!("bsose-zstd-{}", std::process::id())))
+        .env_remove("MONGODB_URI")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--features zstd"));
+}
+
+#[test]
+fn summary_reports_value_statistics_per_variable() {
+    // TRAC02 declares valid_max 2000 in this fixture, so --out-of-range mask turns 6 of the 30 values written into NaN
+    let dir = std::env::temp_dir().join(format!("bsose-value-stats-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let summary_path = dir.join("summary.json");
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .arg(MASKED)
+        .args(["TRAC02", "0", "2", "0", "3", "--basin-file", BASINS, "--out-of-range", "mask", "--summary-json"])
+        .arg(&summary_path)
+        .arg("--output-json")
+        .arg(dir.join("out"))
+        .env_remove("MONGODB_URI")
+        .output()
+        .unwrap();
+    let summary: bsose_sync::Summary = serde_json::from_str(&std::fs::read_to_string(&summary_path).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let stats = &summary.values["TRAC02"];
+    assert_eq!((stats.values, stats.nan), (30, 6));
+    assert_eq!(stats.nan_fraction(), 0.2);
+    assert_eq!((stats.min, stats.max), (Some(1.0), Some(1113.0)));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("TRAC02: 30 values, 20.0% NaN, min 1, max 1113"));
+}
+
+#[test]
+fn force_new_still_merges_what_the_run_wrote() {
+    // two variables in one run: the second finds the documents the first inserted despite --force-new
+    let plain = std::env::temp_dir().join(format!("bsose-merged-{}", std::process::id()));
+    let forced = std::env::temp_dir().join(format!("bsose-force-new-{}", std::process::id()));
+    export(&plain, "TRAC02,THETA", &[]);
+    export(&forced, "TRAC02,THETA", &["--force-new"]);
+
+    for name in ["timeseriesMeta.ndjson", "bsose.ndjson"] {
+        let mut expected: Vec<serde_json::Value> = read(plain.join(name));
+        let mut actual: Vec<serde_json::Value> = read(forced.join(name));
+        for doc in expected.iter_mut().chain(actual.iter_mut()) {
+            doc.as_object_mut().unwrap().remove("date_updated_argovis");
+        }
+        assert_eq!(actual, expected, "{}", name);
+    }
+    std::fs::remove_dir_all(&plain).unwrap();
+    std::fs::remove_dir_all(&forced).unwrap();
+}
+
+#[test]
+fn year_collections_split_each_cell_by_year() {
+    // the negative time fixture's first timestep is in 2011 and its other two in 2012
+    let dir = std::env::temp_dir().join(format!("bsose-export-years-{}", std::process::id()));
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([NEGATIVE_TIME, "TRAC02", "0", "2", "0", "3"])
+        .args(["--time-min", "2011-01-01", "--data-collection", "bsose_{year}", "--meta-collection", "timeseriesMeta_{year}"])
+        .args(["--basin-file", BASINS, "--output-json"])
+        .arg(&dir)
+        .env_remove("MONGODB_URI")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    for (year, timesteps) in [(2011, 1), (2012, 2)] {
+        let metas: Vec<serde_json::Value> = read(dir.join(format!("timeseriesMeta_{}.ndjson", year)));
+        let docs: Vec<BsoseDocument> = read(dir.join(format!("bsose_{}.ndjson", year)));
+        assert_eq!(metas.len(), 6);
+        assert_eq!(docs.len(), 10);
+        assert!(metas.iter().all(|m| m["timeseries"].as_array().unwrap().len() == timesteps));
+        assert!(docs.iter().all(|d| d.data.iter().all(|column| column.len() == timesteps)));
+    }
+    assert!(!dir.join("bsose_{year}.ndjson").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use mongodb::bson::DateTime;
+use bsose_sync::BsoseDocument;
+use bsose_sync::args::Args;
+use bsose_sync::basin::{BasinGrid, BasinLookup, BasinMode, BasinTags};
+use bsose_sync::error::SyncError;
+use bsose_sync::ingest::{ingest_all, ingest_file, partitions, Run, Stop};
+use bsose_sync::sink::MemorySink;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+// the base fixture with its first timestep in August 2011 and the other two in 2012
+const NEGATIVE_TIME: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_negative_time_fixture.nc");
+const BASINS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc");
+
+fn args(file: &str, options: &[&str]) -> Args {
+    // the whole fixture tile
+    let argv: Vec<String> = ["bsose-sync", file, "TRAC02", "0", "2", "0", "3", "--basin-file", BASINS].iter()
+        .chain(options)
+        .map(|a| a.to_string())
+        .collect();
+    Args::parse(&argv).unwrap()
+}
+
+fn basins(args: &Args) -> BasinLookup {
+    let file = netcdf::open(&args.basin_file).unwrap();
+    BasinLookup{
+        grid: BasinGrid::from_file(&file).unwrap(),
+        mode: BasinMode::parse(&args.basin_mode).unwrap(),
+        tags: BasinTags::parse(&args.basin_tags).unwrap()
+    }
+}
+
+fn unsignalled(max_runtime: Option<Duration>) -> Stop {
+    // never signalled
+    Stop::new(Arc::new(AtomicBool::new(false)), max_runtime)
+}
+
+fn failing_sink() -> MemorySink {
+    // refuses to insert one data document
+    MemorySink{fail_insert: Some(String::from("0.500_-60.000_-6.700")), ..MemorySink::default()}
+}
+
+#[tokio::test]
+async fn a_failing_insert_fails_only_its_cell() {
+    let file = netcdf::open(FIXTURE).unwrap();
+
+    // fail-fast by default
+    let stop = unsignalled(None);
+    let fail_fast = args(FIXTURE, &[]);
+    let sink = failing_sink();
+    let err = ingest_file(&fail_fast, &file, FIXTURE, &sink, &basins(&fail_fast), &Run::new(&stop, fail_fast.meta_cache_size)).await.unwrap_err();
+    assert!(err.to_string().contains("injected failure inserting 0.500_-60.000_-6.700"), "{}", err);
+
+    let continuing = args(FIXTURE, &["--continue-on-error"]);
+    let sink = failing_sink();
+    let summary = ingest_file(&continuing, &file, FIXTURE, &sink, &basins(&continuing), &Run::new(&stop, continuing.meta_cache_size)).await.unwrap();
+    assert_eq!(summary.cells_failed, 1);
+    assert_eq!(summary.cells, 5);
+
+    // every metadoc was written in the metadata pass, and every other cell's data documents after the failure
+    let metadocs = sink.metadocs.lock().unwrap();
+    let docs = sink.docs.lock().unwrap();
+    assert_eq!(metadocs.len(), 6);
+    assert!(!docs.contains_key("0.500_-60.000_-6.700"));
+    assert_eq!(docs.values().filter(|d| d.metadata[0] != "0.500_-60.000").count(), 8);
+    assert!(docs.contains_key("-169.500_-60.000_-6.700"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_deadline_covers_every_year_partition() {
+    let options = ["--time-min", "2011-01-01", "--data-collection", "bsose_{year}", "--meta-collection", "timeseriesMeta_{year}"];
+    let years = partitions(&args(NEGATIVE_TIME, &options), &[NEGATIVE_TIME.to_string()]).unwrap();
+    assert_eq!(years.iter().map(|(a, _)| a.data_collection.as_str()).collect::<Vec<&str>>(), vec!("bsose_2011", "bsose_2012"));
+
+    // 2011 is ingested within the run's deadline, and 2012 only starts after it; the test's clock is paused,
+    // so only advancing it passes the deadline
+    let stop = unsignalled(Some(Duration::from_secs(1)));
+    let (first, second) = (MemorySink::default(), MemorySink::default());
+    ingest_all(&years[0].0, &years[0].1, &first, &stop).await.unwrap();
+    assert_eq!(first.metadocs.lock().unwrap().len(), 6);
+    assert!(first.metadocs.lock().unwrap().values().all(|m| m.timeseries.len() == 1));
+    tokio::time::advance(Duration::from_secs(2)).await;
+    let err = ingest_all(&years[1].0, &years[1].1, &second, &stop).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<SyncError>(), Some(SyncError::Deadline)), "{}", err);
+    assert!(second.metadocs.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn the_metadoc_cache_changes_no_documents() {
+    // the second file appends a timestep to every cell the first one created
+    let stop = unsignalled(None);
+    let files = [NEGATIVE_TIME.to_string(), FIXTURE.to_string()];
+    let mut ingested = Vec::new();
+    for size in ["0", "10000"] {
+        let sink = MemorySink::default();
+        ingest_all(&args(FIXTURE, &["--time-min", "2011-01-01", "--meta-cache-size", size]), &files, &sink, &stop).await.unwrap();
+        let mut metadocs = sink.metadocs.into_inner().unwrap();
+        for meta in metadocs.values_mut() {
+            meta.date_updated_argovis = DateTime::from_millis(0);
+        }
+        ingested.push((metadocs, sink.docs.into_inner().unwrap(), sink.meta_lookups.into_inner()));
+    }
+    let (uncached, cached) = (&ingested[0], &ingested[1]);
+    assert!(uncached.0.values().all(|m| m.timeseries.len() == 4));
+    // compared as printed, since land cells store NaN
+    assert_eq!(format!("{:?}", uncached.0), format!("{:?}", cached.0));
+    assert_eq!(format!("{:?}", uncached.1), format!("{:?}", cached.1));
+
+    // without a cache each file looks every cell up in both passes; with one, only the first file's metadata pass does
+    assert_eq!(uncached.2, 24);
+    assert_eq!(cached.2, 6);
+}
+
+#[tokio::test]
+async fn a_merge_writes_the_corrected_mask_key() {
+    let stop = unsignalled(None);
+    let oxygen = args(FIXTURE, &[]);
+    let sink = MemorySink::default();
+    ingest_all(&oxygen, &[FIXTURE.to_string()], &sink, &stop).await.unwrap();
+
+    // one document as stored before the key was corrected, one as --fix-mask-key leaves it
+    let (legacy, migrated) = ("0.500_-70.000_-2.100", "-169.500_-70.000_-2.100");
+    for (id, key) in [(legacy, "sea_binary_mask_at_t_locaiton"), (migrated, "sea_binary_mask_at_t_location")] {
+        let mut stored = mongodb::bson::to_document(&sink.docs.lock().unwrap()[id]).unwrap();
+        let mask = stored.remove("sea_binary_mask_at_t_location").unwrap();
+        stored.insert(key, mask);
+        let doc = mongodb::bson::from_document::<BsoseDocument>(stored).unwrap();
+        sink.docs.lock().unwrap().insert(id.to_string(), doc);
+    }
+
+    let argv: Vec<String> = ["bsose-sync", FIXTURE, "THETA", "0", "2", "0", "3", "--basin-file", BASINS].iter().map(|a| a.to_string()).collect();
+    ingest_all(&Args::parse(&argv).unwrap(), &[FIXTURE.to_string()], &sink, &stop).await.unwrap();
+    for id in [legacy, migrated] {
+        let doc = &sink.docs.lock().unwrap()[id];
+        assert_eq!(doc.data_info.0, vec!(String::from("TRAC02"), String::from("THETA")));
+        let written = mongodb::bson::to_document(doc).unwrap();
+        assert!(written.get_bool("sea_binary_mask_at_t_location").unwrap(), "{}", id);
+        assert!(!written.contains_key("sea_binary_mask_at_t_locaiton"), "{}", id);
+    }
+}
+
+#[tokio::test]
+async fn append_only_leaves_stored_values_alone() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let stop = unsignalled(None);
+    let first = args(FIXTURE, &[]);
+    let sink = MemorySink::default();
+    ingest_file(&first, &file, FIXTURE, &sink, &basins(&first), &Run::new(&stop, first.meta_cache_size)).await.unwrap();
+
+    // a stored value the file disagrees with
+    let id = "0.500_-70.000_-2.100";
+    sink.docs.lock().unwrap().get_mut(id).unwrap().data[0][1] = 42.0;
+    let stored = format!("{:?}", sink.docs.lock().unwrap());
+
+    let append_only = args(FIXTURE, &["--append-only", "--overwrite"]);
+    let err = ingest_file(&append_only, &file, FIXTURE, &sink, &basins(&append_only), &Run::new(&stop, append_only.meta_cache_size)).await.unwrap_err();
+    assert!(err.to_string().contains(&format!("--append-only: document {} would overwrite 1 stored TRAC02 values at timestep indexes [1]", id)), "{}", err);
+    assert_eq!(format!("{:?}", sink.docs.lock().unwrap()), stored);
+
+    // nor can a stored column be truncated to the timeseries
+    let argv: Vec<String> = ["bsose-sync", FIXTURE, "TRAC02", "0", "2", "0", "3", "--append-only", "--on-inconsistent", "repair"].iter().map(|a| a.to_string()).collect();
+    let err = Args::parse(&argv).err().unwrap().to_string();
+    assert!(err.contains("--append-only"), "{}", err);
+}
@@ -0,0 +1,28 @@
+use bsose_sync::input;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+const GZIPPED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc.gz");
+
+#[test]
+fn plain_files_open_in_place() {
+    let file = input::open(FIXTURE).unwrap();
+    assert!(file.variable("TRAC02").is_some());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzipped_files_match_the_uncompressed_fixture() {
+    let plain = input::open(FIXTURE).unwrap();
+    let gzipped = input::open(GZIPPED).unwrap();
+    let names = |f: &netcdf::File| f.variables().map(|v| v.name()).collect::<Vec<_>>();
+    assert_eq!(names(&gzipped), names(&plain));
+    let first = |f: &netcdf::File| f.variable("TRAC02").unwrap().value::<f64, _>([0, 0, 0, 0]).unwrap();
+    assert_eq!(first(&gzipped), first(&plain));
+}
+
+#[cfg(not(feature = "gzip"))]
+#[test]
+fn gzipped_files_need_the_feature() {
+    let err = input::open(GZIPPED).err().unwrap();
+    assert!(err.to_string().contains("--features gzip"));
+}
@@ -0,0 +1,96 @@
+use bsose_sync::{CellTiming, Summary, ValueStats, SLOWEST_CELLS};
+use bsose_sync::metrics::Progress;
+
+fn value(text: &str, metric: &str) -> f64 {
+    text.lines().find_map(|l| l.strip_prefix(&format!("{} ", metric))).unwrap().parse().unwrap()
+}
+
+#[test]
+fn counters_cover_finished_and_current_files() {
+    let progress = Progress::default();
+    progress.finish("TRAC02", &Summary{cells: 4, data_inserted: 10, ..Summary::default()});
+    progress.set_current("TRAC02", &Summary{cells: 2, data_updated: 3, data_skipped: 1, ..Summary::default()});
+
+    let text = progress.render();
+    assert_eq!(value(&text, "bsose_cells_processed_total"), 6.0);
+    assert_eq!(value(&text, "bsose_data_inserted_total"), 10.0);
+    assert_eq!(value(&text, "bsose_data_updated_total"), 3.0);
+    assert_eq!(value(&text, "bsose_data_skipped_total"), 1.0);
+    assert!(text.contains("# TYPE bsose_cells_per_second gauge"));
+    assert_eq!(progress.total().cells, 6);
+}
+
+#[test]
+fn a_failed_file_drops_its_running_counts() {
+    // matching the end of run summary, which only counts files that finished
+    let progress = Progress::default();
+    progress.set_current("TRAC02", &Summary{cells: 2, ..Summary::default()});
+    progress.finish("TRAC02", &Summary::default());
+    assert_eq!(progress.total().cells, 0);
+}
+
+#[cfg(feature = "metrics")]
+#[tokio::test]
+async fn server_answers_scrapes_until_shut_down() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let progress = std::sync::Arc::new(Progress::default());
+    progress.set_current("TRAC02", &Summary{cells: 7, ..Summary::default()});
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let server = bsose_sync::metrics::serve(&addr, progress).await.unwrap();
+
+    let mut stream = tokio::net::TcpStream::connect(&addr).await.unwrap();
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("bsose_cells_processed_total 7"));
+
+    server.shutdown().await;
+    assert!(tokio::net::TcpStream::connect(&addr).await.is_err());
+}
+
+fn timing(cell: &str, seconds: f64) -> CellTiming {
+    CellTiming{cell: String::from(cell), variable: String::from("TRAC02"), seconds: seconds, documents: 52}
+}
+
+#[test]
+fn summaries_keep_the_slowest_cells() {
+    let mut file = Summary::default();
+    for (i, seconds) in [3.0, 40.0, 1.0, 12.0, 7.0, 0.5, 25.0].iter().enumerate() {
+        file.time_cell(timing(&format!("cell{}", i), *seconds));
+    }
+    let seconds: Vec<f64> = file.slowest_cells.iter().map(|t| t.seconds).collect();
+    assert_eq!(seconds, vec!(40.0, 25.0, 12.0, 7.0, 3.0));
+    assert_eq!(file.slowest_cells.len(), SLOWEST_CELLS);
+
+    // a run total takes the slowest of every file
+    let mut total = Summary::default();
+    total.add(&file);
+    total.add(&Summary{slowest_cells: vec!(timing("other", 30.0)), ..Summary::default()});
+    let cells: Vec<&str> = total.slowest_cells.iter().map(|t| t.cell.as_str()).collect();
+    assert_eq!(cells, vec!("cell1", "other", "cell6", "cell3", "cell4"));
+    assert!(total.to_string().contains("slow cell: cell1 TRAC02 took 40.0s over 52 documents"));
+}
+
+#[test]
+fn value_statistics_skip_nan_and_accumulate() {
+    let mut stats = ValueStats::of(&[2.0, f64::NAN, -1.0, 5.0]);
+    assert_eq!((stats.values, stats.nan, stats.min, stats.max), (4, 1, Some(-1.0), Some(5.0)));
+    let empty = ValueStats::of(&[f64::NAN, f64::NAN]);
+    assert_eq!((empty.min, empty.max), (None, None));
+    assert!(empty.to_string().contains("100.0% NaN, no values other than NaN"));
+
+    stats.add(&empty);
+    stats.add(&ValueStats::of(&[7.5]));
+    assert_eq!((stats.values, stats.nan, stats.min, stats.max), (7, 3, Some(-1.0), Some(7.5)));
+    assert_eq!(stats.nan_fraction(), 3.0 / 7.0);
+
+    // a run total keeps each variable's statistics apart
+    let mut total = Summary::default();
+    total.add(&Summary{values: [(String::from("TRAC02"), stats.clone())].into(), ..Summary::default()});
+    total.add(&Summary{values: [(String::from("THETA"), empty.clone()), (String::from("TRAC02"), ValueStats::of(&[9.0]))].into(), ..Summary::default()});
+    assert_eq!(total.values["TRAC02"].max, Some(9.0));
+    assert_eq!(total.values["THETA"], empty);
+}
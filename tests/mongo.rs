@@ -0,0 +1,543 @@
+// run with `cargo test --features mongo-integration`; skips when docker isn't available
+#![cfg(feature = "mongo-integration")]
+
+use std::process::Command;
+use std::time::Duration;
+use bsose_sync::{BsoseDocument, BsoseMetadoc, CellRecord};
+use bsose_sync::sink::{CellTransaction, DocumentSink, MongoSink};
+use futures::stream::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::options::WriteConcern;
+use testcontainers::{clients::Cli, core::WaitFor, images::{generic::GenericImage, mongo::Mongo}, RunnableImage};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+const BASINS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/basinmask_fixture.nc");
+
+fn docker_available() -> bool {
+    match Command::new("docker").arg("info").output() {
+        Ok(out) => out.status.success(),
+        Err(_) => false
+    }
+}
+
+fn ingest(uri: &str, variable: &str, iter: &str) {
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, variable, "0", "2", "0", "3"])
+        .args(["--db", "bsose_test", "--write-concern", "1", "--iter", iter, "--basin-file", BASINS])
+        .env("MONGODB_URI", uri)
+        .status()
+        .unwrap();
+    assert!(status.success(), "ingest of {} failed with {}", variable, status);
+}
+
+#[tokio::test]
+async fn two_ingests_merge_into_the_same_documents() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+
+    ingest(&uri, "TRAC02", "156");
+    ingest(&uri, "THETA", "157");
+
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+
+    // one metadoc per cell, carrying both runs' provenance
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 6);
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-70.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.timeseries.len(), 3);
+    let iters: Vec<String> = meta.source.iter().map(|s| s.iter.clone()).collect();
+    assert_eq!(iters, vec!(String::from("156"), String::from("157")));
+
+    // both variables land in the same data document, in ingest order
+    let data = bsose.find_one(doc! {"_id": "0.500_-60.000_-6.700"}, None).await.unwrap().unwrap();
+    assert_eq!(data.data, vec!(vec!(111.0, 112.0, 113.0), vec!(55.5, 56.0, 56.5)));
+    assert_eq!(data.data_info.0, vec!(String::from("TRAC02"), String::from("THETA")));
+    assert!(data.data.iter().all(|d| d.len() == meta.timeseries.len()));
+
+    // the land cell never gets data documents
+    assert_eq!(bsose.count_documents(doc! {"metadata": "-0.500_-60.000"}, None).await.unwrap(), 0);
+    assert_eq!(bsose.count_documents(None, None).await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn each_iteration_is_recorded_once_as_a_source() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+
+    // a second iteration of the same file, then the same iteration again
+    ingest(&uri, "TRAC02", "156");
+    ingest(&uri, "TRAC02", "157");
+    ingest(&uri, "TRAC02", "157");
+
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 6);
+    let mut metadocs = bsose_meta.find(None, None).await.unwrap();
+    while metadocs.advance().await.unwrap() {
+        let meta = metadocs.deserialize_current().unwrap();
+        let sources: Vec<(String, String)> = meta.source.iter().map(|s| (s.source.join(","), s.iter.clone())).collect();
+        assert_eq!(sources, vec!((String::from("BSOSE"), String::from("156")), (String::from("BSOSE"), String::from("157"))), "{}", meta._id);
+    }
+}
+
+#[tokio::test]
+async fn repeated_metadoc_timestamps_are_repaired() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    ingest(&uri, "TRAC02", "156");
+
+    // seed a duplicate like an older run could leave: the last timestep stored twice
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    for metaid in ["0.500_-60.000", "0.500_-70.000"] {
+        let mut meta = bsose_meta.find_one(doc! {"_id": metaid}, None).await.unwrap().unwrap();
+        meta.timeseries.push(meta.timeseries[2]);
+        bsose_meta.replace_one(doc! {"_id": metaid}, meta, None).await.unwrap();
+    }
+    bsose.update_many(doc! {"metadata": "0.500_-60.000"}, doc! {"$push": {"data.0": 999.0}}, None).await.unwrap();
+    // one level of the other cell never got its value, so that cell's indexes can't be trusted
+    bsose.update_one(doc! {"_id": "0.500_-70.000_-6.700"}, doc! {"$push": {"data.0": 999.0}}, None).await.unwrap();
+
+    let repair = |dry_run: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_bsose-sync"));
+        command.args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--repair-duplicates"])
+            .env("MONGODB_URI", &uri);
+        if dry_run {
+            command.arg("--dry-run");
+        }
+        assert!(command.status().unwrap().success());
+    };
+
+    // a dry run changes nothing
+    repair(true);
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.timeseries.len(), 4);
+
+    repair(false);
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.timeseries.len(), 3);
+    let data = bsose.find_one(doc! {"_id": "0.500_-60.000_-6.700"}, None).await.unwrap().unwrap();
+    assert_eq!(data.data, vec!(vec!(111.0, 112.0, 113.0)));
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-70.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.timeseries.len(), 4);
+    let data = bsose.find_one(doc! {"_id": "0.500_-70.000_-6.700"}, None).await.unwrap().unwrap();
+    assert_eq!(data.data, vec!(vec!(11.0, 12.0, 13.0, 999.0)));
+}
+
+#[tokio::test]
+async fn lost_metadocs_are_rebuilt_from_the_files() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    ingest(&uri, "TRAC02", "156");
+
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    let original = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    bsose_meta.drop(None).await.unwrap();
+
+    let rebuild = || {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "156", "--rebuild-meta"])
+            .env("MONGODB_URI", &uri)
+            .status()
+            .unwrap()
+    };
+
+    assert!(rebuild().success());
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 6);
+    let mut rebuilt = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    rebuilt.date_updated_argovis = original.date_updated_argovis;
+    assert_eq!(rebuilt, original);
+
+    // a data document that doesn't line up with the files leaves its cell alone and fails the run
+    let mut data = bsose.find_one(doc! {"_id": "0.500_-60.000_-6.700"}, None).await.unwrap().unwrap();
+    data.data[0].push(999.0);
+    bsose.replace_one(doc! {"_id": "0.500_-60.000_-6.700"}, data, None).await.unwrap();
+    bsose_meta.delete_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap();
+    assert!(!rebuild().success());
+    assert!(bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().is_none());
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 5);
+}
+
+#[tokio::test]
+async fn resume_skips_cells_the_run_recorded() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    let run = |run_id: &str, options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "156", "--basin-file", BASINS, "--run-id", run_id])
+            .args(options)
+            .env("MONGODB_URI", &uri)
+            .status()
+            .unwrap()
+    };
+
+    // a first attempt that only gets through two cells
+    assert!(run("tile-7", &["--limit", "2"]).success());
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let progress = client.database("bsose_test").collection::<CellRecord>("ingest_progress");
+    let records: Vec<CellRecord> = progress.find(None, None).await.unwrap().try_collect().await.unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.run_id == "tile-7" && r.file == "bsose_fixture.nc" && r.variable == "TRAC02"));
+    let done: Vec<String> = records.iter().map(|r| r.cell.clone()).collect();
+
+    // removing the finished cells' data shows the resumed run doesn't touch them
+    bsose.delete_many(doc! {}, None).await.unwrap();
+    assert!(run("tile-7", &["--resume"]).success());
+    assert_eq!(progress.count_documents(None, None).await.unwrap(), 6);
+    let written: Vec<BsoseDocument> = bsose.find(None, None).await.unwrap().try_collect().await.unwrap();
+    assert!(!written.is_empty());
+    assert!(written.iter().all(|d| !done.contains(&d.metadata[0])));
+
+    // a different run id starts from scratch
+    assert!(run("tile-8", &["--resume"]).success());
+    assert_eq!(progress.count_documents(doc! {"run_id": "tile-8"}, None).await.unwrap(), 6);
+    let cells: Vec<String> = bsose.find(None, None).await.unwrap().try_collect::<Vec<BsoseDocument>>().await.unwrap().iter().map(|d| d.metadata[0].clone()).collect();
+    assert!(done.iter().all(|c| cells.contains(c)));
+}
+
+async fn snapshot(collection: &mongodb::Collection<mongodb::bson::Document>) -> Vec<Vec<u8>> {
+    // every document's BSON bytes in id order, so NaNs compare by bit pattern rather than as unequal
+    let options = mongodb::options::FindOptions::builder().sort(doc! {"_id": 1}).build();
+    let docs: Vec<mongodb::bson::Document> = collection.find(None, options).await.unwrap().try_collect().await.unwrap();
+    docs.iter().map(|d| mongodb::bson::to_vec(d).unwrap()).collect()
+}
+
+#[tokio::test]
+async fn reingesting_a_file_changes_nothing() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    ingest(&uri, "TRAC02", "156");
+    ingest(&uri, "THETA", "157");
+
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<mongodb::bson::Document>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<mongodb::bson::Document>("timeseriesMeta");
+    let data = snapshot(&bsose).await;
+    let metadocs = snapshot(&bsose_meta).await;
+
+    // the same files and iterations again, in either order
+    ingest(&uri, "THETA", "157");
+    ingest(&uri, "TRAC02", "156");
+    assert_eq!(snapshot(&bsose).await, data);
+    assert_eq!(snapshot(&bsose_meta).await, metadocs);
+
+    let meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta")
+        .find_one(doc! {"_id": "0.500_-70.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.source.len(), 2);
+    assert_eq!(meta.timeseries.len(), 3);
+}
+
+#[tokio::test]
+async fn compact_needs_the_dry_run_count() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    ingest(&uri, "TRAC02", "156");
+
+    // blank the middle timestep of every document in one cell
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    let docs: Vec<BsoseDocument> = bsose.find(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap().try_collect().await.unwrap();
+    assert!(!docs.is_empty());
+    for mut doc in docs {
+        doc.data[0][1] = f64::NAN;
+        bsose.replace_one(doc! {"_id": doc._id.clone()}, doc, None).await.unwrap();
+    }
+    let original = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+
+    let compact = |options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--compact"])
+            .args(options)
+            .env("MONGODB_URI", &uri)
+            .status()
+            .unwrap()
+    };
+    assert_eq!(compact(&[]).code(), Some(2));
+    assert!(compact(&["--dry-run"]).success());
+    assert!(!compact(&["--confirm", "2"]).success());
+    assert_eq!(bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap().timeseries.len(), 3);
+
+    assert!(compact(&["--confirm", "1"]).success());
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    assert_eq!(meta.timeseries, vec!(original.timeseries[0], original.timeseries[2]));
+    let data = bsose.find_one(doc! {"_id": "0.500_-60.000_-6.700"}, None).await.unwrap().unwrap();
+    assert_eq!(data.data, vec!(vec!(111.0, 113.0)));
+    // other cells keep every timestep
+    assert_eq!(bsose_meta.find_one(doc! {"_id": "0.500_-70.000"}, None).await.unwrap().unwrap().timeseries.len(), 3);
+}
+
+fn replica_set() -> RunnableImage<GenericImage> {
+    // a single-node replica set, since a standalone server can't run transactions
+    let image = GenericImage::new("mongo", "5.0.6").with_wait_for(WaitFor::message_on_stdout("Waiting for connections"));
+    RunnableImage::from((image, vec!(String::from("--replSet"), String::from("rs0"))))
+}
+
+async fn initiate(uri: &str) {
+    // the member's own address is only reachable inside the container, so clients connect directly
+    let client = mongodb::Client::with_uri_str(uri).await.unwrap();
+    let admin = client.database("admin");
+    admin.run_command(doc! {"replSetInitiate": {"_id": "rs0", "members": [{"_id": 0, "host": "127.0.0.1:27017"}]}}, None).await.unwrap();
+    while !admin.run_command(doc! {"hello": 1}, None).await.unwrap().get_bool("isWritablePrimary").unwrap_or(false) {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+#[tokio::test]
+async fn an_unfinished_cell_transaction_writes_nothing() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(replica_set());
+    let uri = format!("mongodb://127.0.0.1:{}/?directConnection=true", node.get_host_port_ipv4(27017));
+    initiate(&uri).await;
+
+    let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+        .args(["--db", "bsose_test", "--iter", "156", "--basin-file", BASINS, "--transactions"])
+        .env("MONGODB_URI", &uri)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    let progress = client.database("bsose_test").collection::<CellRecord>("ingest_progress");
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 6);
+    assert_eq!(bsose.count_documents(None, None).await.unwrap(), 10);
+
+    // take one cell out, then write it back through a transaction that never commits
+    let meta = bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().unwrap();
+    let data: Vec<BsoseDocument> = bsose.find(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap().try_collect().await.unwrap();
+    assert!(!data.is_empty());
+    bsose_meta.delete_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap();
+    bsose.delete_many(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap();
+    let sink = |session| MongoSink{
+        bsose: bsose.clone(),
+        bsose_meta: bsose_meta.clone(),
+        ingest_progress: progress.clone(),
+        transaction: Some(tokio::sync::Mutex::new(CellTransaction::new(session, WriteConcern::MAJORITY)))
+    };
+    let aborted = sink(client.start_session(None).await.unwrap());
+    aborted.begin_cell().await.unwrap();
+    aborted.insert_meta(meta.clone()).await.unwrap();
+    for doc in &data {
+        aborted.insert_data(doc.clone()).await.unwrap();
+    }
+    drop(aborted);
+    assert!(bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().is_none());
+    assert_eq!(bsose.count_documents(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap(), 0);
+
+    // the same writes, committed, land together
+    let committed = sink(client.start_session(None).await.unwrap());
+    committed.begin_cell().await.unwrap();
+    committed.insert_meta(meta).await.unwrap();
+    for doc in &data {
+        committed.insert_data(doc.clone()).await.unwrap();
+    }
+    committed.commit_cell().await.unwrap();
+    assert!(bsose_meta.find_one(doc! {"_id": "0.500_-60.000"}, None).await.unwrap().is_some());
+    assert_eq!(bsose.count_documents(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap(), data.len() as u64);
+}
+
+#[tokio::test]
+async fn transactions_fall_back_on_a_standalone_server() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+        .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "156", "--basin-file", BASINS, "--transactions"])
+        .env("MONGODB_URI", &uri)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("can't run transactions"));
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    assert_eq!(client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta").count_documents(None, None).await.unwrap(), 6);
+}
+
+#[tokio::test]
+async fn continue_on_error_skips_a_failing_cell() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    ingest(&uri, "TRAC02", "156");
+
+    // one cell's stored metadoc moved off its id's coordinates, which fails that cell alone
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    bsose_meta.update_one(doc! {"_id": "0.500_-60.000"}, doc! {"$set": {"latitude": -59.0}}, None).await.unwrap();
+    bsose.delete_many(doc! {}, None).await.unwrap();
+
+    let run = |options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "THETA", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "157", "--basin-file", BASINS])
+            .args(options)
+            .env("MONGODB_URI", &uri)
+            .output()
+            .unwrap()
+    };
+    // fail-fast by default
+    assert_eq!(run(&[]).status.code(), Some(2));
+
+    bsose.delete_many(doc! {}, None).await.unwrap();
+    let output = run(&["--continue-on-error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cell 0.500_-60.000"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 failed"));
+    assert_eq!(bsose.count_documents(doc! {"metadata": "0.500_-60.000"}, None).await.unwrap(), 0);
+    assert_eq!(bsose.count_documents(None, None).await.unwrap(), 8);
+}
+
+#[tokio::test]
+async fn force_new_stops_at_a_stored_document() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    let run = |variable: &str| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, variable, "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--basin-file", BASINS, "--force-new"])
+            .env("MONGODB_URI", &uri)
+            .output()
+            .unwrap()
+    };
+
+    // into empty collections it writes what a normal ingest does
+    let first = run("TRAC02");
+    assert!(first.status.success(), "{}", String::from_utf8_lossy(&first.stderr));
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose = client.database("bsose_test").collection::<BsoseDocument>("bsose");
+    assert_eq!(bsose.count_documents(None, None).await.unwrap(), 10);
+
+    // the second run's first metadoc is already there
+    let second = run("THETA");
+    assert_eq!(second.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(stderr.contains("--force-new: could not insert") && stderr.contains("E11000"), "{}", stderr);
+    let data = bsose.find_one(doc! {"_id": "0.500_-60.000_-6.700"}, None).await.unwrap().unwrap();
+    assert_eq!(data.data_info.0, vec!(String::from("TRAC02")));
+}
+
+#[tokio::test]
+async fn replace_source_leaves_only_the_new_iteration() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+
+    ingest(&uri, "TRAC02", "156");
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    let iters = |meta: BsoseMetadoc| meta.source.iter().map(|s| s.iter.clone()).collect::<Vec<String>>();
+    for _ in 0..2 {
+        // the second run finds 157 already the only source and leaves the metadocs as they are
+        let status = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "THETA", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "157", "--basin-file", BASINS, "--replace-source"])
+            .env("MONGODB_URI", &uri)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let meta = bsose_meta.find_one(doc! {"_id": "0.500_-70.000"}, None).await.unwrap().unwrap();
+        assert_eq!(iters(meta), vec!(String::from("157")));
+    }
+}
+
+#[tokio::test]
+async fn rebuilt_metadocs_keep_the_longitude_convention() {
+    if !docker_available() {
+        eprintln!("docker not available, skipping mongo integration test");
+        return;
+    }
+    let docker = Cli::default();
+    let node = docker.run(Mongo);
+    let uri = format!("mongodb://127.0.0.1:{}", node.get_host_port_ipv4(27017));
+    let run = |options: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+            .args([FIXTURE, "TRAC02", "0", "2", "0", "3"])
+            .args(["--db", "bsose_test", "--write-concern", "1", "--iter", "156", "--basin-file", BASINS, "--lon-convention", "zero360"])
+            .args(options)
+            .env("MONGODB_URI", &uri)
+            .status()
+            .unwrap()
+    };
+    assert!(run(&[]).success());
+
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let bsose_meta = client.database("bsose_test").collection::<BsoseMetadoc>("timeseriesMeta");
+    let original = bsose_meta.find_one(doc! {"_id": "190.500_-70.000"}, None).await.unwrap().unwrap();
+    bsose_meta.drop(None).await.unwrap();
+
+    // the rebuild finds the data documents under the zero360 ids, and writes no pm180 metadocs beside them
+    assert!(run(&["--rebuild-meta"]).success());
+    assert_eq!(bsose_meta.count_documents(None, None).await.unwrap(), 6);
+    assert!(bsose_meta.find_one(doc! {"_id": "-169.500_-70.000"}, None).await.unwrap().is_none());
+    let mut rebuilt = bsose_meta.find_one(doc! {"_id": "190.500_-70.000"}, None).await.unwrap().unwrap();
+    rebuilt.date_updated_argovis = original.date_updated_argovis;
+    assert_eq!(rebuilt, original);
+}
@@ -0,0 +1,101 @@
+use std::process::Command;
+use bsose_sync::{CellRecord, Extractor, LatIdx, LevelIdx, LonIdx, Sourcedoc};
+use bsose_sync::schema;
+use bson::{Bson, Document};
+use serde_json::Value;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+
+fn bson_type(value: &Bson) -> &'static str {
+    match value {
+        Bson::Double(_) => "double",
+        Bson::String(_) => "string",
+        Bson::Array(_) => "array",
+        Bson::Document(_) => "object",
+        Bson::Boolean(_) => "bool",
+        Bson::Null => "null",
+        Bson::Int32(_) => "int",
+        Bson::Int64(_) => "long",
+        Bson::DateTime(_) => "date",
+        _ => "other"
+    }
+}
+
+fn conforms(path: &str, value: &Bson, schema: &Value) {
+    // the subset of $jsonSchema the hand-written schemas use
+    match &schema["bsonType"] {
+        Value::String(t) => assert_eq!(bson_type(value), t, "{}", path),
+        Value::Array(ts) => assert!(ts.iter().any(|t| t == bson_type(value)), "{} is a {}", path, bson_type(value)),
+        _ => {}
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        assert!(allowed.iter().any(|a| Some(a.as_str().unwrap()) == value.as_str()), "{}", path);
+    }
+    match value {
+        Bson::Document(doc) => conforms_object(path, doc, schema),
+        Bson::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                let item_schema = match &schema["items"] {
+                    Value::Array(positional) => &positional[i],
+                    other => other
+                };
+                conforms(&format!("{}[{}]", path, i), item, item_schema);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn conforms_object(path: &str, doc: &Document, schema: &Value) {
+    for key in schema["required"].as_array().into_iter().flatten() {
+        assert!(doc.contains_key(key.as_str().unwrap()), "{} lacks required {}", path, key);
+    }
+    for (key, value) in doc {
+        let field = &schema["properties"][key];
+        let field = if field.is_null() { &schema["additionalProperties"] } else { field };
+        assert_ne!(field, &Value::Bool(false), "{}.{} isn't in the schema", path, key);
+        conforms(&format!("{}.{}", path, key), value, field);
+    }
+}
+
+#[test]
+fn serialized_documents_match_their_schemas() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let mut source = Sourcedoc{
+        source: vec!(String::from("BSOSE")),
+        iter: String::from("156"),
+        file: String::from("bsose_fixture.nc"),
+        attributes: Default::default()
+    };
+    source.attributes.insert(String::from("title"), String::from("fixture"));
+    let meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), source).unwrap();
+    conforms_object("metadoc", &bson::to_document(&meta).unwrap(), &schema::metadoc());
+
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(1), LonIdx(0)).unwrap();
+    let mut doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(1), LonIdx(0), 5, profile).unwrap();
+    conforms_object("data", &bson::to_document(&doc).unwrap(), &schema::data_document());
+    doc.timeline = Some(bsose_sync::TimelineRef::of(&meta.timeseries));
+    conforms_object("data", &bson::to_document(&doc).unwrap(), &schema::data_document());
+    doc.chunk = Some(bsose_sync::Chunk{index: 1, count: 2, start: 2});
+    conforms_object("data", &bson::to_document(&doc).unwrap(), &schema::data_document());
+    let mut meta = meta;
+    meta.chunked.push(doc._id.clone());
+    conforms_object("metadoc", &bson::to_document(&meta).unwrap(), &schema::metadoc());
+
+    let record = CellRecord::new("tile-7", "bsose_fixture.nc", "TRAC02", &meta._id);
+    conforms_object("record", &bson::to_document(&record).unwrap(), &schema::cell_record());
+}
+
+#[test]
+fn print_schema_needs_no_file_or_database() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .arg("--print-schema")
+        .env_remove("MONGODB_URI")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let printed: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(printed, schema::schemas("bsose", "timeseriesMeta"));
+    assert_eq!(printed["bsose"]["properties"]["data_info"]["items"].as_array().unwrap().len(), 3);
+}
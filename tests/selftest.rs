@@ -0,0 +1,22 @@
+use std::process::Command;
+
+#[test]
+fn built_in_checks_pass() {
+    let failures: Vec<String> = bsose_sync::selftest::run().into_iter()
+        .filter_map(|c| c.failure.map(|f| format!("{}: {}", c.name, f)))
+        .collect();
+    assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn self_test_needs_no_file_or_database() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bsose-sync"))
+        .arg("--self-test")
+        .env_remove("MONGODB_URI")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let checks = bsose_sync::selftest::run().len();
+    assert!(stdout.contains(&format!("{} of {} checks passed", checks, checks)), "{}", stdout);
+}
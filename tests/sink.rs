@@ -0,0 +1,156 @@
+use bsose_sync::{CellRecord, Extractor, Sourcedoc, LatIdx, LonIdx, LevelIdx};
+use std::time::Duration;
+use mongodb::bson::DateTime;
+use bsose_sync::sink::{DocumentSink, MemorySink, IdLocks, write_concern};
+use mongodb::options::Acknowledgment;
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+
+fn sourcedoc(iter: &str) -> Sourcedoc {
+    Sourcedoc{
+        source: vec!(String::from("BSOSE")),
+        iter: String::from(iter),
+        file: String::from("bsose_fixture.nc"),
+        attributes: Default::default()
+    }
+}
+
+#[tokio::test]
+async fn provenance_is_recorded_once_per_iteration() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let sink = MemorySink::default();
+    sink.insert_meta(extractor.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc("156")).unwrap()).await.unwrap();
+
+    assert!(!sink.add_meta_source("0.500_-70.000", &sourcedoc("156")).await.unwrap());
+    assert!(sink.add_meta_source("0.500_-70.000", &sourcedoc("157")).await.unwrap());
+    let meta = sink.find_meta("0.500_-70.000").await.unwrap().unwrap();
+    let iters: Vec<String> = meta.source.iter().map(|s| s.iter.clone()).collect();
+    assert_eq!(iters, vec!(String::from("156"), String::from("157")));
+
+    sink.set_meta_timeseries("0.500_-70.000", &extractor.timeseries[..1]).await.unwrap();
+    assert_eq!(sink.find_meta("0.500_-70.000").await.unwrap().unwrap().timeseries.len(), 1);
+    assert!(sink.find_meta("1.500_-70.000").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn extending_the_timeseries_refreshes_the_update_time() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let sink = MemorySink::default();
+    let mut meta = extractor.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc("156")).unwrap();
+    meta.timeseries.truncate(2);
+    meta.date_updated_argovis = DateTime::from_millis(0);
+    sink.insert_meta(meta).await.unwrap();
+    let updated = || async { sink.find_meta("0.500_-70.000").await.unwrap().unwrap().date_updated_argovis };
+
+    // rewriting the timeseries it already has is a no-op
+    sink.set_meta_timeseries("0.500_-70.000", &extractor.timeseries[..2]).await.unwrap();
+    assert_eq!(updated().await, DateTime::from_millis(0));
+    sink.set_meta_timeseries("0.500_-70.000", &extractor.timeseries).await.unwrap();
+    assert!(updated().await > DateTime::from_millis(0));
+}
+
+#[tokio::test]
+async fn replace_source_resets_provenance() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let sink = MemorySink::default();
+    sink.insert_meta(extractor.metadoc(3, LatIdx(0), LonIdx(0), sourcedoc("156")).unwrap()).await.unwrap();
+    assert!(sink.add_meta_source("0.500_-70.000", &sourcedoc("155")).await.unwrap());
+
+    assert!(sink.replace_meta_source("0.500_-70.000", &sourcedoc("157")).await.unwrap());
+    let meta = sink.find_meta("0.500_-70.000").await.unwrap().unwrap();
+    assert_eq!(meta.source, vec!(sourcedoc("157")));
+    // already the only source, as it is for every later variable and file of the run
+    assert!(!sink.replace_meta_source("0.500_-70.000", &sourcedoc("157")).await.unwrap());
+    assert!(!sink.replace_meta_source("1.500_-70.000", &sourcedoc("157")).await.unwrap());
+}
+
+#[tokio::test]
+async fn data_documents_insert_once_and_then_replace() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let extractor = Extractor::new(&file, "TRAC02").unwrap();
+    let sink = MemorySink::default();
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, profile).unwrap();
+
+    sink.insert_data(doc.clone()).await.unwrap();
+    assert!(sink.insert_data(doc.clone()).await.is_err());
+
+    let mut stored = sink.find_data(&doc._id).await.unwrap().unwrap();
+    assert_eq!(stored, doc);
+    stored.data[0][1] = f64::NAN;
+    sink.replace_data(stored).await.unwrap();
+    assert!(sink.find_data(&doc._id).await.unwrap().unwrap().data[0][1].is_nan());
+}
+
+async fn write_variable(sink: &MemorySink, locks: &IdLocks, extractor: &Extractor<'_>) {
+    // the find, merge and write of one level, with a yield where another variable could get in between
+    let profile = extractor.profile(Some(LevelIdx(0)), LatIdx(0), LonIdx(0)).unwrap();
+    let doc = extractor.document(3, Some(LevelIdx(0)), LatIdx(0), LonIdx(0), 10, profile.clone()).unwrap();
+    let _lock = locks.lock(&doc._id).await;
+    let stored = sink.find_data(&doc._id).await.unwrap();
+    tokio::task::yield_now().await;
+    match stored {
+        Some(mut stored) => {
+            extractor.merge(&mut stored, profile, false);
+            sink.replace_data(stored).await.unwrap();
+        }
+        None => sink.insert_data(doc).await.unwrap()
+    }
+}
+
+#[tokio::test]
+async fn variables_racing_on_one_document_both_land() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let trac = Extractor::new(&file, "TRAC02").unwrap();
+    let theta = Extractor::new(&file, "THETA").unwrap();
+    let sink = MemorySink::default();
+    let locks = IdLocks::default();
+
+    // without the lock both would find nothing and the second insert would fail
+    tokio::join!(write_variable(&sink, &locks, &trac), write_variable(&sink, &locks, &theta));
+
+    let doc = sink.find_data("0.500_-70.000_-2.100").await.unwrap().unwrap();
+    assert_eq!(doc.data_info.0.len(), 2);
+    assert!(doc.data_info.0.contains(&String::from("TRAC02")));
+    assert!(doc.data_info.0.contains(&String::from("THETA")));
+    assert!(locks.is_empty());
+}
+
+#[tokio::test]
+async fn completed_cells_are_kept_per_run_file_and_variable() {
+    let sink = MemorySink::default();
+    sink.record_cell(CellRecord::new("tile-7", "bsose_fixture.nc", "TRAC02", "0.500_-70.000")).await.unwrap();
+    sink.record_cell(CellRecord::new("tile-7", "bsose_fixture.nc", "TRAC02", "0.500_-60.000")).await.unwrap();
+    // recording a cell again replaces its record
+    sink.record_cell(CellRecord::new("tile-7", "bsose_fixture.nc", "TRAC02", "0.500_-60.000")).await.unwrap();
+    sink.record_cell(CellRecord::new("tile-7", "bsose_fixture.nc", "THETA", "1.500_-70.000")).await.unwrap();
+    sink.record_cell(CellRecord::new("tile-8", "bsose_fixture.nc", "TRAC02", "1.500_-60.000")).await.unwrap();
+
+    let done = sink.completed_cells("tile-7", "bsose_fixture.nc", "TRAC02").await.unwrap();
+    assert_eq!(done.len(), 2);
+    assert!(done.contains("0.500_-70.000") && done.contains("0.500_-60.000"));
+    assert_eq!(sink.completed_cells("tile-7", "bsose_fixture.nc", "THETA").await.unwrap().len(), 1);
+    assert!(sink.completed_cells("tile-7", "other.nc", "TRAC02").await.unwrap().is_empty());
+    assert_eq!(sink.cells.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn write_concerns_take_a_node_count_and_timeout() {
+    let concern = write_concern("2", true, Some(Duration::from_millis(5000))).unwrap();
+    assert_eq!(concern.w, Some(Acknowledgment::Nodes(2)));
+    assert_eq!(concern.w_timeout, Some(Duration::from_millis(5000)));
+    assert_eq!(concern.journal, Some(true));
+
+    let concern = write_concern("majority", false, None).unwrap();
+    assert_eq!(concern.w, Some(Acknowledgment::Majority));
+    assert_eq!(concern.w_timeout, None);
+
+    // unacknowledged writes can't be journaled or time out waiting for acknowledgement
+    assert_eq!(write_concern("0", true, None).unwrap().journal, Some(false));
+    assert!(write_concern("0", true, Some(Duration::from_millis(100))).is_err());
+    assert!(write_concern("most", true, None).is_err());
+    assert!(write_concern("-1", true, None).is_err());
+}
@@ -0,0 +1,145 @@
+use bsose_sync::{partition_by_year, collection_name, check_collection_templates, time_unit_seconds, check_unique_times, first_occurrences, parse_duration, select, times_outside, cf_times, CfTime};
+use bson::DateTime;
+use chrono::{Duration, TimeZone, Utc};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_fixture.nc");
+// its first timestep is 40000000 seconds before the origin
+const NEGATIVE_TIME: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_negative_time_fixture.nc");
+
+fn utc(s: &str) -> DateTime {
+    DateTime::parse_rfc3339_str(s).unwrap()
+}
+
+fn decode(units: &str, value: f64) -> DateTime {
+    DateTime::from_chrono(CfTime::parse(units, None).unwrap().decode(value))
+}
+
+#[test]
+fn time_variables_decode_per_their_units() {
+    let file = netcdf::open(FIXTURE).unwrap();
+    let times: Vec<DateTime> = cf_times(&file.variable("time").unwrap()).unwrap().into_iter().map(DateTime::from_chrono).collect();
+    assert_eq!(times, vec!(utc("2012-12-06T00:00:00Z"), utc("2012-12-11T00:00:00Z"), utc("2012-12-16T00:00:00Z")));
+
+    // offsets before the origin count back from it
+    let file = netcdf::open(NEGATIVE_TIME).unwrap();
+    let times = cf_times(&file.variable("time").unwrap()).unwrap();
+    assert_eq!(DateTime::from_chrono(times[0]), utc("2011-08-26T00:53:20Z"));
+}
+
+#[test]
+fn unknown_units_are_rejected() {
+    assert!(time_unit_seconds("fortnights since 2012-12-01").is_err());
+}
+
+#[test]
+fn matches_the_rfc3339_round_trip() {
+    // the previous construction went through an rfc3339 string; stored milliseconds must not change
+    let t0 = Utc.with_ymd_and_hms(2012, 12, 1, 0, 0, 0).unwrap();
+    let encoding = CfTime::parse("seconds since 2012-12-01", None).unwrap();
+    for seconds in [0i64, 1, 59, 432000, 86399, 31536000, 283824000, 1_000_000_007] {
+        let via_string = DateTime::parse_rfc3339_str((t0 + Duration::seconds(seconds)).to_rfc3339().replace("+00:00", "Z")).unwrap();
+        assert_eq!(DateTime::from_chrono(encoding.decode(seconds as f64)).timestamp_millis(), via_string.timestamp_millis());
+    }
+}
+
+#[test]
+fn duplicate_timestamps_are_rejected() {
+    // 5 days and 120 hours decode to the same instant
+    let timeseries = vec!(decode("days since 2012-12-01", 5.0), decode("days since 2012-12-01", 6.0), decode("hours since 2012-12-01", 120.0));
+    let err = check_unique_times(&timeseries).unwrap_err();
+    assert!(err.to_string().contains("time index 2"));
+    assert!(check_unique_times(&timeseries[..2]).is_ok());
+}
+
+#[test]
+fn repeated_stored_timestamps_collapse_to_the_first() {
+    // a metadoc timeseries written by an older run that repeated the second timestep
+    let t = |d: f64| decode("days since 2012-12-01", d);
+    let timeseries = vec!(t(5.0), t(10.0), t(10.0), t(15.0), t(10.0));
+    let keep = first_occurrences(&timeseries);
+    assert_eq!(keep, vec!(0, 1, 3));
+    assert_eq!(select(&timeseries, &keep), vec!(t(5.0), t(10.0), t(15.0)));
+    // the matching data positions go with them
+    assert_eq!(select(&[1.0, 2.0, 2.5, 3.0, 2.7], &keep), vec!(1.0, 2.0, 3.0));
+    assert!(check_unique_times(&select(&timeseries, &keep)).is_ok());
+}
+
+#[test]
+fn durations() {
+    assert_eq!(parse_duration("90s").unwrap(), std::time::Duration::from_secs(90));
+    assert_eq!(parse_duration("30m").unwrap(), std::time::Duration::from_secs(1800));
+    assert_eq!(parse_duration("1h30m").unwrap(), std::time::Duration::from_secs(5400));
+    assert_eq!(parse_duration("0s").unwrap(), std::time::Duration::ZERO);
+    for bad in ["", "2", "h", "2x", "1.5h", "-1h"] {
+        assert!(parse_duration(bad).is_err(), "{} should be rejected", bad);
+    }
+}
+
+#[test]
+fn negative_offsets_fall_before_the_range() {
+    let t = |h: f64| decode("hours since 2012-12-01", h);
+    let times = vec!(t(-9000.0), t(0.0), t(24.0));
+    assert_eq!(times_outside(&times, utc("2012-01-01T00:00:00Z"), utc("2030-01-01T00:00:00Z")), vec!(0));
+    assert_eq!(times_outside(&times, utc("2012-12-01T12:00:00Z"), utc("2012-12-01T23:00:00Z")), vec!(0, 1, 2));
+}
+
+#[test]
+fn cf_time_units() {
+    // units, a value in them, and the instant it decodes to
+    let table = [
+        ("seconds since 2012-12-01", 432000.0, "2012-12-06T00:00:00Z"),
+        ("seconds since 2012-12-01 00:00:00", -40000000.0, "2011-08-26T00:53:20Z"),
+        ("minutes since 2012-12-01 06:00", 90.0, "2012-12-01T07:30:00Z"),
+        ("hours since 2012-12-01T00:00:00Z", 36.5, "2012-12-02T12:30:00Z"),
+        ("hours since 2012-12-01 00:00:00 UTC", 1.0, "2012-12-01T01:00:00Z"),
+        ("hours since 2012-12-01 00:00:00 +00:00", 1.0, "2012-12-01T01:00:00Z"),
+        ("days since 2012-12-01 00:00:00.0", 2.5, "2012-12-03T12:00:00Z"),
+        ("days since 1950-01-01", 22980.0, "2012-12-01T00:00:00Z"),
+        ("day since 2012-12-01", 1.00001, "2012-12-02T00:00:01Z")
+    ];
+    for (units, value, expected) in table {
+        let encoding = CfTime::parse(units, None).unwrap();
+        assert_eq!(DateTime::from_chrono(encoding.decode(value)), utc(expected), "{}", units);
+    }
+}
+
+#[test]
+fn cf_time_calendars() {
+    for calendar in ["standard", "gregorian", "proleptic_gregorian", "Standard"] {
+        assert!(CfTime::parse("days since 2012-12-01", Some(calendar)).is_ok(), "{}", calendar);
+    }
+    for calendar in ["noleap", "365_day", "360_day", "all_leap", "julian", "none"] {
+        let e = CfTime::parse("days since 2012-12-01", Some(calendar)).unwrap_err().to_string();
+        assert!(e.contains(calendar), "{}", e);
+    }
+    // only the proleptic calendar is Gregorian all the way back
+    assert!(CfTime::parse("days since 1500-01-01", Some("standard")).is_err());
+    assert!(CfTime::parse("days since 1500-01-01", Some("proleptic_gregorian")).is_ok());
+}
+
+#[test]
+fn malformed_cf_time_units() {
+    for units in ["seconds", "fortnights since 2012-12-01", "days since Dec 1 2012", "days since 2012-12-01 noon", "hours since 2012-12-01 00:00:00 +05:00"] {
+        assert!(CfTime::parse(units, None).is_err(), "{}", units);
+    }
+}
+
+#[test]
+fn year_partitions_route_timesteps_and_collections_together() {
+    let times = [utc("2011-08-14T00:00:00Z"), utc("2011-12-31T23:59:59Z"), utc("2012-01-01T00:00:00Z"), utc("2014-06-01T00:00:00Z")];
+    let years: Vec<(i32, Vec<usize>)> = partition_by_year(&times).into_iter().collect();
+    assert_eq!(years, vec!((2011, vec!(0, 1)), (2012, vec!(2)), (2014, vec!(3))));
+    assert!(partition_by_year(&[]).is_empty());
+
+    assert_eq!(collection_name("bsose_{year}", Some(2012), "156"), "bsose_2012");
+    assert_eq!(collection_name("bsose_{iter}_{year}", Some(2012), "157"), "bsose_157_2012");
+    assert_eq!(collection_name("bsose_{iter}", None, "157"), "bsose_157");
+    assert_eq!(collection_name("bsose", Some(2012), "157"), "bsose");
+
+    assert!(check_collection_templates("bsose_{year}", "timeseriesMeta_{year}").is_ok());
+    assert!(check_collection_templates("bsose", "timeseriesMeta").is_ok());
+    // data documents index into their metadoc's timeseries, so only both or neither may be split
+    assert!(check_collection_templates("bsose_{year}", "timeseriesMeta").is_err());
+    assert!(check_collection_templates("bsose_{iter}", "timeseriesMeta_{year}").is_err());
+    assert!(check_collection_templates("bsose_{month}", "timeseriesMeta_{month}").is_err());
+}
@@ -0,0 +1,67 @@
+use bsose_sync::{Extractor, LatIdx, LonIdx, LevelIdx};
+use bsose_sync::varmap::VarMap;
+
+// bsose_fixture.nc with YC, XC, Z, rA and hFacC renamed, see tests/fixtures/bsose_renamed_fixture.cdl
+const RENAMED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_renamed_fixture.nc");
+const VARMAP: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/bsose_renamed_varmap.json");
+
+#[test]
+fn defaults_are_the_mitgcm_names() {
+    let names = VarMap::default();
+    assert_eq!(names.name("lat"), "YC");
+    assert_eq!(names.name("cell_vertical_fraction"), "hFacC");
+}
+
+#[test]
+fn remapped_fixture_matches_the_original() {
+    let file = netcdf::open(RENAMED).unwrap();
+    let names = VarMap::load(VARMAP).unwrap();
+    assert_eq!(names.name("lat"), "lat");
+    assert_eq!(names.name("ocean_depth"), "Depth");
+
+    let extractor = Extractor::with_var_map(&file, "TRAC02", &names).unwrap();
+    let profile = extractor.profile(Some(LevelIdx(1)), LatIdx(1), LonIdx(0)).unwrap();
+    assert_eq!(profile, vec!(111.0, 112.0, 113.0));
+    let doc = extractor.document(3, Some(LevelIdx(1)), LatIdx(1), LonIdx(0), 10, profile).unwrap();
+    assert_eq!(doc._id, "0.500_-60.000_-6.700");
+    assert_eq!(doc.cell_vertical_fraction, 0.75);
+    let meta = extractor.metadoc(3, LatIdx(0), LonIdx(1), bsose_sync::Sourcedoc{
+        source: vec!(String::from("BSOSE")),
+        iter: String::from("156"),
+        file: String::from("bsose_renamed_fixture.nc"),
+        attributes: Default::default()
+    }).unwrap();
+    assert_eq!(meta.cell_area, 1.0e8 + 1.0);
+}
+
+#[test]
+fn default_names_fail_on_the_renamed_fixture() {
+    let file = netcdf::open(RENAMED).unwrap();
+    let err = Extractor::new(&file, "TRAC02").err().unwrap();
+    assert!(err.to_string().contains("'YC'"));
+}
+
+#[test]
+fn missing_remapped_name_says_where_it_came_from() {
+    let file = netcdf::open(RENAMED).unwrap();
+    let names = VarMap::from_json(r#"{"lat": "lat", "lon": "lon", "depth": "depth", "cell_area": "rAz"}"#).unwrap();
+    let err = Extractor::with_var_map(&file, "TRAC02", &names).err().unwrap();
+    assert!(err.to_string().contains("'rAz' (mapped from cell_area by --var-map)"));
+}
+
+#[test]
+fn unknown_keys_are_rejected() {
+    assert!(VarMap::from_json(r#"{"latitude": "YG"}"#).is_err());
+    assert!(VarMap::from_json(r#"["YG"]"#).is_err());
+}
+
+#[test]
+fn command_line_overrides_must_agree_with_the_map() {
+    let mut names = VarMap::from_json(r#"{"depth": "Zl"}"#).unwrap();
+    assert!(names.set("depth", "Zl").is_ok());
+    assert!(names.set("depth", "Z").is_err());
+    let mut names = VarMap::default();
+    names.set("depth", "Zl").unwrap();
+    assert_eq!(names.name("depth"), "Zl");
+    assert_eq!(names.name("interface_z_size"), "drC");
+}